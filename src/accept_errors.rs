@@ -0,0 +1,51 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use log::Level;
+
+/// How an `Incoming` reports failed accepts, set with `Endpoint::set_accept_error_log`.
+///
+/// Errors are reported before the stream returns them, so that servers retrying on
+/// transient errors like `ConnectionAborted` needn't log them again. Defaults to
+/// logging at the warning level.
+#[derive(Clone)]
+pub enum AcceptErrorLog {
+    /// Log accept errors at the given level.
+    Level(Level),
+    /// Pass accept errors to a closure instead of logging them, e.g. to count them.
+    Hook(Arc<dyn Fn(&io::Error) + Send + Sync>),
+    /// Don't report accept errors.
+    Off,
+}
+
+impl AcceptErrorLog {
+    /// Report accept errors to `hook` instead of logging them.
+    pub fn hook<F: Fn(&io::Error) + Send + Sync + 'static>(hook: F) -> Self {
+        AcceptErrorLog::Hook(Arc::new(hook))
+    }
+
+    pub(crate) fn report(&self, err: &io::Error) {
+        match *self {
+            AcceptErrorLog::Level(level) => log!(level, "Failed to accept a connection: {:?}", err),
+            AcceptErrorLog::Hook(ref hook) => hook(err),
+            AcceptErrorLog::Off => {},
+        }
+    }
+}
+
+impl Default for AcceptErrorLog {
+    fn default() -> Self {
+        AcceptErrorLog::Level(Level::Warn)
+    }
+}
+
+impl fmt::Debug for AcceptErrorLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AcceptErrorLog::Level(level) => f.debug_tuple("Level").field(&level).finish(),
+            AcceptErrorLog::Hook(_) => f.write_str("Hook(..)"),
+            AcceptErrorLog::Off => f.write_str("Off"),
+        }
+    }
+}
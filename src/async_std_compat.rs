@@ -0,0 +1,266 @@
+//! `async-std` counterparts of `Endpoint` and `IpcConnection`.
+//!
+//! Enabled by the `async-std` feature. Connections implement the `futures-io`
+//! `AsyncRead`/`AsyncWrite` traits, so the `async_std::io` extension traits work on them.
+//! On Unix this is backed by `async-std`'s Unix domain sockets. `async-std` has no
+//! named pipe support, so on Windows pipes are opened in synchronous mode and driven
+//! from `async-std`'s blocking thread pool, which serializes reads and writes on a
+//! single connection: unlike the Tokio `IpcConnection`, a Windows `Connection` is not
+//! full duplex. Security attributes are shared with the Tokio transport.
+
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+#[cfg(windows)]
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use SecurityAttributes;
+
+#[cfg(unix)]
+use async_std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+#[cfg(windows)]
+use async_std::{fs::File, task::JoinHandle};
+
+/// Endpoint for IPC transport on the `async-std` runtime.
+pub struct Endpoint {
+    path: String,
+    security_attributes: SecurityAttributes,
+}
+
+impl Endpoint {
+    /// New IPC endpoint at the given path
+    pub fn new(path: String) -> Self {
+        Endpoint {
+            path,
+            security_attributes: SecurityAttributes::empty(),
+        }
+    }
+
+    /// Set security attributes for the connection
+    pub fn set_security_attributes(&mut self, security_attributes: SecurityAttributes) {
+        self.security_attributes = security_attributes;
+    }
+
+    /// Returns the path of the endpoint.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Start listening for incoming connections
+    #[cfg(unix)]
+    pub fn incoming(self) -> io::Result<Incoming> {
+        let listener = ::std::os::unix::net::UnixListener::bind(&self.path)?;
+        Ok(Incoming { listener: listener.into() })
+    }
+
+    /// Start listening for incoming connections
+    #[cfg(windows)]
+    pub fn incoming(mut self) -> io::Result<Incoming> {
+        let pipe = win::create_pipe(&self.path, true, &mut self.security_attributes)?;
+        Ok(Incoming { path: self.path, security_attributes: self.security_attributes, pipe: Some(Arc::new(pipe)), connecting: None })
+    }
+}
+
+/// Listener accepting incoming connections, created by `Endpoint::incoming`.
+pub struct Incoming {
+    #[cfg(unix)]
+    listener: UnixListener,
+    #[cfg(windows)]
+    path: String,
+    #[cfg(windows)]
+    security_attributes: SecurityAttributes,
+    // Instance waiting for a client, `None` until a failed creation is retried.
+    #[cfg(windows)]
+    pipe: Option<Arc<::std::fs::File>>,
+    // Blocking wait for a client to connect to `pipe`, kept across dropped `Accept`s.
+    #[cfg(windows)]
+    connecting: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Incoming {
+    /// Wait for the next incoming connection.
+    #[cfg(unix)]
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { inner: Box::pin(self.listener.accept()) }
+    }
+
+    /// Wait for the next incoming connection.
+    ///
+    /// Waiting for a client blocks a thread of `async-std`'s blocking pool and can't be
+    /// cancelled: dropping the future leaves the thread waiting until a client
+    /// connects, and the next `accept` returns that connection. If creating the pipe
+    /// instance for the next client fails, the next `accept` tries again.
+    #[cfg(windows)]
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { incoming: self }
+    }
+
+    #[cfg(windows)]
+    fn poll_accept(&mut self, cx: &mut Context) -> Poll<io::Result<Connection>> {
+        let pipe = match self.pipe {
+            Some(ref pipe) => pipe.clone(),
+            None => {
+                let pipe = Arc::new(win::create_pipe(&self.path, false, &mut self.security_attributes)?);
+                self.pipe = Some(pipe.clone());
+                pipe
+            },
+        };
+        let connecting = self.connecting.get_or_insert_with(|| async_std::task::spawn_blocking(move || win::connect_pipe(&pipe)));
+        let result = match Pin::new(connecting).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.connecting = None;
+
+        // A failed instance can't be connected again, the next accept creates a new one.
+        let pipe = self.pipe.take().expect("set above; qed");
+        result?;
+        let pipe = Arc::try_unwrap(pipe).map_err(|_| io::Error::other("pipe instance still in use"))?;
+        self.pipe = win::create_pipe(&self.path, false, &mut self.security_attributes)
+            .map(Arc::new)
+            .map_err(|e| warn!("Failed to create replacement pipe instance, retrying on the next accept: {:?}", e))
+            .ok();
+        Poll::Ready(Ok(Connection { inner: File::from(pipe) }))
+    }
+}
+
+#[cfg(unix)]
+type AcceptFuture<'a> = Pin<Box<dyn Future<Output = io::Result<(UnixStream, SocketAddr)>> + Send + 'a>>;
+
+/// Future returned by `Incoming::accept`.
+pub struct Accept<'a> {
+    #[cfg(unix)]
+    inner: AcceptFuture<'a>,
+    #[cfg(windows)]
+    incoming: &'a mut Incoming,
+}
+
+impl<'a> Future for Accept<'a> {
+    type Output = io::Result<Connection>;
+
+    #[cfg(unix)]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.inner.as_mut().poll(cx) {
+            Poll::Ready(Ok((stream, _))) => Poll::Ready(Ok(Connection { inner: stream })),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    #[cfg(windows)]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().incoming.poll_accept(cx)
+    }
+}
+
+/// IPC Connection on the `async-std` runtime.
+///
+/// Full duplex on Unix. On Windows a read and a write can't be in progress at the
+/// same time: both go through the synchronous pipe handle one at a time, so a pending
+/// read holds up writes until data arrives.
+pub struct Connection {
+    #[cfg(unix)]
+    inner: UnixStream,
+    #[cfg(windows)]
+    inner: File,
+}
+
+impl Connection {
+    /// Make new connection using the provided path.
+    #[cfg(unix)]
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Connection> {
+        let stream = ::std::os::unix::net::UnixStream::connect(path)?;
+        Ok(Connection { inner: stream.into() })
+    }
+
+    /// Make new connection using the provided path.
+    #[cfg(windows)]
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Connection> {
+        let file = ::win_pipe::open_client(path.as_ref(), ::PipeDirection::Duplex.client_access(), 0, ::PipeWait::default())?;
+        Ok(Connection { inner: File::from(file) })
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+    use winapi::um::namedpipeapi::ConnectNamedPipe;
+
+    use win_pipe::PipeOptions;
+    use SecurityAttributes;
+
+    // Create a pipe instance in synchronous (non-overlapped) mode.
+    pub fn create_pipe(path: &str, first: bool, security_attributes: &mut SecurityAttributes) -> io::Result<File> {
+        PipeOptions::default().create(path, first, false, security_attributes)
+    }
+
+    // Block until a client connects to the pipe instance.
+    pub fn connect_pipe(pipe: &File) -> io::Result<()> {
+        if unsafe { ConnectNamedPipe(pipe.as_raw_handle() as _, ptr::null_mut()) } == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::io::{ReadExt, WriteExt};
+    use async_std::task;
+
+    use super::{Connection, Endpoint};
+
+    #[test]
+    fn test_echo() {
+        let path = ::dummy_endpoint();
+        let mut incoming = Endpoint::new(path.clone()).incoming().expect("failed to open up a new pipe/socket");
+
+        let mut client = Connection::connect(&path).expect("failed to connect");
+        let mut server = task::block_on(incoming.accept()).expect("failed to accept connection");
+
+        let msg = b"hello";
+        task::block_on(client.write_all(msg)).expect("client write error");
+        task::block_on(client.flush()).expect("client flush error");
+
+        let mut buf = [0u8; 5];
+        task::block_on(server.read_exact(&mut buf)).expect("server read error");
+        task::block_on(server.write_all(&buf)).expect("server write error");
+        task::block_on(server.flush()).expect("server flush error");
+
+        let mut reply = [0u8; 5];
+        task::block_on(client.read_exact(&mut reply)).expect("client read error");
+        assert_eq!(&reply, msg);
+    }
+}
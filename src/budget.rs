@@ -0,0 +1,206 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures::{task::{self, Task}, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+struct State {
+    available: usize,
+    waiting: Vec<Task>,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Bytes that the framed connections of a server may buffer in total, shared by cloning.
+///
+/// Passed to `MessageConnection::with_budget`. Every byte read into a connection's
+/// buffer is charged until the message it belongs to is received, so thousands of
+/// connections with partial messages can't exhaust memory. Connections finding the
+/// budget spent stop reading until other connections free some, which also pauses
+/// their peers once the kernel buffers fill up. Messages longer than the budget fail
+/// with `InvalidData`; size it well above the largest message, or connections each
+/// holding part of one can stall one another.
+#[derive(Clone)]
+pub struct FrameMemoryBudget {
+    state: Arc<Mutex<State>>,
+    limit: usize,
+}
+
+impl FrameMemoryBudget {
+    /// Budget of `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        FrameMemoryBudget { state: Arc::new(Mutex::new(State { available: limit, waiting: Vec::new() })), limit }
+    }
+
+    /// Bytes not currently charged to any connection.
+    pub fn available(&self) -> usize {
+        lock(&self.state).available
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    // Take up to `wanted` bytes, registering the current task to be woken up once
+    // bytes are freed if none are left.
+    fn acquire(&self, wanted: usize) -> usize {
+        let mut state = lock(&self.state);
+        let granted = cmp::min(wanted, state.available);
+        if granted == 0 {
+            state.waiting.push(task::current());
+        }
+        state.available -= granted;
+        granted
+    }
+
+    fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let mut state = lock(&self.state);
+        state.available += bytes;
+        for task in state.waiting.drain(..) {
+            task.notify();
+        }
+    }
+}
+
+// Bytes a connection holds, returned to the budget when dropped.
+struct Charge {
+    budget: FrameMemoryBudget,
+    held: usize,
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.budget.release(self.held);
+    }
+}
+
+/// Stream charging the bytes read from it against a budget until `release`d.
+pub(crate) struct Charged<S> {
+    inner: S,
+    charge: Option<Charge>,
+}
+
+impl<S> Charged<S> {
+    pub(crate) fn new(inner: S, budget: Option<FrameMemoryBudget>) -> Self {
+        Charged { inner, charge: budget.map(|budget| Charge { budget, held: 0 }) }
+    }
+
+    /// Return `bytes` read earlier to the budget.
+    pub(crate) fn release(&mut self, bytes: usize) {
+        if let Some(ref mut charge) = self.charge {
+            let bytes = cmp::min(bytes, charge.held);
+            charge.held -= bytes;
+            charge.budget.release(bytes);
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Read> Read for Charged<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let charge = match self.charge {
+            Some(ref mut charge) => charge,
+            None => return self.inner.read(buf),
+        };
+        let granted = charge.budget.acquire(buf.len());
+        if granted == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let result = self.inner.read(&mut buf[..granted]);
+        let read = *result.as_ref().unwrap_or(&0);
+        charge.budget.release(granted - read);
+        charge.held += read;
+        result
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Charged<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<S: Write> Write for Charged<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Charged<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::{future::Either, Future};
+    use tokio::{self, io, timer::Delay};
+
+    use tests::connected_pair;
+    use MessageConnection;
+    use super::FrameMemoryBudget;
+
+    #[test]
+    fn test_reads_pause_until_memory_frees() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let budget = FrameMemoryBudget::new(20);
+        let (first_peer, first) = connected_pair(&mut runtime);
+        let (second_peer, second) = connected_pair(&mut runtime);
+
+        // The first connection buffers 12 bytes of a message announced as 12 bytes long.
+        let _first_peer = runtime.block_on(io::write_all(first_peer, [0, 0, 0, 12, 1, 2, 3, 4, 5, 6, 7, 8])).expect("failed to write").0;
+        let waited = MessageConnection::with_budget(first, budget.clone()).recv()
+            .select2(Delay::new(Instant::now() + Duration::from_millis(100)));
+        let first = match runtime.block_on(waited) {
+            Ok(Either::B((_, first))) => first,
+            _ => panic!("the message is incomplete"),
+        };
+        assert_eq!(budget.available(), 8);
+
+        // The second one reads 8 bytes of its 16 byte message, then waits for the budget.
+        let _second_peer = runtime.block_on(MessageConnection::new(second_peer).send(&[9; 12])).expect("failed to send");
+        let (tx, rx) = mpsc::channel();
+        runtime.spawn(MessageConnection::with_budget(second, budget.clone()).recv()
+            .map(move |(_, message)| tx.send(message).unwrap())
+            .map_err(|err| panic!("failed to receive: {:?}", err)));
+        thread::sleep(Duration::from_millis(100));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(budget.available(), 0);
+
+        // Dropping the first connection frees its bytes.
+        drop(first);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).expect("receive did not resume"), vec![9; 12]);
+        assert_eq!(budget.available(), 20);
+    }
+
+    #[test]
+    fn test_message_longer_than_budget() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (peer, connection) = connected_pair(&mut runtime);
+
+        let _peer = runtime.block_on(MessageConnection::new(peer).send(&[0; 100])).expect("failed to send");
+        match runtime.block_on(MessageConnection::with_budget(connection, FrameMemoryBudget::new(64)).recv()) {
+            Ok(_) => panic!("the message exceeds the budget"),
+            Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll as StdPoll, Wake, Waker};
+
+use futures::{stream::Stream, task::{self, Task}, Async, Poll};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+/// Stream of incoming connections ending once a token is cancelled.
+///
+/// Created by `Endpoint::incoming_with_token`. When the token is cancelled the stream
+/// yields `None` and drops the wrapped stream, closing the listener along with any
+/// accept that was pending. Connections accepted before stay open.
+pub struct Cancellable<S> {
+    inner: Option<S>,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl<S> Cancellable<S> {
+    pub(crate) fn new(inner: S, token: CancellationToken) -> Self {
+        Cancellable { inner: Some(inner), cancelled: Box::pin(token.cancelled_owned()) }
+    }
+
+    /// Reference to the wrapped stream, `None` once cancelled.
+    pub fn get_ref(&self) -> Option<&S> {
+        self.inner.as_ref()
+    }
+}
+
+// Wakes the futures 0.1 task polling the stream when the token is cancelled.
+struct TaskWaker(Task);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.notify();
+    }
+}
+
+impl<S: Stream<Error = io::Error>> Stream for Cancellable<S> {
+    type Item = S::Item;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, io::Error> {
+        let inner = match self.inner {
+            Some(ref mut inner) => inner,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let waker = Waker::from(Arc::new(TaskWaker(task::current())));
+        if let StdPoll::Ready(()) = self.cancelled.as_mut().poll(&mut Context::from_waker(&waker)) {
+            trace!("Accept loop cancelled");
+            self.inner = None;
+            return Ok(Async::Ready(None));
+        }
+        inner.poll()
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::Stream;
+    use tokio;
+    use tokio_util::sync::CancellationToken;
+
+    use Endpoint;
+
+    #[test]
+    fn test_cancel_pending_accept() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let token = CancellationToken::new();
+        let incoming = Endpoint::new(::dummy_endpoint())
+            .incoming_with_token(runtime.reactor(), token.clone())
+            .expect("failed to open up a new pipe/socket");
+
+        // Nobody connects, the accept is still pending when the token is cancelled.
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+        let started = Instant::now();
+        let connections = runtime.block_on(incoming.collect()).expect("accept loop failed");
+        assert!(connections.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(5), "accept loop took {:?} to stop", started.elapsed());
+        canceller.join().expect("canceller panicked");
+    }
+}
@@ -0,0 +1,99 @@
+use std::io;
+
+use futures::{Async, Future, Poll};
+
+use IpcConnection;
+
+/// Future resolving to the connection once the peer disconnected.
+///
+/// Created by `IpcConnection::closed`. Unread data is left untouched, so it can still
+/// be read after the future resolved.
+pub struct Closed {
+    connection: Option<IpcConnection>,
+    #[cfg(windows)]
+    interval: Option<::tokio::timer::Delay>,
+}
+
+impl Closed {
+    pub(crate) fn new(connection: IpcConnection) -> Self {
+        Closed {
+            connection: Some(connection),
+            #[cfg(windows)]
+            interval: None,
+        }
+    }
+}
+
+impl Future for Closed {
+    type Item = IpcConnection;
+    type Error = io::Error;
+
+    // Waits for the hang-up (`EPOLLRDHUP`/`EV_EOF`) event, ignoring plain readability.
+    #[cfg(unix)]
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        use mio::unix::UnixReady;
+
+        let connection = self.connection.as_mut().expect("poll a Closed after it's done");
+        try_ready!(connection.inner.poll_read_ready(UnixReady::hup().into()));
+        Ok(Async::Ready(self.connection.take().expect("checked above; qed")))
+    }
+
+    // Pipes get no hang-up event while unread data is buffered, so the pipe state is
+    // checked with `PeekNamedPipe` on every readiness change and periodically.
+    #[cfg(windows)]
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        use std::os::windows::io::AsRawHandle;
+        use std::ptr;
+        use std::time::{Duration, Instant};
+        use tokio::timer::Delay;
+        use winapi::shared::winerror::{ERROR_BROKEN_PIPE, ERROR_PIPE_NOT_CONNECTED};
+        use winapi::um::namedpipeapi::PeekNamedPipe;
+
+        const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+        loop {
+            {
+                let connection = self.connection.as_mut().expect("poll a Closed after it's done");
+                let peeked = unsafe {
+                    PeekNamedPipe(connection.inner.as_raw_handle() as _, ptr::null_mut(), 0,
+                        ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+                };
+                if peeked == 0 {
+                    let err = io::Error::last_os_error();
+                    match err.raw_os_error() {
+                        Some(code) if code == ERROR_BROKEN_PIPE as i32 || code == ERROR_PIPE_NOT_CONNECTED as i32 => break,
+                        _ => return Err(err),
+                    }
+                }
+                // Registers the task for the completion of the outstanding read.
+                let _ = connection.poll_read_ready()?;
+            }
+
+            let interval = self.interval.get_or_insert_with(|| Delay::new(Instant::now() + CHECK_INTERVAL));
+            try_ready!(interval.poll().map_err(io::Error::other));
+            interval.reset(Instant::now() + CHECK_INTERVAL);
+        }
+
+        Ok(Async::Ready(self.connection.take().expect("checked above; qed")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_closed_on_disconnect() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let (client, _) = runtime.block_on(io::write_all(client, b"bye")).expect("failed to write");
+        drop(client);
+
+        let server = runtime.block_on(server.closed()).expect("closed should resolve once the client is gone");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 3])).expect("unread data should be kept");
+        assert_eq!(&buf, b"bye");
+    }
+}
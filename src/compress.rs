@@ -0,0 +1,206 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use flate2::{self, read::ZlibDecoder, write::ZlibEncoder};
+use futures::{Async, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+// Largest frame accepted from the peer, compressed or not, as a guard against
+// decompression bombs. Writes are split so that frames never exceed it.
+const MAX_FRAME_LEN: usize = 1 << 20;
+// Largest chunk of plain data compressed into a single frame.
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+
+/// Compression algorithm of a `Compressed` connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// zlib (deflate) at the given level, 0 (none) to 9 (best).
+    Zlib(u32),
+}
+
+/// Connection wrapper compressing writes and decompressing reads.
+///
+/// Created by `IpcConnection::compressed`; both ends have to use it. Every write is
+/// compressed into its own length-prefixed frame, so this pays off for large, redundant
+/// payloads (logs, JSON) and mostly costs CPU and latency for small or binary ones.
+pub struct Compressed<S> {
+    inner: S,
+    compression: Compression,
+    // Compressed frames accepted from the caller but not yet written to `inner`.
+    write_buf: Vec<u8>,
+    // Raw bytes read from `inner`, not yet forming a complete frame.
+    read_buf: Vec<u8>,
+    // Decompressed data of the last frame and how much of it was read.
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+}
+
+impl<S> Compressed<S> {
+    /// Wrap `inner`, compressing with `compression`.
+    pub fn new(inner: S, compression: Compression) -> Self {
+        Compressed {
+            inner,
+            compression,
+            write_buf: Vec::new(),
+            read_buf: Vec::new(),
+            decoded: Vec::new(),
+            decoded_pos: 0,
+        }
+    }
+
+    /// Reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the connection, discarding buffered data.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn encode(&mut self, buf: &[u8]) -> io::Result<()> {
+        let Compression::Zlib(level) = self.compression;
+        // Reserve the length prefix, filled in once the frame is complete.
+        let start = self.write_buf.len();
+        self.write_buf.extend_from_slice(&[0; 4]);
+        let mut encoder = ZlibEncoder::new(&mut self.write_buf, flate2::Compression::new(level));
+        encoder.write_all(buf)?;
+        encoder.finish()?;
+
+        let len = self.write_buf.len() - start - 4;
+        self.write_buf[start..start + 4].copy_from_slice(&(len as u32).to_be_bytes());
+        Ok(())
+    }
+
+    fn decode_frame(&mut self) -> io::Result<bool> {
+        if self.read_buf.len() < 4 {
+            return Ok(false);
+        }
+        let len = u32::from_be_bytes([self.read_buf[0], self.read_buf[1], self.read_buf[2], self.read_buf[3]]) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "compressed frame too large"));
+        }
+        if self.read_buf.len() < 4 + len {
+            return Ok(false);
+        }
+
+        self.decoded.clear();
+        self.decoded_pos = 0;
+        ZlibDecoder::new(&self.read_buf[4..4 + len])
+            .take(MAX_FRAME_LEN as u64 + 1)
+            .read_to_end(&mut self.decoded)?;
+        if self.decoded.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decompressed frame too large"));
+        }
+        self.read_buf.drain(..4 + len);
+        Ok(true)
+    }
+}
+
+impl<S: Write> Compressed<S> {
+    fn flush_frames(&mut self) -> io::Result<()> {
+        while !self.write_buf.is_empty() {
+            let n = self.inner.write(&self.write_buf)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write compressed frame"));
+            }
+            self.write_buf.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read> Read for Compressed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.decoded_pos < self.decoded.len() {
+                let n = cmp::min(buf.len(), self.decoded.len() - self.decoded_pos);
+                buf[..n].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + n]);
+                self.decoded_pos += n;
+                return Ok(n);
+            }
+            if self.decode_frame()? {
+                continue;
+            }
+
+            let mut chunk = [0u8; 8192];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                if self.read_buf.is_empty() {
+                    return Ok(0);
+                }
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of a frame"));
+            }
+            self.read_buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+impl<S: Write> Write for Compressed<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only take new data once the previous frames are out, to bound the buffer.
+        self.flush_frames()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = cmp::min(buf.len(), MAX_CHUNK_LEN);
+        self.encode(&buf[..len])?;
+        match self.flush_frames() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+            result => result?,
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_frames()?;
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Compressed<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, _buf: &mut [u8]) -> bool {
+        false
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Compressed<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.flush_frames() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            result => result?,
+        }
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+    use super::{Compressed, Compression};
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let payload = "{\"level\":\"info\",\"message\":\"all good\"}\n".repeat(10_000).into_bytes();
+        let expected = payload.clone();
+
+        let client = client.compressed(Compression::Zlib(6));
+        // Count the bytes actually going over the connection.
+        let server = Compressed::new(server.with_quota(1 << 32, 0), Compression::Zlib(6));
+        runtime.spawn(io::write_all(client, payload)
+            .and_then(|(client, _)| io::flush(client))
+            .map(|_| ())
+            .map_err(|err| panic!("failed to write: {:?}", err)));
+
+        let (server, buf) = runtime.block_on(io::read_exact(server, vec![0u8; expected.len()])).expect("failed to read");
+        assert!(buf == expected, "decompressed payload differs");
+        assert!((server.get_ref().bytes_read() as usize) < expected.len() / 10, "payload was not compressed");
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use IpcConnection;
+
+/// Settings applied by an endpoint to every connection it accepts.
+///
+/// Set with `Endpoint::set_connection_config`, so that connection policy lives in one
+/// place instead of being repeated by every accept loop. Fields left at `None` keep
+/// the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionConfig {
+    /// Kernel receive buffer size, see `IpcConnection::set_recv_buffer_size`. Unix only,
+    /// pipe buffers are sized when creating the pipe.
+    pub recv_buffer_size: Option<usize>,
+    /// Kernel send buffer size, see `IpcConnection::set_send_buffer_size`. Unix only.
+    pub send_buffer_size: Option<usize>,
+    /// Most bytes read per poll, see `IpcConnection::max_read_chunk`.
+    pub max_read_chunk: Option<usize>,
+    /// Fail reads after this long without data, see `IpcConnection::idle_timeout`.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl ConnectionConfig {
+    /// Configure an accepted connection. Buffer sizes the system refuses are logged
+    /// rather than failing the accept.
+    pub(crate) fn apply(&self, mut connection: IpcConnection) -> IpcConnection {
+        #[cfg(unix)]
+        {
+            if let Some(size) = self.recv_buffer_size {
+                if let Err(e) = connection.set_recv_buffer_size(size) {
+                    warn!("Failed to set the receive buffer size of an accepted connection: {:?}", e);
+                }
+            }
+            if let Some(size) = self.send_buffer_size {
+                if let Err(e) = connection.set_send_buffer_size(size) {
+                    warn!("Failed to set the send buffer size of an accepted connection: {:?}", e);
+                }
+            }
+        }
+        if let Some(max) = self.max_read_chunk {
+            connection = connection.max_read_chunk(max);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            connection = connection.idle_timeout(timeout);
+        }
+        connection
+    }
+}
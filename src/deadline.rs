@@ -0,0 +1,81 @@
+use std::io;
+use std::time::Instant;
+
+use futures::{Async, Future, Poll};
+use tokio::io::AsyncRead;
+use tokio::timer::Delay;
+
+use IpcConnection;
+
+/// Future filling a buffer until it is full, the peer closes its end or a deadline passes.
+///
+/// Created by `IpcConnection::read_until_deadline`. Resolves to the connection, the
+/// buffer and the number of bytes read into it; reaching the deadline isn't an error.
+pub struct ReadUntilDeadline<B> {
+    state: Option<(IpcConnection, B)>,
+    read: usize,
+    deadline: Delay,
+}
+
+impl<B> ReadUntilDeadline<B> {
+    pub(crate) fn new(connection: IpcConnection, buf: B, deadline: Instant) -> Self {
+        ReadUntilDeadline { state: Some((connection, buf)), read: 0, deadline: Delay::new(deadline) }
+    }
+}
+
+impl<B: AsMut<[u8]>> Future for ReadUntilDeadline<B> {
+    type Item = (IpcConnection, B, usize);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        {
+            let (ref mut connection, ref mut buf) = *self.state.as_mut().expect("poll a ReadUntilDeadline after it's done");
+            let buf = buf.as_mut();
+            while self.read < buf.len() {
+                match connection.poll_read(&mut buf[self.read..])? {
+                    Async::Ready(0) => break,
+                    Async::Ready(n) => self.read += n,
+                    Async::NotReady => match self.deadline.poll().map_err(io::Error::other)? {
+                        Async::Ready(()) => break,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    },
+                }
+            }
+        }
+
+        let (connection, buf) = self.state.take().expect("checked above; qed");
+        Ok(Async::Ready((connection, buf, self.read)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_partial_read_at_deadline() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let client = runtime.block_on(io::write_all(client, b"abc")).expect("failed to write").0;
+        let started = Instant::now();
+        let (server, buf, n) = runtime.block_on(server.read_until_deadline(vec![0u8; 10], started + Duration::from_millis(200)))
+            .expect("failed to read");
+        assert_eq!(&buf[..n], b"abc");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // A full buffer or the end of file resolve right away.
+        let client = runtime.block_on(io::write_all(client, b"defg")).expect("failed to write").0;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (server, buf, n) = runtime.block_on(server.read_until_deadline([0u8; 4], deadline)).expect("failed to read");
+        assert_eq!(&buf[..n], b"defg");
+        drop(client);
+        let (_, _, n) = runtime.block_on(server.read_until_deadline([0u8; 4], deadline)).expect("failed to read");
+        assert_eq!(n, 0);
+        assert!(Instant::now() < deadline);
+    }
+}
@@ -0,0 +1,81 @@
+use std::cmp;
+use std::io;
+
+use futures::{Async, Future, Poll};
+use tokio::io::AsyncRead;
+
+use IpcConnection;
+
+/// Future reading and discarding the remaining bytes of a connection.
+///
+/// Created by `IpcConnection::drain`. Resolves to the connection and the number
+/// of bytes discarded once the peer closes its end or `limit` bytes were read.
+pub struct Drain {
+    connection: Option<IpcConnection>,
+    limit: u64,
+    discarded: u64,
+}
+
+impl Drain {
+    pub(crate) fn new(connection: IpcConnection, limit: u64) -> Self {
+        Drain { connection: Some(connection), limit, discarded: 0 }
+    }
+}
+
+impl Future for Drain {
+    type Item = (IpcConnection, u64);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        {
+            let connection = self.connection.as_mut().expect("poll a Drain after it's done");
+            let mut buf = [0u8; 4096];
+            while self.discarded < self.limit {
+                let len = cmp::min(buf.len() as u64, self.limit - self.discarded) as usize;
+                let n = try_ready!(connection.poll_read(&mut buf[..len]));
+                if n == 0 {
+                    break;
+                }
+                self.discarded += n as u64;
+            }
+        }
+
+        let connection = self.connection.take().expect("checked above; qed");
+        Ok(Async::Ready((connection, self.discarded)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_drain_trailing_bytes() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let (client, _) = runtime.block_on(io::write_all(client, b"hello, trailing garbage")).expect("failed to write");
+        drop(client);
+
+        let (server, buf) = runtime.block_on(io::read_exact(server, [0u8; 5])).expect("failed to read");
+        assert_eq!(&buf, b"hello");
+
+        let (server, discarded) = runtime.block_on(server.drain(u64::MAX)).expect("failed to drain");
+        assert_eq!(discarded, b", trailing garbage".len() as u64);
+
+        let (_, _, n) = runtime.block_on(io::read(server, [0u8; 1])).expect("connection should close cleanly");
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn test_drain_limit() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let _client = runtime.block_on(io::write_all(client, [0u8; 64])).expect("failed to write");
+        let (_, discarded) = runtime.block_on(server.drain(16)).expect("failed to drain");
+        assert_eq!(discarded, 16);
+    }
+}
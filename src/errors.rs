@@ -0,0 +1,149 @@
+//! Meaning of the OS error codes IPC transports run into.
+//!
+//! Lets downstream crates tell a busy pipe from a missing one without matching on
+//! Win32 or errno constants themselves.
+
+use std::io;
+
+/// Meaning of an IPC error, finer grained than `io::ErrorKind` where it matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKindExt {
+    /// All pipe instances are busy (Windows) or the listen backlog is full (Unix).
+    PipeBusy,
+    /// The peer closed its end of the connection.
+    BrokenPipe,
+    /// The pipe exists but is not connected to a peer.
+    NotConnected,
+    /// The endpoint's security does not allow the operation.
+    AccessDenied,
+    /// There is no endpoint at the path, or nobody is listening on it.
+    NotFound,
+    /// Waiting for the endpoint timed out.
+    TimedOut,
+    /// The endpoint path is already in use.
+    AddrInUse,
+    /// The host of a remote endpoint, or the network to it, can't be reached.
+    HostUnreachable,
+    /// Any other error.
+    Other,
+}
+
+/// Classify an I/O error returned by this crate.
+pub fn classify(err: &io::Error) -> ErrorKindExt {
+    match err.raw_os_error() {
+        Some(code) => classify_os_code(code),
+        None => match err.kind() {
+            io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof => ErrorKindExt::BrokenPipe,
+            io::ErrorKind::NotConnected => ErrorKindExt::NotConnected,
+            io::ErrorKind::PermissionDenied => ErrorKindExt::AccessDenied,
+            io::ErrorKind::NotFound => ErrorKindExt::NotFound,
+            io::ErrorKind::TimedOut => ErrorKindExt::TimedOut,
+            io::ErrorKind::AddrInUse => ErrorKindExt::AddrInUse,
+            io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable => ErrorKindExt::HostUnreachable,
+            _ => ErrorKindExt::Other,
+        },
+    }
+}
+
+/// Classify a raw OS error code (`errno` on Unix, `GetLastError` on Windows).
+#[cfg(unix)]
+pub fn classify_os_code(code: i32) -> ErrorKindExt {
+    use libc::*;
+
+    match code {
+        // A full backlog of a Unix socket fails non-blocking connects with `EAGAIN`.
+        EAGAIN => ErrorKindExt::PipeBusy,
+        EPIPE | ECONNRESET => ErrorKindExt::BrokenPipe,
+        ENOTCONN => ErrorKindExt::NotConnected,
+        EACCES | EPERM => ErrorKindExt::AccessDenied,
+        // A socket file left behind by a server that is gone refuses connections.
+        ENOENT | ECONNREFUSED => ErrorKindExt::NotFound,
+        ETIMEDOUT => ErrorKindExt::TimedOut,
+        EADDRINUSE => ErrorKindExt::AddrInUse,
+        EHOSTUNREACH | ENETUNREACH => ErrorKindExt::HostUnreachable,
+        _ => ErrorKindExt::Other,
+    }
+}
+
+/// Classify a raw OS error code (`errno` on Unix, `GetLastError` on Windows).
+#[cfg(windows)]
+pub fn classify_os_code(code: i32) -> ErrorKindExt {
+    use winapi::shared::winerror::*;
+
+    match code as u32 {
+        ERROR_PIPE_BUSY => ErrorKindExt::PipeBusy,
+        ERROR_BROKEN_PIPE | ERROR_NO_DATA => ErrorKindExt::BrokenPipe,
+        ERROR_PIPE_NOT_CONNECTED => ErrorKindExt::NotConnected,
+        ERROR_ACCESS_DENIED => ErrorKindExt::AccessDenied,
+        ERROR_FILE_NOT_FOUND | ERROR_PATH_NOT_FOUND => ErrorKindExt::NotFound,
+        ERROR_SEM_TIMEOUT => ErrorKindExt::TimedOut,
+        // Note creating the first instance of an existing pipe fails with `ERROR_ACCESS_DENIED`.
+        ERROR_ALREADY_EXISTS => ErrorKindExt::AddrInUse,
+        // `ERROR_BAD_NETPATH` is what SMB reports for a host that doesn't answer.
+        ERROR_BAD_NETPATH | ERROR_NETWORK_UNREACHABLE | ERROR_HOST_UNREACHABLE => ErrorKindExt::HostUnreachable,
+        _ => ErrorKindExt::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{classify, classify_os_code, ErrorKindExt};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_os_codes() {
+        use libc::*;
+
+        for &(code, kind) in &[
+            (EAGAIN, ErrorKindExt::PipeBusy),
+            (EPIPE, ErrorKindExt::BrokenPipe),
+            (ECONNRESET, ErrorKindExt::BrokenPipe),
+            (ENOTCONN, ErrorKindExt::NotConnected),
+            (EACCES, ErrorKindExt::AccessDenied),
+            (EPERM, ErrorKindExt::AccessDenied),
+            (ENOENT, ErrorKindExt::NotFound),
+            (ECONNREFUSED, ErrorKindExt::NotFound),
+            (ETIMEDOUT, ErrorKindExt::TimedOut),
+            (EADDRINUSE, ErrorKindExt::AddrInUse),
+            (EHOSTUNREACH, ErrorKindExt::HostUnreachable),
+            (EINVAL, ErrorKindExt::Other),
+        ] {
+            assert_eq!(classify_os_code(code), kind, "errno {}", code);
+            assert_eq!(classify(&io::Error::from_raw_os_error(code)), kind, "errno {}", code);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_classify_os_codes() {
+        use winapi::shared::winerror::*;
+
+        for &(code, kind) in &[
+            (ERROR_PIPE_BUSY, ErrorKindExt::PipeBusy),
+            (ERROR_BROKEN_PIPE, ErrorKindExt::BrokenPipe),
+            (ERROR_NO_DATA, ErrorKindExt::BrokenPipe),
+            (ERROR_PIPE_NOT_CONNECTED, ErrorKindExt::NotConnected),
+            (ERROR_ACCESS_DENIED, ErrorKindExt::AccessDenied),
+            (ERROR_FILE_NOT_FOUND, ErrorKindExt::NotFound),
+            (ERROR_PATH_NOT_FOUND, ErrorKindExt::NotFound),
+            (ERROR_SEM_TIMEOUT, ErrorKindExt::TimedOut),
+            (ERROR_ALREADY_EXISTS, ErrorKindExt::AddrInUse),
+            (ERROR_BAD_NETPATH, ErrorKindExt::HostUnreachable),
+            (ERROR_NETWORK_UNREACHABLE, ErrorKindExt::HostUnreachable),
+            (ERROR_INVALID_PARAMETER, ErrorKindExt::Other),
+        ] {
+            assert_eq!(classify_os_code(code as i32), kind, "error code {}", code);
+            assert_eq!(classify(&io::Error::from_raw_os_error(code as i32)), kind, "error code {}", code);
+        }
+    }
+
+    #[test]
+    fn test_classify_error_kinds() {
+        assert_eq!(classify(&io::Error::new(io::ErrorKind::BrokenPipe, "gone")), ErrorKindExt::BrokenPipe);
+        assert_eq!(classify(&io::Error::new(io::ErrorKind::TimedOut, "slow")), ErrorKindExt::TimedOut);
+        assert_eq!(classify(&io::Error::new(io::ErrorKind::HostUnreachable, "no route")), ErrorKindExt::HostUnreachable);
+        assert_eq!(classify(&io::Error::other("quota exceeded")), ErrorKindExt::Other);
+    }
+}
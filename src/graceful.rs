@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, BufMut};
+use futures::{stream::Stream, task::{self, Task}, Async, Future, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::timer::Delay;
+
+use {Incoming, IpcConnection, RemoteId};
+
+#[derive(Default)]
+struct State {
+    stopping: bool,
+    next_id: u64,
+    // Raw descriptors or handles of the connections still open, kept valid by removing
+    // them under the lock before the connection is closed.
+    active: HashMap<u64, usize>,
+    // Connections that finished on their own since the shutdown began.
+    drained: usize,
+    incoming_task: Option<Task>,
+    shutdown_task: Option<Task>,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Stream of incoming connections that can be shut down gracefully.
+///
+/// Created by `Endpoint::incoming_graceful`, along with the `ShutdownHandle` stopping
+/// it. Accepted connections are tracked until dropped.
+pub struct GracefulIncoming {
+    inner: Option<Incoming>,
+    state: Arc<Mutex<State>>,
+}
+
+impl GracefulIncoming {
+    pub(crate) fn new(inner: Incoming) -> (Self, ShutdownHandle) {
+        let state = Arc::new(Mutex::new(State::default()));
+        (GracefulIncoming { inner: Some(inner), state: state.clone() }, ShutdownHandle { state })
+    }
+}
+
+impl Stream for GracefulIncoming {
+    type Item = (TrackedConnection, RemoteId);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        {
+            let mut state = lock(&self.state);
+            if state.stopping {
+                // Closes the listener.
+                self.inner = None;
+                return Ok(Async::Ready(None));
+            }
+            state.incoming_task = Some(task::current());
+        }
+
+        let inner = match self.inner {
+            Some(ref mut inner) => inner,
+            None => return Ok(Async::Ready(None)),
+        };
+        let (connection, remote_id) = match try_ready!(inner.poll()) {
+            Some(accepted) => accepted,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let mut state = lock(&self.state);
+        let id = state.next_id;
+        state.next_id += 1;
+        state.active.insert(id, raw(&connection));
+        Ok(Async::Ready(Some((TrackedConnection { connection, id, state: self.state.clone() }, remote_id))))
+    }
+}
+
+#[cfg(unix)]
+fn raw(connection: &IpcConnection) -> usize {
+    use std::os::unix::io::AsRawFd;
+
+    connection.inner.as_raw_fd() as usize
+}
+
+#[cfg(windows)]
+fn raw(connection: &IpcConnection) -> usize {
+    use std::os::windows::io::AsRawHandle;
+
+    connection.inner.as_raw_handle() as usize
+}
+
+// Closing the socket for both directions wakes up the task owning the connection,
+// whose reads then see the end of file and whose writes fail.
+#[cfg(unix)]
+fn force_close(raw: usize) {
+    unsafe { ::libc::shutdown(raw as ::libc::c_int, ::libc::SHUT_RDWR) };
+}
+
+// Disconnecting the server end fails the pending and all later operations on it.
+#[cfg(windows)]
+fn force_close(raw: usize) {
+    unsafe { ::winapi::um::namedpipeapi::DisconnectNamedPipe(raw as _) };
+}
+
+/// Connection accepted by a `GracefulIncoming`, no longer tracked once dropped.
+pub struct TrackedConnection {
+    connection: IpcConnection,
+    id: u64,
+    state: Arc<Mutex<State>>,
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.active.remove(&self.id);
+        if state.stopping {
+            state.drained += 1;
+            if let Some(ref task) = state.shutdown_task {
+                task.notify();
+            }
+        }
+    }
+}
+
+impl Deref for TrackedConnection {
+    type Target = IpcConnection;
+
+    fn deref(&self) -> &IpcConnection {
+        &self.connection
+    }
+}
+
+impl DerefMut for TrackedConnection {
+    fn deref_mut(&mut self) -> &mut IpcConnection {
+        &mut self.connection
+    }
+}
+
+impl Read for TrackedConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.connection.read(buf)
+    }
+}
+
+impl Write for TrackedConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.connection.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush()
+    }
+}
+
+impl AsyncRead for TrackedConnection {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.connection.prepare_uninitialized_buffer(buf)
+    }
+
+    fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        AsyncRead::read_buf(&mut self.connection, buf)
+    }
+}
+
+impl AsyncWrite for TrackedConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        AsyncWrite::shutdown(&mut self.connection)
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        AsyncWrite::write_buf(&mut self.connection, buf)
+    }
+}
+
+/// Handle shutting down a `GracefulIncoming` and its connections.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    state: Arc<Mutex<State>>,
+}
+
+impl ShutdownHandle {
+    /// Stop accepting, wait up to `grace` for the open connections to be dropped, then
+    /// force-close the remaining ones.
+    ///
+    /// The incoming stream ends and closes the listener right away. Connections still
+    /// open after `grace` are shut down under their owners, whose reads see the end of
+    /// file and whose writes fail from then on.
+    pub fn graceful_shutdown(&self, grace: Duration) -> GracefulShutdown {
+        GracefulShutdown { state: self.state.clone(), grace, deadline: None }
+    }
+
+    /// Number of accepted connections still open.
+    pub fn active(&self) -> usize {
+        lock(&self.state).active.len()
+    }
+}
+
+/// Outcome of a graceful shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// Connections that finished within the grace period.
+    pub drained: usize,
+    /// Connections force-closed once the grace period was over.
+    pub force_closed: usize,
+}
+
+/// Future shutting down a `GracefulIncoming`, created by `ShutdownHandle::graceful_shutdown`.
+pub struct GracefulShutdown {
+    state: Arc<Mutex<State>>,
+    grace: Duration,
+    deadline: Option<Delay>,
+}
+
+impl Future for GracefulShutdown {
+    type Item = ShutdownSummary;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<ShutdownSummary, io::Error> {
+        if self.deadline.is_none() {
+            let mut state = lock(&self.state);
+            state.stopping = true;
+            if let Some(task) = state.incoming_task.take() {
+                task.notify();
+            }
+            self.deadline = Some(Delay::new(Instant::now() + self.grace));
+        }
+
+        {
+            let mut state = lock(&self.state);
+            if state.active.is_empty() {
+                return Ok(Async::Ready(ShutdownSummary { drained: state.drained, force_closed: 0 }));
+            }
+            state.shutdown_task = Some(task::current());
+        }
+
+        let deadline = self.deadline.as_mut().expect("set above; qed");
+        try_ready!(deadline.poll().map_err(io::Error::other));
+        let state = lock(&self.state);
+        for &raw in state.active.values() {
+            force_close(raw);
+        }
+        trace!("Force-closed {} connections after the grace period", state.active.len());
+        Ok(Async::Ready(ShutdownSummary { drained: state.drained, force_closed: state.active.len() }))
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::{sync::{mpsc, oneshot}, Future, Stream};
+    use tokio::{self, io};
+
+    use {Endpoint, IpcConnection};
+    use super::ShutdownSummary;
+
+    #[test]
+    fn test_hung_connection_is_force_closed() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = ::dummy_endpoint();
+        let (incoming, shutdown) = Endpoint::new(path.clone()).incoming_graceful(&handle)
+            .expect("failed to open up a new pipe/socket");
+
+        // Every connection is served until its client sends a byte or it is closed.
+        let (served_tx, served_rx) = mpsc::unbounded();
+        let (stopped_tx, stopped_rx) = oneshot::channel();
+        runtime.spawn(incoming
+            .for_each(move |(connection, _)| {
+                let served_tx = served_tx.clone();
+                tokio::spawn(io::read_exact(connection, [0u8; 1]).then(move |result| {
+                    let _ = served_tx.unbounded_send(result.is_ok());
+                    Ok(())
+                }));
+                Ok(())
+            })
+            .map_err(|err| panic!("server failed: {:?}", err))
+            .map(|()| { let _ = stopped_tx.send(()); }));
+
+        let quick = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let _hung = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        while shutdown.active() < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let started = Instant::now();
+        let summary = oneshot::spawn(shutdown.graceful_shutdown(Duration::from_millis(300)), &runtime.executor());
+        // Accepting stops first, then the quick connection finishes within the grace period.
+        runtime.block_on(stopped_rx).expect("accept loop did not stop");
+        let _quick = runtime.block_on(io::write_all(quick, b"x")).expect("failed to write");
+        let summary = runtime.block_on(summary).expect("shutdown failed");
+        assert_eq!(summary, ShutdownSummary { drained: 1, force_closed: 1 });
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // The quick connection read its byte, the hung one saw the end of file.
+        let served = runtime.block_on(served_rx.take(2).collect()).expect("server tasks failed");
+        assert_eq!(served, vec![true, false]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::io;
+
+use futures::{stream::Stream, Async, Poll};
+use tokio::reactor::Handle;
+
+use {Endpoint, Incoming, IpcConnection};
+
+/// Several endpoints serving the same service, e.g. a compatibility path next to a new one.
+///
+/// # Examples
+///
+/// ```
+/// use susy_tokio_ipc::{Endpoint, EndpointGroup, dummy_endpoint};
+///
+/// let group = EndpointGroup::new()
+///     .endpoint(Endpoint::new(dummy_endpoint()))
+///     .endpoint(Endpoint::new(dummy_endpoint()));
+/// ```
+#[derive(Default)]
+pub struct EndpointGroup {
+    endpoints: Vec<Endpoint>,
+}
+
+impl EndpointGroup {
+    /// New empty group.
+    pub fn new() -> Self {
+        EndpointGroup::default()
+    }
+
+    /// Add an endpoint to the group.
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// Merged stream of incoming connections on all endpoints of the group.
+    ///
+    /// Fails if any of the endpoints can't be bound.
+    pub fn incoming(self, handle: &Handle) -> io::Result<GroupIncoming> {
+        let mut listeners = Vec::with_capacity(self.endpoints.len());
+        for endpoint in self.endpoints {
+            let path = endpoint.path().to_owned();
+            listeners.push((path, endpoint.incoming(handle)?));
+        }
+        Ok(GroupIncoming { listeners, next: 0 })
+    }
+}
+
+/// Stream of incoming connections of an `EndpointGroup`, tagged with the path they came in on.
+pub struct GroupIncoming {
+    listeners: Vec<(String, Incoming)>,
+    // Listener polled first, rotated so that a busy endpoint can't starve the others.
+    next: usize,
+}
+
+impl Stream for GroupIncoming {
+    type Item = (IpcConnection, String);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        let count = self.listeners.len();
+        for i in 0..count {
+            let index = (self.next + i) % count;
+            let (ref path, ref mut incoming) = self.listeners[index];
+            if let Async::Ready(Some((connection, _))) = incoming.poll()? {
+                self.next = (index + 1) % count;
+                return Ok(Async::Ready(Some((connection, path.clone()))));
+            }
+        }
+
+        if count == 0 {
+            return Ok(Async::Ready(None));
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use futures::Stream;
+    use tokio;
+
+    use {Endpoint, IpcConnection};
+    use super::EndpointGroup;
+
+    #[test]
+    fn test_group_tags_connections() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let first = ::dummy_endpoint();
+        let second = ::dummy_endpoint();
+
+        let incoming = EndpointGroup::new()
+            .endpoint(Endpoint::new(first.clone()))
+            .endpoint(Endpoint::new(second.clone()))
+            .incoming(&handle)
+            .expect("failed to bind endpoint group");
+
+        let _second_client = IpcConnection::connect(&second, &handle).expect("failed to connect");
+        let (accepted, incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert_eq!(accepted.expect("no connection received").1, second);
+
+        let _first_client = IpcConnection::connect(&first, &handle).expect("failed to connect");
+        let (accepted, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert_eq!(accepted.expect("no connection received").1, first);
+    }
+}
@@ -0,0 +1,70 @@
+use std::io;
+use std::sync::Arc;
+
+use futures::{stream::Stream, Async, Future, Poll};
+use tokio;
+
+use Incoming;
+
+/// Future answering every connection with a canned response, e.g. for liveness probes.
+///
+/// Created by `Endpoint::serve_health`. Each accepted connection is sent the response
+/// from a task of its own and closed; clients failing to read it are ignored. The future
+/// runs until accepting fails, so spawn it on a runtime.
+pub struct ServeHealth {
+    incoming: Incoming,
+    response: Arc<[u8]>,
+}
+
+impl ServeHealth {
+    pub(crate) fn new(incoming: Incoming, response: &[u8]) -> Self {
+        ServeHealth { incoming, response: response.into() }
+    }
+}
+
+impl Future for ServeHealth {
+    type Item = ();
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(), io::Error> {
+        loop {
+            let (connection, _) = match try_ready!(self.incoming.poll()) {
+                Some(accepted) => accepted,
+                None => return Ok(Async::Ready(())),
+            };
+            let response = self.response.clone();
+            tokio::spawn(tokio::io::write_all(connection, response).then(|result| {
+                if let Err(e) = result {
+                    trace!("Failed to send the health response: {:?}", e);
+                }
+                Ok(())
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use futures::Future;
+    use tokio::{self, io};
+
+    use {Endpoint, IpcConnection};
+
+    #[test]
+    fn test_canned_response() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = ::dummy_endpoint();
+        let server = Endpoint::new(path.clone()).serve_health(&handle, b"ok\n")
+            .expect("failed to open up a new pipe/socket");
+        runtime.spawn(server.map_err(|err| panic!("health server failed: {:?}", err)));
+
+        // Every probe gets the response, then the end of the stream.
+        for _ in 0..2 {
+            let probe = IpcConnection::connect(&path, &handle).expect("failed to connect");
+            let (_, response) = runtime.block_on(io::read_to_end(probe, Vec::new())).expect("failed to read");
+            assert_eq!(response, b"ok\n");
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use tokio::codec::{Framed, LengthDelimitedCodec};
+use tokio::timer::Delay;
+
+use IpcConnection;
+
+const DATA: u8 = 0;
+const PING: u8 = 1;
+const PONG: u8 = 2;
+
+/// Framed connection exchanging periodic pings to detect a hung peer.
+///
+/// Data frames are sent through the `Sink` and received through the `Stream`. Pings are
+/// sent every `interval` and answered by the peer's `Heartbeat`; once `max_missed` pings
+/// in a row went unanswered the stream fails with a `TimedOut` "peer unresponsive" error.
+/// This catches peers that hang without closing the connection, which `closed` can't.
+/// The heartbeat runs while the stream is polled, so keep reading from it.
+pub struct Heartbeat {
+    framed: Framed<IpcConnection, LengthDelimitedCodec>,
+    interval: Duration,
+    max_missed: u32,
+    missed: u32,
+    awaiting_pong: bool,
+    timer: Option<Delay>,
+    control: VecDeque<Bytes>,
+}
+
+impl Heartbeat {
+    /// Wrap a connection whose peer also uses a `Heartbeat`.
+    pub fn new(connection: IpcConnection, interval: Duration, max_missed: u32) -> Self {
+        Heartbeat {
+            framed: Framed::new(connection, LengthDelimitedCodec::new()),
+            interval,
+            max_missed,
+            missed: 0,
+            awaiting_pong: false,
+            timer: None,
+            control: VecDeque::new(),
+        }
+    }
+
+    /// Unwrap the connection, discarding any buffered data.
+    pub fn into_inner(self) -> IpcConnection {
+        self.framed.into_inner()
+    }
+
+    fn poll_timer(&mut self) -> io::Result<()> {
+        let interval = self.interval;
+        loop {
+            let timer = self.timer.get_or_insert_with(|| Delay::new(Instant::now() + interval));
+            if let Async::NotReady = timer.poll().map_err(io::Error::other)? {
+                return Ok(());
+            }
+            timer.reset(Instant::now() + interval);
+
+            if self.awaiting_pong {
+                self.missed += 1;
+                if self.missed >= self.max_missed {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "peer unresponsive"));
+                }
+            }
+            self.awaiting_pong = true;
+            self.control.push_back(Bytes::from_static(&[PING]));
+        }
+    }
+
+    fn poll_control(&mut self) -> Poll<(), io::Error> {
+        while let Some(frame) = self.control.pop_front() {
+            if let AsyncSink::NotReady(frame) = self.framed.start_send(frame)? {
+                self.control.push_front(frame);
+                return Ok(Async::NotReady);
+            }
+        }
+        self.framed.poll_complete()
+    }
+}
+
+impl Stream for Heartbeat {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, io::Error> {
+        self.poll_timer()?;
+        self.poll_control()?;
+
+        loop {
+            let frame = match try_ready!(self.framed.poll()) {
+                Some(frame) => frame.freeze(),
+                None => return Ok(Async::Ready(None)),
+            };
+            match frame.first() {
+                Some(&DATA) => return Ok(Async::Ready(Some(frame.slice_from(1)))),
+                Some(&PING) => {
+                    self.control.push_back(Bytes::from_static(&[PONG]));
+                    self.poll_control()?;
+                },
+                Some(&PONG) => {
+                    self.awaiting_pong = false;
+                    self.missed = 0;
+                },
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid heartbeat frame")),
+            }
+        }
+    }
+}
+
+impl Sink for Heartbeat {
+    type SinkItem = Bytes;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, data: Bytes) -> StartSend<Bytes, io::Error> {
+        let mut frame = BytesMut::with_capacity(data.len() + 1);
+        frame.put_u8(DATA);
+        frame.put_slice(&data);
+        match self.framed.start_send(frame.freeze())? {
+            AsyncSink::Ready => Ok(AsyncSink::Ready),
+            AsyncSink::NotReady(_) => Ok(AsyncSink::NotReady(data)),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        try_ready!(self.poll_control());
+        self.framed.poll_complete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::{Future, Sink, Stream};
+    use tokio;
+
+    use tests::connected_pair;
+    use super::Heartbeat;
+
+    #[test]
+    fn test_data_with_heartbeat() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let server = Heartbeat::new(server, Duration::from_millis(10), 3);
+        let (sink, stream) = server.split();
+        runtime.spawn(sink.send_all(stream).map(|_| ()).map_err(|err| panic!("echo server failed: {:?}", err)));
+
+        let client = Heartbeat::new(client, Duration::from_millis(10), 3);
+        let client = runtime.block_on(client.send(Bytes::from("hello"))).expect("failed to send");
+        let (reply, _client) = runtime.block_on(client.into_future()).map_err(|(err, _)| err).expect("failed to receive");
+        assert_eq!(reply, Some(Bytes::from("hello")));
+    }
+
+    #[test]
+    fn test_unresponsive_peer() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        // The server end is kept open but never answers pings.
+        let (client, _server) = connected_pair(&mut runtime);
+
+        let client = Heartbeat::new(client, Duration::from_millis(10), 3);
+        let err = match runtime.block_on(client.into_future()) {
+            Ok(_) => panic!("heartbeat should fail without pongs"),
+            Err((err, _)) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+}
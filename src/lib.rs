@@ -3,7 +3,7 @@
 
 #![warn(missing_docs)]
 
-extern crate futures;
+#[macro_use] extern crate futures;
 extern crate tokio_uds;
 extern crate tokio_named_pipes;
 extern crate tokio;
@@ -11,6 +11,25 @@ extern crate tokio;
 extern crate bytes;
 #[allow(unused_imports)] #[macro_use] extern crate log;
 
+#[cfg(unix)]
+extern crate libc;
+#[cfg(unix)]
+extern crate mio;
+
+#[cfg(feature = "async-std")]
+extern crate async_std;
+#[cfg(feature = "async-std")]
+extern crate futures_io;
+
+#[cfg(feature = "compress")]
+extern crate flate2;
+/// Noise protocol implementation, re-exported to configure `IpcConnection::secure`.
+#[cfg(feature = "noise")]
+pub extern crate snow;
+/// Cancellation tokens, re-exported to configure `Endpoint::incoming_with_token`.
+#[cfg(feature = "cancellation")]
+pub extern crate tokio_util;
+
 #[cfg(windows)]
 extern crate miow;
 #[cfg(windows)]
@@ -18,12 +37,19 @@ extern crate mio_named_pipes;
 #[cfg(windows)]
 extern crate winapi;
 
+use std::fs::File;
 use std::io::{self, Read, Write};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
 
 use futures::{stream::Stream, Async, Poll};
+use tokio::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::reactor::Handle;
+use tokio::timer::Delay;
 use bytes::{BufMut, Buf};
 
 #[cfg(windows)]
@@ -31,14 +57,127 @@ use tokio_named_pipes::NamedPipe;
 
 #[cfg(windows)]
 mod win_permissions;
+#[cfg(windows)]
+mod win_pipe;
+#[cfg(any(windows, test))]
+mod pipe_name;
+
+#[cfg(windows)]
+pub use win_pipe::{ClientOptions, InstanceStrategy, PipeDirection, PipeInfo, PipeMode, PipeWait, MAX_MESSAGE_LEN};
+#[cfg(windows)]
+use win_pipe::PipeOptions;
 
 #[cfg(windows)]
-pub use win_permissions::SecurityAttributes;
+pub use win_permissions::{SecurityAttributes, SecurityAttributesBuilder};
 
 #[cfg(unix)]
 mod unix_permissions;
 #[cfg(unix)]
-pub use unix_permissions::SecurityAttributes;
+pub use unix_permissions::{SecurityAttributes, SecurityAttributesBuilder};
+
+mod send_file;
+pub use send_file::SendFile;
+
+mod write_frame;
+pub use write_frame::WriteFrame;
+
+mod retry;
+pub use retry::RetryConfig;
+
+mod config;
+pub use config::ConnectionConfig;
+
+mod accept_errors;
+pub use accept_errors::AcceptErrorLog;
+
+mod readiness;
+pub use readiness::{Readable, Writable};
+
+mod quota;
+pub use quota::Quota;
+
+mod prepend;
+pub use prepend::Prepended;
+
+mod drain;
+pub use drain::Drain;
+
+mod deadline;
+pub use deadline::ReadUntilDeadline;
+
+mod shutdown;
+pub use shutdown::ShutdownBoth;
+
+mod closed;
+pub use closed::Closed;
+
+mod graceful;
+pub use graceful::{GracefulIncoming, GracefulShutdown, ShutdownHandle, ShutdownSummary, TrackedConnection};
+
+mod peer;
+pub use peer::PeerInfo;
+
+mod split;
+pub use split::{OwnedReadHalf, OwnedWriteHalf, ReuniteError};
+
+pub mod errors;
+
+pub mod testing;
+
+mod stream;
+pub use stream::IpcStream;
+
+mod group;
+pub use group::{EndpointGroup, GroupIncoming};
+
+mod reqrep;
+pub use reqrep::{Call, ReqRep};
+
+mod pool;
+pub use pool::{ConnectionPool, PooledConnection};
+
+mod heartbeat;
+pub use heartbeat::Heartbeat;
+
+mod budget;
+pub use budget::FrameMemoryBudget;
+
+mod message;
+pub use message::{MessageConnection, RecvMessage, SendMessage};
+
+mod health;
+pub use health::ServeHealth;
+
+mod proxy;
+pub use proxy::{copy_bidirectional, CopyBidirectional};
+
+mod rate_limit;
+pub use rate_limit::{RateLimited, ReadRateLimited};
+
+#[cfg(unix)]
+mod sockopt;
+#[cfg(unix)]
+mod umask;
+#[cfg(target_os = "linux")]
+mod selinux;
+
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "compress")]
+pub use compress::{Compressed, Compression};
+
+#[cfg(feature = "noise")]
+mod secure;
+#[cfg(feature = "noise")]
+pub use secure::{Handshake, SecureTransport};
+
+#[cfg(feature = "cancellation")]
+mod cancel;
+#[cfg(feature = "cancellation")]
+pub use cancel::Cancellable;
+
+#[cfg(feature = "async-std")]
+pub mod async_std_compat;
 
 #[cfg(windows)]
 const PIPE_AVAILABILITY_TIMEOUT: u64 = 5000;
@@ -84,43 +223,208 @@ pub fn dummy_endpoint() -> String {
 /// ```
 pub struct Endpoint {
     path: String,
-    security_attributes: SecurityAttributes,
+    // Shared with the pipe instances created by the streams of incoming connections.
+    security_attributes: Arc<Mutex<SecurityAttributes>>,
+    #[cfg(windows)]
+    pipe_options: PipeOptions,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    #[cfg(unix)]
+    owner: Option<(u32, u32)>,
+    #[cfg(unix)]
+    create_parent_dirs: bool,
+    #[cfg(unix)]
+    keep_socket_file: bool,
+    #[cfg(target_os = "linux")]
+    selinux_context: Option<String>,
+    connection_config: ConnectionConfig,
+    accept_error_log: AcceptErrorLog,
+    on_ready: Mutex<Option<ReadyCallback>>,
 }
 
+type ReadyCallback = Box<dyn FnOnce() + Send>;
+
 impl Endpoint {
     /// Stream of incoming connections
     #[cfg(not(windows))]
     pub fn incoming(self, handle: &Handle) -> io::Result<Incoming> {
+        let inner = self.inner(handle)?;
+        self.notify_ready();
         Ok(
-            Incoming { inner: self.inner(handle)?.incoming() }
+            Incoming { inner, config: self.connection_config, accept_error_log: self.accept_error_log }
           )
     }
 
     /// Stream of incoming connections
+    ///
+    /// Every pipe instance, including the ones created to replace accepted instances, is
+    /// associated with the completion port of `handle`'s reactor. Latency-sensitive
+    /// servers can keep their pipe I/O away from other work by passing the handle of a
+    /// dedicated `tokio::reactor::Reactor`, driven by a current-thread executor on a
+    /// thread of its own.
+    #[cfg(windows)]
+    pub fn incoming(self, handle: &Handle) -> io::Result<Incoming> {
+        self.rebind(handle)
+    }
+
+    /// Stream of incoming connections, keeping the endpoint to listen again later.
+    ///
+    /// Lets servers stop and restart listening with the same configuration. The
+    /// previous stream has to be dropped first: binding fails with `AddrInUse` while it
+    /// still accepts connections. A socket file left behind by a dropped listener is
+    /// replaced, once a connection attempt showed nothing listens on it anymore;
+    /// `incoming` leaves that to the caller.
+    #[cfg(not(windows))]
+    pub fn rebind(&self, handle: &Handle) -> io::Result<Incoming> {
+        let inner = self.bind(handle)?;
+        self.notify_ready();
+        Ok(Incoming { inner, config: self.connection_config.clone(), accept_error_log: self.accept_error_log.clone() })
+    }
+
+    /// Stream of incoming connections, keeping the endpoint to listen again later.
+    ///
+    /// Lets servers stop and restart listening with the same configuration. The
+    /// previous stream has to be dropped first, along with the connections it accepted:
+    /// creating the first pipe instance fails while any instance of the pipe remains.
     #[cfg(windows)]
-    pub fn incoming(mut self, handle: &Handle) -> io::Result<Incoming> {
+    pub fn rebind(&self, handle: &Handle) -> io::Result<Incoming> {
         let pipe = self.inner(handle)?;
-        Ok(
-            Incoming { inner: NamedPipeSupport { path: self.path, handle: handle.clone(),
-                pipe: pipe, security_attributes: self.security_attributes} }
-          )
+        let mut inner = NamedPipeSupport { path: self.path.clone(), handle: handle.clone(),
+            pipes: vec![pipe], security_attributes: self.security_attributes.clone(), pipe_options: self.pipe_options.clone(),
+            missing: 0, failures: 0, retry_delay: None };
+        if let InstanceStrategy::Eager(instances) = inner.pipe_options.instances {
+            for _ in 1..instances {
+                let pipe = inner.replacement_pipe()?;
+                inner.pipes.push(pipe);
+            }
+        }
+        self.notify_ready();
+        Ok(Incoming { inner, config: self.connection_config.clone(), accept_error_log: self.accept_error_log.clone() })
+    }
+
+    fn notify_ready(&self) {
+        let on_ready = self.on_ready.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+        if let Some(on_ready) = on_ready {
+            on_ready();
+        }
+    }
+
+    /// Make sure the directory of the socket file exists, creating it if configured to.
+    #[cfg(not(windows))]
+    fn check_parent_dir(&self) -> io::Result<()> {
+        let dir = match Path::new(&self.path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => return Ok(()),
+        };
+        if dir.is_dir() {
+            return Ok(());
+        }
+        if self.create_parent_dirs {
+            trace!("Creating socket directory {}", dir.display());
+            return ::std::fs::create_dir_all(dir).map_err(|err| {
+                io::Error::new(err.kind(), format!("failed to create socket directory {}: {}", dir.display(), err))
+            });
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound,
+            format!("socket directory {} of {} does not exist", dir.display(), self.path)))
+    }
+
+    /// Bind the listener, replacing a stale socket file once.
+    #[cfg(not(windows))]
+    fn bind(&self, handle: &Handle) -> io::Result<tokio_uds::UnixListener> {
+        match self.inner(handle) {
+            Err(ref e) if e.kind() == io::ErrorKind::AddrInUse && self.remove_stale_socket() => self.inner(handle),
+            result => result,
+        }
+    }
+
+    /// Remove the socket file at the endpoint path if nothing listens on it, returning
+    /// whether it was removed. Failures are logged, so that binding reports `AddrInUse`.
+    #[cfg(not(windows))]
+    fn remove_stale_socket(&self) -> bool {
+        use std::os::unix::{fs::{FileTypeExt, MetadataExt}, net};
+
+        if self.keep_socket_file {
+            return false;
+        }
+        let stale = match ::std::fs::symlink_metadata(&self.path) {
+            Ok(metadata) if metadata.file_type().is_socket() => metadata,
+            _ => return false,
+        };
+        match net::UnixStream::connect(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {},
+            _ => return false,
+        }
+        // Another process may have replaced the stale file with a socket of its own
+        // since the probe; leave that one alone.
+        match ::std::fs::symlink_metadata(&self.path) {
+            Ok(ref metadata) if metadata.dev() == stale.dev() && metadata.ino() == stale.ino() => {},
+            _ => return false,
+        }
+        match ::std::fs::remove_file(&self.path) {
+            Ok(()) => {
+                trace!("Removed stale socket file {}", self.path);
+                true
+            },
+            Err(e) => {
+                warn!("Failed to remove stale socket file {}: {:?}", self.path, e);
+                false
+            },
+        }
+    }
+
+    /// Stream of incoming connections accepted at most `max_per_sec` a second, allowing
+    /// bursts of up to `burst` connections.
+    ///
+    /// Excess connections are delayed rather than dropped, smoothing the load on
+    /// expensive per-connection handlers during connection storms. Panics if
+    /// `max_per_sec` is zero.
+    pub fn incoming_rate_limited(self, handle: &Handle, max_per_sec: u32, burst: u32) -> io::Result<RateLimited<Incoming>> {
+        Ok(RateLimited::new(self.incoming(handle)?, max_per_sec, burst))
+    }
+
+    /// Stream of incoming connections tagged with the address they were accepted on,
+    /// like the connections of an `EndpointGroup`.
+    pub fn incoming_with_addr(self, handle: &Handle) -> io::Result<IncomingWithAddr> {
+        let inner = self.incoming(handle)?;
+        let addr = inner.local_addr()?;
+        Ok(IncomingWithAddr { inner, addr })
+    }
+
+    /// Stream of incoming connections along with a handle shutting it down gracefully.
+    ///
+    /// `ShutdownHandle::graceful_shutdown` stops accepting, waits up to a grace period
+    /// for the accepted connections to finish, then force-closes the remaining ones.
+    pub fn incoming_graceful(self, handle: &Handle) -> io::Result<(GracefulIncoming, ShutdownHandle)> {
+        Ok(GracefulIncoming::new(self.incoming(handle)?))
+    }
+
+    /// Answer every connection with `response` and close it, for liveness probes of
+    /// orchestrators connecting to the endpoint.
+    pub fn serve_health(self, handle: &Handle, response: &[u8]) -> io::Result<ServeHealth> {
+        Ok(ServeHealth::new(self.incoming(handle)?, response))
+    }
+
+    /// Stream of incoming connections ending once `token` is cancelled.
+    ///
+    /// Lets an accept loop take part in a graceful shutdown: the stream yields `None`
+    /// as soon as the token is cancelled, even while waiting for a client, and closes
+    /// the listener.
+    #[cfg(feature = "cancellation")]
+    pub fn incoming_with_token(self, handle: &Handle, token: tokio_util::sync::CancellationToken) -> io::Result<Cancellable<Incoming>> {
+        Ok(Cancellable::new(self.incoming(handle)?, token))
     }
 
     /// Inner platform-dependant state of the endpoint
     #[cfg(windows)]
-    fn inner(&mut self, handle: &Handle) -> io::Result<NamedPipe> {
+    fn inner(&self, handle: &Handle) -> io::Result<NamedPipe> {
         extern crate mio_named_pipes;
         use std::os::windows::io::*;
-        use miow::pipe::NamedPipeBuilder;
 
-        let raw_handle = unsafe { NamedPipeBuilder::new(&self.path)
-            .first(true)
-            .inbound(true)
-            .outbound(true)
-            .out_buffer_size(65536)
-            .in_buffer_size(65536)
-            .with_security_attributes(self.security_attributes.as_ptr())?
-            .into_raw_handle()};
+        let mut security_attributes = self.security_attributes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let raw_handle = self.pipe_options
+            .create(&self.path, true, true, &mut security_attributes)?
+            .into_raw_handle();
 
         let mio_pipe = unsafe { mio_named_pipes::NamedPipe::from_raw_handle(raw_handle) };
         NamedPipe::from_pipe(mio_pipe, handle)
@@ -128,221 +432,1683 @@ impl Endpoint {
 
     /// Inner platform-dependant state of the endpoint
     #[cfg(not(windows))]
-    fn inner(&self, _handle: &Handle) -> io::Result<tokio_uds::UnixListener> {
-        tokio_uds::UnixListener::bind(&self.path)
+    fn inner(&self, handle: &Handle) -> io::Result<tokio_uds::UnixListener> {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(name) = self.path.strip_prefix('@') {
+                use std::os::linux::net::SocketAddrExt;
+                use std::os::unix::net::{SocketAddr, UnixListener};
+
+                let listener = UnixListener::bind_addr(&SocketAddr::from_abstract_name(name)?)?;
+                listener.set_nonblocking(true)?;
+                return tokio_uds::UnixListener::from_std(listener, handle);
+            }
+        }
+        let _ = handle;
+
+        self.check_parent_dir()?;
+        let listener = match self.mode {
+            Some(mode) => {
+                let _umask = umask::UmaskGuard::restrict_to(mode);
+                tokio_uds::UnixListener::bind(&self.path)?
+            },
+            None => tokio_uds::UnixListener::bind(&self.path)?,
+        };
+        if let Some((uid, gid)) = self.owner {
+            ::std::os::unix::fs::chown(&self.path, Some(uid), Some(gid)).map_err(|err| {
+                io::Error::new(err.kind(), format!("failed to change the owner of {} to {}:{}: {}", self.path, uid, gid, err))
+            })?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(ref context) = self.selinux_context {
+                selinux::set_context(Path::new(&self.path), context)?;
+            }
+        }
+        Ok(listener)
     }
 
     /// Set security attributes for the connection
     pub fn set_security_attributes(&mut self, security_attributes: SecurityAttributes) {
-        self.security_attributes = security_attributes;
+        self.security_attributes = Arc::new(Mutex::new(security_attributes));
     }
 
-    /// Returns the path of the endpoint.
-    pub fn path(&self) -> &str {
-        &self.path
+    /// Set the type of the created pipes, byte stream by default.
+    ///
+    /// Clients connecting through `IpcConnection::connect` switch to message
+    /// read mode automatically when the pipe is a message pipe.
+    #[cfg(windows)]
+    pub fn set_pipe_mode(&mut self, pipe_mode: PipeMode) {
+        self.pipe_options.pipe_mode = pipe_mode;
     }
 
-    /// New IPC endpoint at the given path
-    pub fn new(path: String) -> Self {
-        Endpoint {
-            path: path,
-            security_attributes: SecurityAttributes::empty(),
-        }
+    /// Set the read mode of the server ends of the created pipes, the same as the type
+    /// of the pipe by default.
+    ///
+    /// Lets a server read a message pipe as a byte stream while its clients write
+    /// messages. Byte pipes can only be read as bytes, creating them with `Message`
+    /// read mode fails with `InvalidInput`.
+    #[cfg(windows)]
+    pub fn set_read_mode(&mut self, read_mode: PipeMode) {
+        self.pipe_options.read_mode = Some(read_mode);
     }
-}
-
-/// Remote connection data, if any available
-pub struct RemoteId;
-
-#[cfg(windows)]
-struct NamedPipeSupport {
-    path: String,
-    handle: Handle,
-    pipe: NamedPipe,
-    security_attributes: SecurityAttributes,
-}
 
-#[cfg(windows)]
-impl NamedPipeSupport {
-    fn replacement_pipe(&mut self) -> io::Result<NamedPipe> {
-        extern crate mio_named_pipes;
-
-        use std::os::windows::io::*;
-        use miow::pipe::NamedPipeBuilder;
+    /// Set the maximum number of pipe instances, 255 meaning unlimited (the default).
+    #[cfg(windows)]
+    pub fn set_max_instances(&mut self, max_instances: u8) {
+        self.pipe_options.max_instances = max_instances;
+    }
 
-        let raw_handle = unsafe { NamedPipeBuilder::new(&self.path)
-            .first(false)
-            .inbound(true)
-            .outbound(true)
-            .out_buffer_size(65536)
-            .in_buffer_size(65536)
-            .with_security_attributes(self.security_attributes.as_ptr())?
-            .into_raw_handle()};
+    /// Set how many pipe instances await connections, see `InstanceStrategy`.
+    #[cfg(windows)]
+    pub fn set_instance_strategy(&mut self, instances: InstanceStrategy) {
+        self.pipe_options.instances = instances;
+    }
 
-        let mio_pipe = unsafe { mio_named_pipes::NamedPipe::from_raw_handle(raw_handle) };
-        NamedPipe::from_pipe(mio_pipe, &self.handle)
+    /// Create the pipes with `FILE_FLAG_WRITE_THROUGH`, off by default.
+    ///
+    /// Write-through only matters for pipes accessed over the network, where it stops
+    /// the redirector from buffering small writes; clients can set it with `ClientOptions`.
+    #[cfg(windows)]
+    pub fn set_write_through(&mut self, write_through: bool) {
+        self.pipe_options.write_through = write_through;
     }
-}
 
-/// Stream of incoming connections
-pub struct Incoming {
-    #[cfg(not(windows))]
-    inner: tokio_uds::Incoming,
+    /// Retry creating pipe instances that failed to be created, e.g. with
+    /// `ERROR_TOO_MANY_OPEN_FILES` or once the maximum number of instances is reached,
+    /// with the backoff of `retry`.
+    ///
+    /// By default the first failure ends the stream of incoming connections along with
+    /// the connection just accepted. With a policy, the connection is handed out and its
+    /// replacement created later on; meanwhile clients may find no instance to connect
+    /// to. The stream fails once `retry.max_attempts` attempts in a row failed.
     #[cfg(windows)]
-    inner: NamedPipeSupport,
-}
+    pub fn set_instance_retry(&mut self, retry: Option<RetryConfig>) {
+        self.pipe_options.instance_retry = retry;
+    }
 
-impl Stream for Incoming {
-    type Item = (IpcConnection, RemoteId);
-    type Error = io::Error;
+    /// Set the direction of the pipes, duplex by default.
+    ///
+    /// One-way pipes don't allocate the buffer of the unused direction, whatever
+    /// `set_buffer_size` says, saving memory for streaming workloads. Clients have to
+    /// connect with the matching `ClientOptions::direction`.
+    #[cfg(windows)]
+    pub fn set_direction(&mut self, direction: PipeDirection) {
+        self.pipe_options.direction = direction;
+    }
 
-    #[cfg(not(windows))]
-    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
-        self.inner.poll().map(|poll| match poll {
-            Async::Ready(Some(val)) => Async::Ready(Some((IpcConnection { inner: val }, RemoteId))),
-            Async::Ready(None) => Async::Ready(None),
-            Async::NotReady => Async::NotReady,
-        })
+    /// Set the sizes the system reserves for the inbound (client to server) and outbound
+    /// buffers of each pipe, 64 KiB each by default.
+    ///
+    /// The sizes are advisory: the system may round them or grow the buffers as needed.
+    #[cfg(windows)]
+    pub fn set_buffer_size(&mut self, in_size: u32, out_size: u32) {
+        self.pipe_options.in_buffer_size = in_size;
+        self.pipe_options.out_buffer_size = out_size;
     }
 
+    /// Set the default timeout of the pipe, used by clients waiting for an instance
+    /// with `WaitNamedPipe(NMPWAIT_USE_DEFAULT_WAIT)`.
+    ///
+    /// `None` and `Duration::ZERO` both mean the system default of 50 milliseconds, not
+    /// an infinite timeout. Longer timeouts are rounded to whole milliseconds. Clients of
+    /// this crate always wait as given by their `PipeWait`.
     #[cfg(windows)]
-    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
-        match self.inner.pipe.connect() {
-            Ok(()) => {
-                trace!("Incoming connection polled successfully");
-                let new_listener = self.inner.replacement_pipe()?;
-                Ok(Async::Ready(Some((
-                        IpcConnection {
-                            inner: ::std::mem::replace(
-                                &mut self.inner.pipe,
-                                new_listener,
-                            )
-                        },
-                        RemoteId,
-                ))))
-            },
-            Err(e) => {
-                if e.kind() == io::ErrorKind::WouldBlock {
-                    trace!("Incoming connection was to block, waiting for connection to become writeable");
-                    self.inner.pipe.poll_write_ready()?;
-                    Ok(Async::NotReady)
-                } else {
-                    Err(e)
-                }
-            },
-        }
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.pipe_options.default_timeout = timeout;
     }
-}
 
-/// IPC Connection
-pub struct IpcConnection {
-    #[cfg(not(windows))]
-    inner: tokio_uds::UnixStream,
+    /// Whether a server created the pipe at `path`, even if all its instances are busy.
+    ///
+    /// Tells a client whether waiting is worth it: connect fails with `ERROR_PIPE_BUSY`
+    /// (`ErrorKindExt::PipeBusy`) when the server exists but has no free instance, and
+    /// with `ERROR_FILE_NOT_FOUND` (`ErrorKindExt::NotFound`) when there is no server.
+    /// Checked with `WaitNamedPipe`, which doesn't take up an instance.
     #[cfg(windows)]
-    inner: tokio_named_pipes::NamedPipe,
-}
+    pub fn server_exists<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+        win_pipe::pipe_exists(path.as_ref())
+    }
 
-impl IpcConnection {
-    /// Make new connection using the provided path and running event pool.
-    pub fn connect<P: AsRef<Path>>(path: P, handle: &Handle) -> io::Result<IpcConnection> {
-        Ok(IpcConnection{
-            inner: Self::connect_inner(path.as_ref(), handle)?,
-        })
+    /// Set the permissions of the socket file, e.g. `0o600` for the owner only.
+    ///
+    /// The socket is created with a temporary umask, so it is never accessible with
+    /// wider permissions. The umask is process-global: files created by other threads
+    /// while binding get it as well.
+    #[cfg(unix)]
+    pub fn set_mode(&mut self, mode: u32) {
+        self.mode = Some(mode);
     }
 
+    /// Change the owner of the socket file to `uid` and group to `gid` after binding.
+    ///
+    /// Together with `set_mode`, e.g. `0o660`, this restricts a socket created by root
+    /// to a service group. Changing the owner needs privileges: without them binding
+    /// fails with `PermissionDenied`, leaving the socket file behind. Abstract sockets
+    /// have no file and are not affected.
     #[cfg(unix)]
-    fn connect_inner(path: &Path, _handle: &Handle) -> io::Result<tokio_uds::UnixStream> {
-        use futures::Future;
-        tokio_uds::UnixStream::connect(&path).wait()
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.owner = Some((uid, gid));
     }
 
-    #[cfg(windows)]
-    fn connect_inner(path: &Path, handle: &Handle) -> io::Result<NamedPipe> {
-        use std::fs::OpenOptions;
-        use std::os::windows::fs::OpenOptionsExt;
-        use std::os::windows::io::{FromRawHandle, IntoRawHandle};
-        use winapi::um::winbase::FILE_FLAG_OVERLAPPED;
+    /// Create the directory of the socket file, and its missing parents, when binding.
+    ///
+    /// Off by default: binding into a missing directory, e.g. one removed while the
+    /// server was running, fails with a `NotFound` error naming the directory. The
+    /// directories are created with the default permissions, minus the umask.
+    #[cfg(unix)]
+    pub fn set_create_parent_dirs(&mut self, create_parent_dirs: bool) {
+        self.create_parent_dirs = create_parent_dirs;
+    }
 
-        // Wait for the pipe to become available or fail after 5 seconds.
-        miow::pipe::NamedPipe::wait(path, Some(std::time::Duration::from_millis(PIPE_AVAILABILITY_TIMEOUT))).unwrap();
-        let mut options = OpenOptions::new();
-        options.read(true)
-            .write(true)
-            .custom_flags(FILE_FLAG_OVERLAPPED);
-        let file = options.open(path)?;
-        let mio_pipe = unsafe { mio_named_pipes::NamedPipe::from_raw_handle(file.into_raw_handle()) };
-        let pipe = NamedPipe::from_pipe(mio_pipe, handle)?;
-        Ok(pipe)
+    /// Never remove the socket file at the endpoint path, e.g. when it is bind-mounted
+    /// read-only or managed by the host.
+    ///
+    /// By default `rebind` replaces a stale socket file nothing listens on; with this
+    /// set, it fails with `AddrInUse` instead. Dropping the stream
+    /// never removes the file either way.
+    #[cfg(unix)]
+    pub fn set_keep_socket_file(&mut self, keep_socket_file: bool) {
+        self.keep_socket_file = keep_socket_file;
     }
-}
 
-impl Read for IpcConnection {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+    /// Label the socket file with the SELinux security context `context` after binding,
+    /// e.g. `system_u:object_r:my_sock_t:s0`, as needed by policies confining who connects.
+    ///
+    /// Binding fails with `InvalidInput` if the policy rejects the context and with
+    /// `Unsupported` if the file system can't store it, leaving the socket file behind.
+    /// Abstract sockets have no file and are not affected.
+    #[cfg(target_os = "linux")]
+    pub fn set_selinux_context(&mut self, context: String) {
+        self.selinux_context = Some(context);
     }
-}
 
-impl Write for IpcConnection {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+    /// Settings applied to every accepted connection, e.g. an idle timeout.
+    pub fn set_connection_config(&mut self, config: ConnectionConfig) {
+        self.connection_config = config;
     }
-    fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+
+    /// How failed accepts are reported, by default logged as warnings.
+    pub fn set_accept_error_log(&mut self, accept_error_log: AcceptErrorLog) {
+        self.accept_error_log = accept_error_log;
     }
-}
 
-impl AsyncRead for IpcConnection {
-    unsafe fn prepare_uninitialized_buffer(&self, b: &mut [u8]) -> bool {
-        self.inner.prepare_uninitialized_buffer(b)
+    /// Call `on_ready` once the endpoint listens, e.g. to notify a supervisor such as
+    /// systemd that the server is up.
+    ///
+    /// Called by the first `incoming` or `rebind` that succeeds, right after the socket
+    /// is bound or the pipe instances are created and before it returns, so clients
+    /// connecting from then on are accepted. Called at most once.
+    pub fn set_on_ready<F: FnOnce() + Send + 'static>(&mut self, on_ready: F) {
+        self.on_ready = Mutex::new(Some(Box::new(on_ready)));
     }
 
-    fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
-        self.inner.read_buf(buf)
+    /// Returns the path of the endpoint.
+    pub fn path(&self) -> &str {
+        &self.path
     }
-}
 
-impl AsyncWrite for IpcConnection {
-    fn shutdown(&mut self) -> Poll<(), io::Error> {
-        AsyncWrite::shutdown(&mut self.inner)
+    /// New IPC endpoint for `name`, picking the best kind of endpoint for the platform.
+    ///
+    /// On Linux this is an abstract socket, `@name`, which leaves no file behind to go
+    /// stale; use `new` with a filesystem path to share the socket across containers.
+    /// Other Unix systems get a socket in the temporary directory and Windows a pipe
+    /// `\\.\pipe\name`. `path` reports the choice.
+    pub fn new_auto(name: &str) -> Self {
+        let path = if cfg!(windows) {
+            format!(r"\\.\pipe\{}", name)
+        } else if cfg!(target_os = "linux") {
+            format!("@{}", name)
+        } else {
+            std::env::temp_dir().join(name).to_string_lossy().into_owned()
+        };
+        Endpoint::new(path)
     }
 
-    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
-        self.inner.write_buf(buf)
+    /// New IPC endpoint at the given path
+    ///
+    /// On Linux a path starting with `@` is an abstract socket.
+    pub fn new(path: String) -> Self {
+        Endpoint {
+            path,
+            security_attributes: Arc::new(Mutex::new(SecurityAttributes::empty())),
+            #[cfg(windows)]
+            pipe_options: PipeOptions::default(),
+            #[cfg(unix)]
+            mode: None,
+            #[cfg(unix)]
+            owner: None,
+            #[cfg(unix)]
+            create_parent_dirs: false,
+            #[cfg(unix)]
+            keep_socket_file: false,
+            #[cfg(target_os = "linux")]
+            selinux_context: None,
+            connection_config: ConnectionConfig::default(),
+            accept_error_log: AcceptErrorLog::default(),
+            on_ready: Mutex::new(None),
+        }
+    }
+
+    /// New IPC endpoint at the given path with the given security attributes
+    pub fn with_security_attributes(path: String, security_attributes: SecurityAttributes) -> Self {
+        let mut endpoint = Endpoint::new(path);
+        endpoint.set_security_attributes(security_attributes);
+        endpoint
+    }
+
+    /// Builder for an endpoint with several options.
+    pub fn builder() -> EndpointBuilder {
+        EndpointBuilder {
+            path: None,
+            security_attributes: None,
+            #[cfg(windows)]
+            pipe_options: PipeOptions::default(),
+            #[cfg(unix)]
+            mode: None,
+            #[cfg(unix)]
+            owner: None,
+            #[cfg(unix)]
+            create_parent_dirs: false,
+            #[cfg(unix)]
+            keep_socket_file: false,
+            #[cfg(target_os = "linux")]
+            selinux_context: None,
+            connection_config: ConnectionConfig::default(),
+            accept_error_log: AcceptErrorLog::default(),
+            on_ready: None,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    extern crate rand;
+/// Builder for `Endpoint`, created by `Endpoint::builder`.
+///
+/// # Examples
+///
+/// ```
+/// use susy_tokio_ipc::{Endpoint, SecurityAttributes, dummy_endpoint};
+///
+/// let endpoint = Endpoint::builder()
+///     .path(dummy_endpoint())
+///     .security_attributes(SecurityAttributes::allow_everyone_connect().unwrap())
+///     .build()
+///     .expect("path is set");
+/// ```
+pub struct EndpointBuilder {
+    path: Option<String>,
+    security_attributes: Option<SecurityAttributes>,
+    #[cfg(windows)]
+    pipe_options: PipeOptions,
+    #[cfg(unix)]
+    mode: Option<u32>,
+    #[cfg(unix)]
+    owner: Option<(u32, u32)>,
+    #[cfg(unix)]
+    create_parent_dirs: bool,
+    #[cfg(unix)]
+    keep_socket_file: bool,
+    #[cfg(target_os = "linux")]
+    selinux_context: Option<String>,
+    connection_config: ConnectionConfig,
+    accept_error_log: AcceptErrorLog,
+    on_ready: Option<ReadyCallback>,
+}
 
-    use tokio::{self, io::{self, AsyncRead}, runtime::TaskExecutor, reactor::Handle};
-    use futures::{sync::oneshot, Stream, Future};
-    use std::thread;
+impl EndpointBuilder {
+    /// Path of the endpoint, required.
+    pub fn path(mut self, path: String) -> Self {
+        self.path = Some(path);
+        self
+    }
 
-    use super::Endpoint;
-    use super::IpcConnection;
+    /// Security attributes for the connection
+    pub fn security_attributes(mut self, security_attributes: SecurityAttributes) -> Self {
+        self.security_attributes = Some(security_attributes);
+        self
+    }
+
+    /// Type of the created pipes, see `Endpoint::set_pipe_mode`.
     #[cfg(windows)]
-    use super::SecurityAttributes;
+    pub fn pipe_mode(mut self, pipe_mode: PipeMode) -> Self {
+        self.pipe_options.pipe_mode = pipe_mode;
+        self
+    }
 
-    #[cfg(not(windows))]
-    fn random_pipe_path() -> String {
-        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
-        format!(r"/tmp/susy-tokio-ipc-test-pipe-{}", num)
+    /// Read mode of the server ends of the created pipes, see `Endpoint::set_read_mode`.
+    #[cfg(windows)]
+    pub fn read_mode(mut self, read_mode: PipeMode) -> Self {
+        self.pipe_options.read_mode = Some(read_mode);
+        self
     }
 
+    /// Maximum number of pipe instances, see `Endpoint::set_max_instances`.
     #[cfg(windows)]
-    fn random_pipe_path() -> String {
-        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
-        format!(r"\\.\pipe\my-pipe-{}", num)
+    pub fn max_instances(mut self, max_instances: u8) -> Self {
+        self.pipe_options.max_instances = max_instances;
+        self
     }
 
-    fn run_server(path: &str, exec: TaskExecutor, handle: Handle) {
-        let path = path.to_owned();
-        let (ok_signal, ok_rx) = oneshot::channel();
-        thread::spawn(move || {
-            let endpoint = Endpoint::new(path);
-            let connections = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
-            let srv = connections.for_each(|(stream, _)| {
+    /// How many pipe instances await connections, see `Endpoint::set_instance_strategy`.
+    #[cfg(windows)]
+    pub fn instance_strategy(mut self, instances: InstanceStrategy) -> Self {
+        self.pipe_options.instances = instances;
+        self
+    }
+
+    /// Retry policy for creating pipe instances, see `Endpoint::set_instance_retry`.
+    #[cfg(windows)]
+    pub fn instance_retry(mut self, retry: Option<RetryConfig>) -> Self {
+        self.pipe_options.instance_retry = retry;
+        self
+    }
+
+    /// Direction of the pipes, see `Endpoint::set_direction`.
+    #[cfg(windows)]
+    pub fn direction(mut self, direction: PipeDirection) -> Self {
+        self.pipe_options.direction = direction;
+        self
+    }
+
+    /// Inbound and outbound buffer sizes of the pipes, see `Endpoint::set_buffer_size`.
+    #[cfg(windows)]
+    pub fn buffer_size(mut self, in_size: u32, out_size: u32) -> Self {
+        self.pipe_options.in_buffer_size = in_size;
+        self.pipe_options.out_buffer_size = out_size;
+        self
+    }
+
+    /// Create pipes with `FILE_FLAG_WRITE_THROUGH`, see `Endpoint::set_write_through`.
+    #[cfg(windows)]
+    pub fn write_through(mut self, write_through: bool) -> Self {
+        self.pipe_options.write_through = write_through;
+        self
+    }
+
+    /// Default timeout of the pipes, see `Endpoint::set_default_timeout`.
+    #[cfg(windows)]
+    pub fn default_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pipe_options.default_timeout = timeout;
+        self
+    }
+
+    /// Permissions of the socket file, see `Endpoint::set_mode`.
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Owner and group of the socket file, see `Endpoint::set_owner`.
+    #[cfg(unix)]
+    pub fn owner(mut self, uid: u32, gid: u32) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Create missing directories of the socket file, see `Endpoint::set_create_parent_dirs`.
+    #[cfg(unix)]
+    pub fn create_parent_dirs(mut self, create_parent_dirs: bool) -> Self {
+        self.create_parent_dirs = create_parent_dirs;
+        self
+    }
+
+    /// Never remove the socket file, see `Endpoint::set_keep_socket_file`.
+    #[cfg(unix)]
+    pub fn keep_socket_file(mut self, keep_socket_file: bool) -> Self {
+        self.keep_socket_file = keep_socket_file;
+        self
+    }
+
+    /// SELinux context of the socket file, see `Endpoint::set_selinux_context`.
+    #[cfg(target_os = "linux")]
+    pub fn selinux_context(mut self, context: String) -> Self {
+        self.selinux_context = Some(context);
+        self
+    }
+
+    /// Settings applied to every accepted connection, see `Endpoint::set_connection_config`.
+    pub fn connection_config(mut self, config: ConnectionConfig) -> Self {
+        self.connection_config = config;
+        self
+    }
+
+    /// How failed accepts are reported, see `Endpoint::set_accept_error_log`.
+    pub fn accept_error_log(mut self, accept_error_log: AcceptErrorLog) -> Self {
+        self.accept_error_log = accept_error_log;
+        self
+    }
+
+    /// Called once the endpoint listens, see `Endpoint::set_on_ready`.
+    pub fn on_ready<F: FnOnce() + Send + 'static>(mut self, on_ready: F) -> Self {
+        self.on_ready = Some(Box::new(on_ready));
+        self
+    }
+
+    /// Build the endpoint, failing with `InvalidInput` if no path was set.
+    pub fn build(self) -> io::Result<Endpoint> {
+        let path = self.path.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "endpoint path is not set"))?;
+        Ok(Endpoint {
+            path,
+            security_attributes: Arc::new(Mutex::new(self.security_attributes.unwrap_or_else(SecurityAttributes::empty))),
+            #[cfg(windows)]
+            pipe_options: self.pipe_options,
+            #[cfg(unix)]
+            mode: self.mode,
+            #[cfg(unix)]
+            owner: self.owner,
+            #[cfg(unix)]
+            create_parent_dirs: self.create_parent_dirs,
+            #[cfg(unix)]
+            keep_socket_file: self.keep_socket_file,
+            #[cfg(target_os = "linux")]
+            selinux_context: self.selinux_context,
+            connection_config: self.connection_config,
+            accept_error_log: self.accept_error_log,
+            on_ready: Mutex::new(self.on_ready),
+        })
+    }
+}
+
+/// Remote connection data, if any available
+pub struct RemoteId;
+
+#[cfg(windows)]
+struct NamedPipeSupport {
+    path: String,
+    handle: Handle,
+    // Instances awaiting a connection, only empty while replacements are retried.
+    pipes: Vec<NamedPipe>,
+    security_attributes: Arc<Mutex<SecurityAttributes>>,
+    pipe_options: PipeOptions,
+    // Instances that failed to be replaced, created again once `retry_delay` elapsed.
+    missing: usize,
+    failures: usize,
+    retry_delay: Option<Delay>,
+}
+
+#[cfg(windows)]
+impl NamedPipeSupport {
+    fn replacement_pipe(&mut self) -> io::Result<NamedPipe> {
+        extern crate mio_named_pipes;
+
+        use std::os::windows::io::*;
+
+        let mut security_attributes = self.security_attributes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let raw_handle = self.pipe_options
+            .create(&self.path, false, true, &mut security_attributes)?
+            .into_raw_handle();
+
+        let mio_pipe = unsafe { mio_named_pipes::NamedPipe::from_raw_handle(raw_handle) };
+        NamedPipe::from_pipe(mio_pipe, &self.handle)
+    }
+
+    /// Schedule another attempt at creating an instance after `err`, if the retry policy
+    /// allows, failing with `err` otherwise.
+    fn retry_replacement(&mut self, err: io::Error) -> io::Result<()> {
+        let delay = match self.pipe_options.instance_retry {
+            Some(ref retry) if self.failures + 1 < retry.max_attempts => retry.delay(self.failures),
+            _ => return Err(err),
+        };
+        self.failures += 1;
+        warn!("Failed to create a pipe instance: {:?}, retrying in {:?}", err, delay);
+        self.retry_delay = Some(Delay::new(Instant::now() + delay));
+        Ok(())
+    }
+
+    /// Create the missing instances, as far as their retry delay elapsed.
+    fn poll_replacements(&mut self) -> io::Result<()> {
+        use futures::Future;
+
+        while self.missing > 0 {
+            if let Some(ref mut delay) = self.retry_delay {
+                if let Async::NotReady = delay.poll().map_err(io::Error::other)? {
+                    return Ok(());
+                }
+            }
+            self.retry_delay = None;
+
+            match self.replacement_pipe() {
+                Ok(pipe) => {
+                    self.pipes.push(pipe);
+                    self.missing -= 1;
+                    self.failures = 0;
+                },
+                Err(e) => self.retry_replacement(e)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn first_pipe(&self) -> io::Result<&NamedPipe> {
+        self.pipes.first().ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "no pipe instance awaits connections"))
+    }
+}
+
+/// Stream of incoming connections
+pub struct Incoming {
+    #[cfg(not(windows))]
+    inner: tokio_uds::UnixListener,
+    #[cfg(windows)]
+    inner: NamedPipeSupport,
+    config: ConnectionConfig,
+    accept_error_log: AcceptErrorLog,
+}
+
+impl Incoming {
+    /// Whether the listener is bound and able to accept connections.
+    ///
+    /// On Windows this means a pipe instance exists awaiting a connection.
+    pub fn is_bound(&self) -> bool {
+        self.local_addr().is_ok()
+    }
+
+    /// Path the listener is bound to, `@name` for abstract sockets.
+    #[cfg(not(windows))]
+    pub fn local_addr(&self) -> io::Result<String> {
+        let addr = self.inner.local_addr()?;
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+
+            if let Some(name) = addr.as_abstract_name() {
+                return Ok(format!("@{}", String::from_utf8_lossy(name)));
+            }
+        }
+        addr.as_pathname()
+            .map(|path| path.to_string_lossy().into_owned())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "socket is not bound to a path"))
+    }
+
+    /// Name of the pipe awaiting connections.
+    #[cfg(windows)]
+    pub fn local_addr(&self) -> io::Result<String> {
+        use std::os::windows::io::AsRawHandle;
+
+        win_pipe::pipe_flags(self.inner.first_pipe()?.as_raw_handle())?;
+        Ok(self.inner.path.clone())
+    }
+
+    /// DACL actually applied to the pipe awaiting connections, in SDDL form.
+    ///
+    /// Useful to confirm the configured security attributes took effect, e.g. that
+    /// `allow_everyone_connect` grants access to the World SID (`WD`).
+    #[cfg(windows)]
+    pub fn effective_security(&self) -> io::Result<String> {
+        use std::os::windows::io::AsRawHandle;
+
+        win_permissions::dacl_sddl(self.inner.first_pipe()?.as_raw_handle() as _)
+    }
+}
+
+impl Incoming {
+    #[cfg(not(windows))]
+    fn poll_connection(&mut self) -> Poll<Option<(IpcConnection, RemoteId)>, io::Error> {
+        let (stream, _) = try_ready!(self.inner.poll_accept());
+        Ok(Async::Ready(Some((self.config.apply(IpcConnection::from_inner(stream)), RemoteId))))
+    }
+
+    #[cfg(windows)]
+    fn poll_connection(&mut self) -> Poll<Option<(IpcConnection, RemoteId)>, io::Error> {
+        self.inner.poll_replacements()?;
+        for index in 0..self.inner.pipes.len() {
+            match self.inner.pipes[index].connect() {
+                Ok(()) => {
+                    trace!("Incoming connection polled successfully");
+                    let pipe = match self.inner.replacement_pipe() {
+                        Ok(new_listener) => ::std::mem::replace(&mut self.inner.pipes[index], new_listener),
+                        Err(e) => {
+                            // Hand out the connection anyway, its instance is created again later.
+                            self.inner.retry_replacement(e)?;
+                            self.inner.missing += 1;
+                            self.inner.pipes.remove(index)
+                        },
+                    };
+                    return Ok(Async::Ready(Some((self.config.apply(IpcConnection::from_inner(pipe)), RemoteId))));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    trace!("Incoming connection was to block, waiting for connection to become writeable");
+                    self.inner.pipes[index].poll_write_ready()?;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Stream for Incoming {
+    type Item = (IpcConnection, RemoteId);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        self.poll_connection().inspect_err(|err| self.accept_error_log.report(err))
+    }
+}
+
+/// Stream of incoming connections tagged with the address they were accepted on.
+///
+/// Created by `Endpoint::incoming_with_addr`. The address is the one reported by
+/// `Incoming::local_addr`: the socket path, `@name` for abstract sockets, or the pipe
+/// name. Unix clients usually connect from unnamed sockets, so the address of the
+/// listener identifies a connection's origin better than the peer's.
+pub struct IncomingWithAddr {
+    inner: Incoming,
+    addr: String,
+}
+
+impl IncomingWithAddr {
+    /// Address the connections are accepted on.
+    pub fn local_addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+impl Stream for IncomingWithAddr {
+    type Item = (IpcConnection, String);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        let accepted = try_ready!(self.inner.poll());
+        Ok(Async::Ready(accepted.map(|(connection, _)| (connection, self.addr.clone()))))
+    }
+}
+
+/// IPC Connection
+pub struct IpcConnection {
+    #[cfg(not(windows))]
+    inner: tokio_uds::UnixStream,
+    #[cfg(windows)]
+    inner: tokio_named_pipes::NamedPipe,
+    max_read_chunk: Option<usize>,
+    read_yield: bool,
+    idle_timeout: Option<Duration>,
+    // Deadline of the current wait for data, if reads are waiting.
+    idle_timer: Option<Delay>,
+    peer_info: Option<PeerInfo>,
+    connect_duration: Option<Duration>,
+    // Data read by `poll_peek`, returned by the next reads.
+    #[cfg(windows)]
+    peeked: Vec<u8>,
+}
+
+impl IpcConnection {
+    #[cfg(not(windows))]
+    fn from_inner(inner: tokio_uds::UnixStream) -> Self {
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+        {
+            if let Err(e) = sockopt::set_nosigpipe(inner.as_raw_fd()) {
+                warn!("Failed to disable SIGPIPE on the socket: {:?}", e);
+            }
+        }
+        let peer_info = Self::capture_peer_info(peer::of(&inner));
+        IpcConnection { inner, max_read_chunk: None, read_yield: false, idle_timeout: None, idle_timer: None, peer_info, connect_duration: None }
+    }
+
+    #[cfg(windows)]
+    fn from_inner(inner: tokio_named_pipes::NamedPipe) -> Self {
+        let peer_info = Self::capture_peer_info(peer::of(&inner));
+        IpcConnection { inner, max_read_chunk: None, read_yield: false, idle_timeout: None, idle_timer: None, peer_info, connect_duration: None, peeked: Vec::new() }
+    }
+
+    fn capture_peer_info(peer_info: io::Result<PeerInfo>) -> Option<PeerInfo> {
+        peer_info.map_err(|e| trace!("Failed to get peer info: {:?}", e)).ok()
+    }
+
+    fn connected_since(mut self, started: Instant) -> Self {
+        self.connect_duration = Some(started.elapsed());
+        self
+    }
+
+    /// Make new connection using the provided path and running event pool.
+    pub fn connect<P: AsRef<Path>>(path: P, handle: &Handle) -> io::Result<IpcConnection> {
+        let started = Instant::now();
+        Ok(IpcConnection::from_inner(Self::connect_inner(path.as_ref(), handle)?).connected_since(started))
+    }
+
+    /// Make new connection, retrying with backoff according to `config` on failure.
+    ///
+    /// Blocks the current thread between attempts and returns the last error once
+    /// `config.max_attempts` is exhausted.
+    pub fn connect_with_retry<P: AsRef<Path>>(path: P, handle: &Handle, config: &RetryConfig) -> io::Result<IpcConnection> {
+        let started = Instant::now();
+        let mut retry = 0;
+        loop {
+            match Self::connect(path.as_ref(), handle) {
+                Ok(connection) => return Ok(connection.connected_since(started)),
+                Err(ref e) if retry + 1 < config.max_attempts => {
+                    let delay = config.delay(retry);
+                    trace!("Connection attempt {} failed: {:?}, retrying in {:?}", retry + 1, e, delay);
+                    std::thread::sleep(delay);
+                    retry += 1;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Make new connection to the first of `paths` that accepts one, e.g. a per-user
+    /// socket before the system-wide one.
+    ///
+    /// Paths are tried in order. When all fail, the error lists each path with its
+    /// error, and has their kind if they all agree, `Other` otherwise.
+    pub fn connect_any<P: AsRef<Path>>(paths: &[P], handle: &Handle) -> io::Result<IpcConnection> {
+        let mut errors = Vec::with_capacity(paths.len());
+        for path in paths {
+            match Self::connect(path, handle) {
+                Ok(connection) => return Ok(connection),
+                Err(e) => {
+                    trace!("Failed to connect to {}: {:?}", path.as_ref().display(), e);
+                    errors.push((path.as_ref(), e));
+                },
+            }
+        }
+
+        let kind = match errors.first() {
+            Some((_, first)) if errors.iter().all(|(_, e)| e.kind() == first.kind()) => first.kind(),
+            Some(_) => io::ErrorKind::Other,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no paths to connect to")),
+        };
+        let details = errors.iter()
+            .map(|&(path, ref e)| format!("{}: {}", path.display(), e))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(io::Error::new(kind, format!("failed to connect to any endpoint ({})", details)))
+    }
+
+    #[cfg(unix)]
+    fn connect_inner(path: &Path, handle: &Handle) -> io::Result<tokio_uds::UnixStream> {
+        use futures::Future;
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+                return Self::connect_abstract_inner(name, handle);
+            }
+        }
+        let _ = handle;
+        tokio_uds::UnixStream::connect(path).wait()
+    }
+
+    /// Make new connection to the abstract socket `name`, as bound by an endpoint at `@name`.
+    ///
+    /// `connect` does the same for paths starting with `@`.
+    #[cfg(target_os = "linux")]
+    pub fn connect_abstract(name: &str, handle: &Handle) -> io::Result<IpcConnection> {
+        let started = Instant::now();
+        Ok(IpcConnection::from_inner(Self::connect_abstract_inner(name, handle)?).connected_since(started))
+    }
+
+    /// Make new connection to the abstract socket `name` if given, falling back to the
+    /// filesystem socket at `path`, for services bound to both so that clients work in
+    /// sandboxes blocking either kind.
+    ///
+    /// Pairs with an endpoint from `Endpoint::new_auto(name)` next to one at `path`.
+    /// When both fail, the error lists both, as for `connect_any`.
+    #[cfg(target_os = "linux")]
+    pub fn connect_abstract_or_path<P: AsRef<Path>>(name: Option<&str>, path: P, handle: &Handle) -> io::Result<IpcConnection> {
+        match name {
+            Some(name) => Self::connect_any(&[Path::new(&format!("@{}", name)), path.as_ref()], handle),
+            None => Self::connect(path, handle),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn connect_abstract_inner(name: &str, handle: &Handle) -> io::Result<tokio_uds::UnixStream> {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixStream};
+
+        // Connecting a Unix socket doesn't block, the server's backlog takes the connection.
+        let stream = UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)?;
+        stream.set_nonblocking(true)?;
+        tokio_uds::UnixStream::from_std(stream, handle)
+    }
+
+    /// Make new connection, waiting as specified by `wait` while all pipe instances are busy.
+    ///
+    /// `connect` waits up to 5 seconds.
+    #[cfg(windows)]
+    pub fn connect_with_wait<P: AsRef<Path>>(path: P, handle: &Handle, wait: PipeWait) -> io::Result<IpcConnection> {
+        Self::connect_with_options(path, handle, &ClientOptions::new().wait(wait))
+    }
+
+    /// Make new connection with the given client options.
+    #[cfg(windows)]
+    pub fn connect_with_options<P: AsRef<Path>>(path: P, handle: &Handle, options: &ClientOptions) -> io::Result<IpcConnection> {
+        let started = Instant::now();
+        Ok(IpcConnection::from_inner(Self::connect_pipe(path.as_ref(), handle, options)?).connected_since(started))
+    }
+
+    /// Make new connection, waiting up to `total_timeout` for a server to create the pipe,
+    /// e.g. for a client started along with its server.
+    ///
+    /// Blocks the current thread, polling with exponential backoff while the pipe doesn't
+    /// exist and waiting on busy instances otherwise. Fails with `TimedOut` once no server
+    /// turned up in time.
+    #[cfg(windows)]
+    pub fn connect_wait_for_server<P: AsRef<Path>>(path: P, handle: &Handle, total_timeout: Duration) -> io::Result<IpcConnection> {
+        let started = Instant::now();
+        let file = win_pipe::open_waiting_for_server(path.as_ref(), total_timeout)?;
+        Ok(IpcConnection::from_inner(Self::client_pipe(file, handle)?).connected_since(started))
+    }
+
+    /// Make new connection, rejecting the server unless the pipe is owned by the
+    /// string SID `expected_owner` (e.g. `S-1-5-18` for LocalSystem).
+    ///
+    /// Guards against a squatting server that created the pipe name first. On mismatch
+    /// the pipe is closed and a `PermissionDenied` error returned. Note processes run by
+    /// administrators usually create objects owned by the Administrators group (`S-1-5-32-544`).
+    #[cfg(windows)]
+    pub fn connect_expecting_owner<P: AsRef<Path>>(path: P, handle: &Handle, expected_owner: &str) -> io::Result<IpcConnection> {
+        use std::os::windows::io::AsRawHandle;
+
+        let connection = Self::connect(path, handle)?;
+        win_permissions::check_owner(connection.inner.as_raw_handle() as _, expected_owner)?;
+        Ok(connection)
+    }
+
+    #[cfg(windows)]
+    fn connect_inner(path: &Path, handle: &Handle) -> io::Result<NamedPipe> {
+        Self::connect_pipe(path, handle, &ClientOptions::new())
+    }
+
+    #[cfg(windows)]
+    fn connect_pipe(path: &Path, handle: &Handle, client_options: &ClientOptions) -> io::Result<NamedPipe> {
+        Self::client_pipe(client_options.open(path)?, handle)
+    }
+
+    #[cfg(windows)]
+    fn client_pipe(file: ::std::fs::File, handle: &Handle) -> io::Result<NamedPipe> {
+        use std::os::windows::io::{AsRawHandle, FromRawHandle, IntoRawHandle};
+
+        win_pipe::match_read_mode(file.as_raw_handle())?;
+        let mio_pipe = unsafe { mio_named_pipes::NamedPipe::from_raw_handle(file.into_raw_handle()) };
+        let pipe = NamedPipe::from_pipe(mio_pipe, handle)?;
+        Ok(pipe)
+    }
+
+    /// Poll whether the connection is readable, registering the current task otherwise.
+    ///
+    /// Once ready, `read` either returns data or `WouldBlock`, in which case readiness
+    /// is cleared and the connection has to be polled again.
+    #[cfg(not(windows))]
+    pub fn poll_read_ready(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll_read_ready(mio::Ready::readable()).map(|ready| ready.map(|_| ()))
+    }
+
+    /// Poll whether the connection is readable, registering the current task otherwise.
+    ///
+    /// Once ready, `read` either returns data or `WouldBlock`, in which case readiness
+    /// is cleared and the connection has to be polled again.
+    #[cfg(windows)]
+    pub fn poll_read_ready(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll_read_ready_readable().map(|ready| ready.map(|_| ()))
+    }
+
+    /// Read into several buffers at once, e.g. a fixed size header and the body,
+    /// filling them in order.
+    ///
+    /// Done with a single `readv` call on Unix. Behaves like `read` otherwise, returning
+    /// `WouldBlock` and registering the current task when no data is available.
+    #[cfg(unix)]
+    pub fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        use std::mem::ManuallyDrop;
+        use std::os::unix::{io::FromRawFd, net};
+
+        if let Async::NotReady = self.poll_read_ready()? {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // Borrow the descriptor as a std stream, whose `read_vectored` uses `readv`.
+        let mut stream = ManuallyDrop::new(unsafe { net::UnixStream::from_raw_fd(self.inner.as_raw_fd()) });
+        match stream.read_vectored(bufs) {
+            // Readiness can only be cleared by a plain read that would block, which
+            // returns any data that arrived meanwhile instead.
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+                Some(buf) => self.inner.read(buf),
+                None => Ok(0),
+            },
+            result => result,
+        }
+    }
+
+    /// Read into several buffers at once, see the Unix version.
+    ///
+    /// Pipes don't support scatter reads (`ReadFileScatter` only works on unbuffered
+    /// files), so only the first non-empty buffer is filled.
+    #[cfg(windows)]
+    pub fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+            Some(buf) => self.read(buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Read a whole message from a message-mode pipe.
+    ///
+    /// Plain reads split a message across calls when the buffer is smaller than the
+    /// message, losing its boundary. Each read of the pipe driver completes with a
+    /// single message, which is returned entirely here. Messages up to
+    /// `MAX_MESSAGE_LEN` bytes can be read: the driver discards the data of a read
+    /// failing with `ERROR_MORE_DATA`, so longer ones can't be reassembled and fail
+    /// with `InvalidData`. Returns `WouldBlock` and registers the current task like
+    /// `read` when no message is available.
+    #[cfg(windows)]
+    pub fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        use winapi::shared::winerror::ERROR_MORE_DATA;
+
+        let mut message = vec![0u8; win_pipe::MAX_MESSAGE_LEN];
+        match self.read(&mut message) {
+            Ok(n) => {
+                message.truncate(n);
+                Ok(message)
+            },
+            Err(ref e) if e.raw_os_error() == Some(ERROR_MORE_DATA as i32) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("message longer than {} bytes", win_pipe::MAX_MESSAGE_LEN))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copy the next incoming bytes into `buf` without consuming them, registering the
+    /// current task when none are available.
+    ///
+    /// Unlike `read`, the same bytes are returned again by the next `poll_peek` or read,
+    /// so a router can look at a magic prefix from within `poll_fn` before handing the
+    /// connection over. Returns fewer bytes than `buf` holds when fewer arrived so far,
+    /// and `Ready(0)` once the peer closed its end.
+    #[cfg(unix)]
+    pub fn poll_peek(&mut self, buf: &mut [u8]) -> Poll<usize, io::Error> {
+        try_ready!(self.poll_read_ready());
+        let peeked = unsafe {
+            libc::recv(self.inner.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len(), libc::MSG_PEEK | libc::MSG_DONTWAIT)
+        };
+        if peeked >= 0 {
+            return Ok(Async::Ready(peeked as usize));
+        }
+        match io::Error::last_os_error() {
+            // Readiness can only be cleared by a plain read that would block; an empty
+            // one consumes nothing should data arrive meanwhile.
+            ref e if e.kind() == io::ErrorKind::WouldBlock => match self.inner.read(&mut []) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+                Err(e) => Err(e),
+                Ok(_) => {
+                    futures::task::current().notify();
+                    Ok(Async::NotReady)
+                },
+            },
+            e => Err(e),
+        }
+    }
+
+    /// Copy the next incoming bytes into `buf` without consuming them, see the Unix version.
+    ///
+    /// The reactor reads pipes ahead into its own buffer, out of reach of
+    /// `PeekNamedPipe`, so the bytes are read here and kept for the next reads instead.
+    #[cfg(windows)]
+    pub fn poll_peek(&mut self, buf: &mut [u8]) -> Poll<usize, io::Error> {
+        if self.peeked.len() < buf.len() {
+            let mut chunk = vec![0u8; buf.len() - self.peeked.len()];
+            match self.inner.read(&mut chunk) {
+                Ok(n) => self.peeked.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock && !self.peeked.is_empty() => {},
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(e) => return Err(e),
+            }
+        }
+        let n = buf.len().min(self.peeked.len());
+        buf[..n].copy_from_slice(&self.peeked[..n]);
+        Ok(Async::Ready(n))
+    }
+
+    /// Poll whether the connection is writable, registering the current task otherwise.
+    pub fn poll_write_ready(&mut self) -> Poll<(), io::Error> {
+        self.inner.poll_write_ready().map(|ready| ready.map(|_| ()))
+    }
+
+    /// Future resolving to the connection once it is readable.
+    pub fn readable(self) -> Readable {
+        Readable::new(self)
+    }
+
+    /// Future resolving to the connection once it is writable.
+    pub fn writable(self) -> Writable {
+        Writable::new(self)
+    }
+
+    /// New handle to the same connection, registered with the reactor of the task
+    /// first using it.
+    ///
+    /// Both handles share the underlying socket: data read through one isn't seen by
+    /// the other and the connection stays open until all handles are dropped. Only
+    /// supported on Unix, where the file descriptor is duplicated; an overlapped pipe
+    /// handle can't be associated with the reactor's completion port a second time.
+    #[cfg(unix)]
+    pub fn try_clone(&self) -> io::Result<IpcConnection> {
+        use std::mem::ManuallyDrop;
+        use std::os::unix::{io::FromRawFd, net};
+
+        // Borrow the descriptor as a std stream just to `dup` it.
+        let stream = ManuallyDrop::new(unsafe { net::UnixStream::from_raw_fd(self.inner.as_raw_fd()) });
+        let clone = stream.try_clone()?;
+        Ok(IpcConnection::from_inner(tokio_uds::UnixStream::from_std(clone, &Handle::default())?))
+    }
+
+    /// New handle to the same connection, see the Unix version.
+    ///
+    /// Always fails on Windows: an overlapped pipe handle can't be associated with the
+    /// reactor's completion port a second time.
+    #[cfg(windows)]
+    pub fn try_clone(&self) -> io::Result<IpcConnection> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "cloning pipe connections is not supported"))
+    }
+
+    /// Turn the connection into a blocking std stream, for driving the socket
+    /// synchronously, e.g. from FFI code or a blocking thread.
+    ///
+    /// The descriptor is duplicated and the connection dropped, deregistering it from
+    /// its reactor, before the duplicate is made blocking: the blocking flag is shared by
+    /// all descriptors of a socket, so it can't be cleared while the reactor still polls
+    /// it. Use `from_blocking` to hand the stream back to a reactor.
+    #[cfg(unix)]
+    pub fn into_blocking(self) -> io::Result<::std::os::unix::net::UnixStream> {
+        use std::mem::ManuallyDrop;
+        use std::os::unix::{io::FromRawFd, net};
+
+        let stream = ManuallyDrop::new(unsafe { net::UnixStream::from_raw_fd(self.inner.as_raw_fd()) });
+        let blocking = stream.try_clone()?;
+        drop(self);
+        blocking.set_nonblocking(false)?;
+        Ok(blocking)
+    }
+
+    /// Turn the connection into a blocking std handle, see the Unix version.
+    ///
+    /// Always fails on Windows: the pipe was opened for overlapped I/O, which can't be
+    /// turned off, and stays associated with the reactor's completion port until closed.
+    #[cfg(windows)]
+    pub fn into_blocking(self) -> io::Result<::std::fs::File> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "pipe connections can't be made blocking"))
+    }
+
+    /// Register a blocking std stream, e.g. from `into_blocking`, with `handle`'s reactor.
+    ///
+    /// The stream is made non-blocking again.
+    #[cfg(unix)]
+    pub fn from_blocking(stream: ::std::os::unix::net::UnixStream, handle: &Handle) -> io::Result<IpcConnection> {
+        Ok(IpcConnection::from_inner(tokio_uds::UnixStream::from_std(stream, handle)?))
+    }
+
+    /// Register a pipe opened synchronously elsewhere, e.g. by a library that did a
+    /// blocking handshake, with `handle`'s reactor.
+    ///
+    /// Fails with `InvalidInput` unless `file` is a named pipe opened with
+    /// `FILE_FLAG_OVERLAPPED`, and with `InvalidParameter` OS errors if it is bound to a
+    /// completion port already. The connection closes the pipe when dropped.
+    #[cfg(windows)]
+    pub fn from_blocking(file: ::std::fs::File, handle: &Handle) -> io::Result<IpcConnection> {
+        use std::os::windows::io::{AsRawHandle, IntoRawHandle};
+
+        if win_pipe::pipe_flags(file.as_raw_handle()).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the handle is not a named pipe"));
+        }
+        if !win_pipe::is_overlapped(file.as_raw_handle())? {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "the pipe was not opened with FILE_FLAG_OVERLAPPED"));
+        }
+        unsafe { IpcConnection::from_raw_handle(file.into_raw_handle(), handle) }
+    }
+
+    /// Adopt a socket created elsewhere, e.g. by FFI code, registering it with `handle`'s reactor.
+    ///
+    /// The connection takes ownership of `fd` and closes it when dropped. The socket is
+    /// made non-blocking.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open, connected Unix domain stream socket that nothing else owns.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(fd: ::std::os::unix::io::RawFd, handle: &Handle) -> io::Result<IpcConnection> {
+        use std::os::unix::{io::FromRawFd, net};
+
+        let stream = net::UnixStream::from_raw_fd(fd);
+        Ok(IpcConnection::from_inner(tokio_uds::UnixStream::from_std(stream, handle)?))
+    }
+
+    /// Adopt a pipe opened elsewhere, e.g. by FFI code, associating it with the
+    /// completion port of `handle`'s reactor.
+    ///
+    /// The connection takes ownership of `raw` and closes it when dropped.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be an open handle to a connected named pipe (either end) that nothing
+    /// else owns. It must have been opened with `FILE_FLAG_OVERLAPPED` and not be
+    /// associated with a completion port yet.
+    #[cfg(windows)]
+    pub unsafe fn from_raw_handle(raw: ::std::os::windows::io::RawHandle, handle: &Handle) -> io::Result<IpcConnection> {
+        use std::os::windows::io::FromRawHandle;
+
+        let mio_pipe = mio_named_pipes::NamedPipe::from_raw_handle(raw);
+        Ok(IpcConnection::from_inner(NamedPipe::from_pipe(mio_pipe, handle)?))
+    }
+
+    /// Read at most `max` bytes per poll, yielding to other tasks after each full chunk.
+    ///
+    /// Keeps a large transfer from starving the other tasks of a busy runtime, at the
+    /// cost of throughput: each chunk takes a trip through the scheduler. Smaller chunks
+    /// mean lower latency for the other tasks and more overhead for this one.
+    /// Panics if `max` is zero.
+    pub fn max_read_chunk(mut self, max: usize) -> Self {
+        assert!(max > 0, "max read chunk must be positive");
+        self.max_read_chunk = Some(max);
+        self
+    }
+
+    /// Supplementary groups of the peer, for authorizing by group membership beyond
+    /// the primary group of `peer_info`.
+    ///
+    /// Linux 4.13 and later record them when the peer connects. Older kernels only
+    /// offer the groups the peer's process has now, read from `/proc`: they may have
+    /// changed since, and if the peer exited its pid may already belong to another
+    /// process, whose groups would be returned instead.
+    #[cfg(target_os = "linux")]
+    pub fn peer_groups(&self) -> io::Result<Vec<u32>> {
+        peer::groups(&self.inner, self.peer_info.and_then(|info| info.pid))
+    }
+
+    /// Fail reads with `TimedOut` once no data arrived for `timeout`.
+    ///
+    /// The time counts from the first read finding no data, and restarts with every
+    /// read returning some, so a peer that sends nothing for too long gets noticed by
+    /// the next read. Needs a Tokio timer, like the one of the default runtime.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self.idle_timer = None;
+        self
+    }
+
+    /// Identity of the peer process, captured when the connection was accepted or made.
+    ///
+    /// Unlike querying the credentials later, this still works once the peer exited or
+    /// its pid was reused. `None` if the platform couldn't tell.
+    pub fn peer_info(&self) -> Option<PeerInfo> {
+        self.peer_info
+    }
+
+    /// How long making the connection took, `None` for accepted or adopted connections.
+    ///
+    /// Covers the whole `connect` call, waiting for a free pipe instance and retries of
+    /// `connect_with_retry` included, to tell slow handshakes apart from slow requests.
+    pub fn connect_duration(&self) -> Option<Duration> {
+        self.connect_duration
+    }
+
+    /// Split the connection into owned halves that can be moved into separate tasks.
+    ///
+    /// The halves take turns using the connection and can be put back together with
+    /// `reunite`.
+    pub fn into_split(self) -> (OwnedReadHalf, OwnedWriteHalf) {
+        split::into_split(self)
+    }
+
+    /// Put the halves returned by `into_split` back together, failing with both halves
+    /// if they are from different connections.
+    pub fn reunite(read: OwnedReadHalf, write: OwnedWriteHalf) -> Result<IpcConnection, ReuniteError> {
+        read.reunite(write)
+    }
+
+    /// Buffer reads in `capacity` bytes, for delimiter-based parsing with
+    /// `tokio::io::read_until` or `tokio::io::lines`.
+    ///
+    /// Data beyond the delimiter stays in the buffer: switching to another framing
+    /// later has to start with `BufReader::buffer` rather than the bare connection,
+    /// which `into_inner` returns without it. Write through `get_mut`.
+    pub fn into_buf_reader(self, capacity: usize) -> io::BufReader<IpcConnection> {
+        io::BufReader::with_capacity(capacity, self)
+    }
+
+    /// Split the connection like `into_split`, framing the halves with length-delimited
+    /// codecs so that one task can decode messages while another encodes them.
+    ///
+    /// Use `into_inner` on both halves and `OwnedReadHalf::reunite` to get the
+    /// connection back; buffered data is lost.
+    pub fn split_framed(self) -> (FramedRead<OwnedReadHalf, LengthDelimitedCodec>, FramedWrite<OwnedWriteHalf, LengthDelimitedCodec>) {
+        let (read, write) = split::into_split(self);
+        (FramedRead::new(read, LengthDelimitedCodec::new()), FramedWrite::new(write, LengthDelimitedCodec::new()))
+    }
+
+    /// Whether the peer still seems connected, checked without consuming any data.
+    ///
+    /// Best effort and racy: the peer may go away right after the check, so writes
+    /// still have to handle errors. On Unix a peer that shut down its writing half
+    /// counts as gone, as does one that reset the connection. On Windows the pipe is
+    /// peeked, failing once the peer closed its end.
+    #[cfg(unix)]
+    pub fn is_connected(&self) -> bool {
+        let mut byte = 0u8;
+        let result = unsafe {
+            libc::recv(self.inner.as_raw_fd(), &mut byte as *mut u8 as *mut _, 1, libc::MSG_PEEK | libc::MSG_DONTWAIT)
+        };
+        match result {
+            0 => false,
+            -1 => io::Error::last_os_error().kind() == io::ErrorKind::WouldBlock,
+            _ => true,
+        }
+    }
+
+    /// Whether the peer still seems connected, see the Unix version.
+    #[cfg(windows)]
+    pub fn is_connected(&self) -> bool {
+        use std::os::windows::io::AsRawHandle;
+        use std::ptr;
+        use winapi::um::namedpipeapi::PeekNamedPipe;
+
+        unsafe {
+            PeekNamedPipe(self.inner.as_raw_handle() as _, ptr::null_mut(), 0,
+                ptr::null_mut(), ptr::null_mut(), ptr::null_mut()) != 0
+        }
+    }
+
+    /// Future resolving to the connection once the peer disconnected, without reading.
+    ///
+    /// Lets servers clean up after clients that went away while not reading from them.
+    /// On Unix a peer shutting down its writing half already counts as closed; on Windows
+    /// the pipe is checked whenever data arrives and every 100 milliseconds.
+    pub fn closed(self) -> Closed {
+        Closed::new(self)
+    }
+
+    /// Read and discard incoming bytes until the peer closes its end or `limit` bytes were read.
+    ///
+    /// Closing with unread data may reset the connection on some platforms, so protocols
+    /// can drain leftovers before a clean close. Resolves to the connection and the number
+    /// of discarded bytes.
+    pub fn drain(self, limit: u64) -> Drain {
+        Drain::new(self, limit)
+    }
+
+    /// Read into `buf` until it is full, the peer closes its end or `deadline` passes,
+    /// for latency-bounded batch reads.
+    ///
+    /// Resolves to the connection, the buffer and the number of bytes read, which may be
+    /// anything from zero to the buffer size.
+    pub fn read_until_deadline<B: AsMut<[u8]>>(self, buf: B, deadline: Instant) -> ReadUntilDeadline<B> {
+        ReadUntilDeadline::new(self, buf, deadline)
+    }
+
+    /// Close both directions of the connection, independently of when it is dropped.
+    ///
+    /// On Unix this is `shutdown(SHUT_RDWR)`: further reads return end of file and
+    /// writes fail. On Windows the server end waits for the in-flight write and then
+    /// disconnects the pipe; the client end of a pipe has no equivalent and is only
+    /// closed when dropped.
+    pub fn shutdown_both(self) -> ShutdownBoth {
+        ShutdownBoth::new(self)
+    }
+
+    /// Wrap the connection to fail once more than `max_read` bytes are received
+    /// or `max_write` bytes are sent in total.
+    pub fn with_quota(self, max_read: u64, max_write: u64) -> Quota<IpcConnection> {
+        Quota::new(self, max_read, max_write)
+    }
+
+    /// Wrap the connection to read at most `bytes_per_sec` bytes a second, throttling a
+    /// greedy peer. Panics if `bytes_per_sec` is zero.
+    pub fn read_rate_limited(self, bytes_per_sec: u32) -> ReadRateLimited<IpcConnection> {
+        ReadRateLimited::new(self, bytes_per_sec)
+    }
+
+    /// Wrap the connection to read `prefix` first, e.g. the bytes a router read to pick
+    /// the handler, then the data still to come.
+    pub fn prepend(self, prefix: Vec<u8>) -> Prepended<IpcConnection> {
+        Prepended::new(self, prefix)
+    }
+
+    /// Wrap the connection to compress everything written and decompress everything
+    /// read, the peer has to wrap its end as well.
+    #[cfg(feature = "compress")]
+    pub fn compressed(self, compression: Compression) -> Compressed<IpcConnection> {
+        Compressed::new(self, compression)
+    }
+
+    /// Encrypt the connection with the Noise protocol, after a handshake set up by
+    /// `state`, e.g. `snow::Builder::new(params).local_private_key(key)?.build_initiator()?`.
+    ///
+    /// Both ends have to use it, one as initiator and the other as responder. Which
+    /// peers are accepted depends on the handshake pattern and keys; check
+    /// `SecureTransport::remote_static` for patterns authenticating the peer.
+    #[cfg(feature = "noise")]
+    pub fn secure(self, state: snow::HandshakeState) -> Handshake<IpcConnection> {
+        Handshake::new(self, state)
+    }
+
+    /// Write `header` and `payload` as one frame, prefixed with their combined length
+    /// like `LengthDelimitedCodec` does, without concatenating them first.
+    ///
+    /// Prefix, header and payload go out with a single gather write on Unix when the
+    /// socket has room. Pipes have no gather writes, so on Windows the parts are copied
+    /// into one buffer written as usual.
+    pub fn write_frame_vectored<H: AsRef<[u8]>, P: AsRef<[u8]>>(self, header: H, payload: P) -> WriteFrame<H, P> {
+        WriteFrame::new(self, header, payload)
+    }
+
+    /// Send `len` bytes of `file` starting at `offset` over the connection.
+    ///
+    /// Uses `sendfile` on Linux to avoid copying through userspace and falls back to
+    /// a copy loop elsewhere (Windows `TransmitFile` only supports sockets, not named pipes).
+    /// Resolves to the connection, the file and the number of bytes sent, which is
+    /// less than `len` if the end of the file is reached first.
+    pub fn send_file(self, file: File, offset: u64, len: usize) -> SendFile {
+        SendFile::new(self, file, offset, len)
+    }
+
+    /// Set the kernel receive timeout (`SO_RCVTIMEO`) of the socket, `None` disabling it.
+    ///
+    /// Only affects blocking reads on the raw file descriptor, e.g. from helper threads;
+    /// the non-blocking reads of this connection are unaffected.
+    #[cfg(unix)]
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        sockopt::set_timeout(self.inner.as_raw_fd(), libc::SO_RCVTIMEO, timeout)
+    }
+
+    /// Set the kernel send timeout (`SO_SNDTIMEO`) of the socket, `None` disabling it.
+    ///
+    /// Like `set_read_timeout`, only blocking writes on the raw file descriptor are affected.
+    #[cfg(unix)]
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        sockopt::set_timeout(self.inner.as_raw_fd(), libc::SO_SNDTIMEO, timeout)
+    }
+
+    /// Whether the underlying handle was opened for overlapped I/O.
+    ///
+    /// Both ends are always opened with `FILE_FLAG_OVERLAPPED`, which the reactor depends
+    /// on; this allows checking it before handing the raw handle to custom IOCP code.
+    #[cfg(windows)]
+    pub fn is_overlapped(&self) -> io::Result<bool> {
+        use std::os::windows::io::AsRawHandle;
+
+        win_pipe::is_overlapped(self.inner.as_raw_handle())
+    }
+
+    /// Properties of the underlying pipe, useful to check it was created as intended.
+    #[cfg(windows)]
+    pub fn pipe_info(&self) -> io::Result<PipeInfo> {
+        use std::os::windows::io::AsRawHandle;
+
+        PipeInfo::of(self.inner.as_raw_handle())
+    }
+
+    /// Terminal services session of the connected client, for authorizing clients by
+    /// session on multi-user systems.
+    ///
+    /// Only available on the server end of a pipe.
+    #[cfg(windows)]
+    pub fn client_session_id(&self) -> io::Result<u32> {
+        use std::os::windows::io::AsRawHandle;
+
+        win_pipe::client_session_id(self.inner.as_raw_handle())
+    }
+
+    /// Terminal services session of the connected client, see the Windows version.
+    ///
+    /// Always fails on Unix, which has no such sessions.
+    #[cfg(unix)]
+    pub fn client_session_id(&self) -> io::Result<u32> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "client sessions are only available on Windows"))
+    }
+
+    /// Set the kernel receive buffer size (`SO_RCVBUF`) of the socket.
+    ///
+    /// Larger buffers help high-throughput transfers. Linux doubles the value to
+    /// account for its bookkeeping and caps it at `net.core.rmem_max`, so read it back
+    /// with `recv_buffer_size` for the effective size. Unix only: pipe buffers are sized
+    /// when the server creates the pipe and can't be changed afterwards.
+    #[cfg(unix)]
+    pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+        sockopt::set_buffer_size(self.inner.as_raw_fd(), libc::SO_RCVBUF, size)
+    }
+
+    /// Set the kernel send buffer size (`SO_SNDBUF`) of the socket, see `set_recv_buffer_size`.
+    #[cfg(unix)]
+    pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+        sockopt::set_buffer_size(self.inner.as_raw_fd(), libc::SO_SNDBUF, size)
+    }
+
+    /// Effective kernel receive buffer size of the socket.
+    #[cfg(unix)]
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        sockopt::buffer_size(self.inner.as_raw_fd(), libc::SO_RCVBUF)
+    }
+
+    /// Effective kernel send buffer size of the socket.
+    #[cfg(unix)]
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        sockopt::buffer_size(self.inner.as_raw_fd(), libc::SO_SNDBUF)
+    }
+
+    /// Kernel receive timeout of the socket, see `set_read_timeout`.
+    #[cfg(unix)]
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        sockopt::timeout(self.inner.as_raw_fd(), libc::SO_RCVTIMEO)
+    }
+
+    /// Kernel send timeout of the socket, see `set_write_timeout`.
+    #[cfg(unix)]
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        sockopt::timeout(self.inner.as_raw_fd(), libc::SO_SNDTIMEO)
+    }
+}
+
+/// The raw pipe handle, opened with `FILE_FLAG_OVERLAPPED` (see `IpcConnection::is_overlapped`).
+///
+/// The handle is already associated with the reactor's completion port, which can't be
+/// undone, so completions of overlapped operations issued on it are posted there.
+#[cfg(windows)]
+impl ::std::os::windows::io::AsRawHandle for IpcConnection {
+    fn as_raw_handle(&self) -> ::std::os::windows::io::RawHandle {
+        self.inner.as_raw_handle()
+    }
+}
+
+impl IpcConnection {
+    fn read_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = match self.max_read_chunk {
+            Some(max) => max,
+            None => return self.inner.read(buf),
+        };
+        if self.read_yield {
+            // Let other tasks run before reading the next chunk.
+            self.read_yield = false;
+            futures::task::current().notify();
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let len = buf.len().min(max);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.read_yield = read == max;
+        Ok(read)
+    }
+
+    /// Whether bytes read by `poll_peek` are waiting to be returned by reads.
+    fn has_peeked(&self) -> bool {
+        #[cfg(windows)]
+        {
+            !self.peeked.is_empty()
+        }
+        #[cfg(not(windows))]
+        {
+            false
+        }
+    }
+
+    /// Fail a read that would block once the idle timeout elapsed.
+    fn check_idle(&mut self, result: io::Result<usize>) -> io::Result<usize> {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return result,
+        };
+        match result {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+            result => {
+                self.idle_timer = None;
+                return result;
+            },
+        }
+
+        let timer = self.idle_timer.get_or_insert_with(|| Delay::new(::std::time::Instant::now() + timeout));
+        match ::futures::Future::poll(timer).map_err(io::Error::other)? {
+            Async::Ready(()) => {
+                self.idle_timer = None;
+                Err(io::Error::new(io::ErrorKind::TimedOut, format!("no data received for {:?}", timeout)))
+            },
+            Async::NotReady => result,
+        }
+    }
+}
+
+impl Read for IpcConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(windows)]
+        {
+            if !self.peeked.is_empty() {
+                let n = buf.len().min(self.peeked.len());
+                buf[..n].copy_from_slice(&self.peeked[..n]);
+                self.peeked.drain(..n);
+                return Ok(n);
+            }
+        }
+        let result = self.read_chunk(buf);
+        self.check_idle(result)
+    }
+}
+
+impl Write for IpcConnection {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        sockopt::write_nosignal(&mut self.inner, buf)
+    }
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+    #[cfg(windows)]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).map_err(win_pipe::map_write_error)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl AsyncRead for IpcConnection {
+    unsafe fn prepare_uninitialized_buffer(&self, b: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(b)
+    }
+
+    fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        if self.max_read_chunk.is_none() && self.idle_timeout.is_none() && !self.has_peeked() {
+            return AsyncRead::read_buf(&mut self.inner, buf);
+        }
+        if !buf.has_remaining_mut() {
+            return Ok(Async::Ready(0));
+        }
+        let read = unsafe {
+            let bytes = buf.bytes_mut();
+            self.inner.prepare_uninitialized_buffer(bytes);
+            match self.read(bytes) {
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(e) => return Err(e),
+            }
+        };
+        unsafe { buf.advance_mut(read) };
+        Ok(Async::Ready(read))
+    }
+}
+
+impl AsyncWrite for IpcConnection {
+    // Sockets are closed for writing, so that the peer reads the end of file, e.g. when
+    // proxying with `copy_bidirectional`; `tokio-uds` only flushes. Pipes can't be
+    // half-closed and keep both directions open until dropped.
+    #[cfg(not(windows))]
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.inner.shutdown(::std::net::Shutdown::Write) {
+            // The peer is gone already.
+            Err(ref e) if e.kind() == io::ErrorKind::NotConnected => Ok(Async::Ready(())),
+            result => result.map(Async::Ready),
+        }
+    }
+
+    #[cfg(windows)]
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        AsyncWrite::shutdown(&mut self.inner)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        if !buf.has_remaining() {
+            return Ok(Async::Ready(0));
+        }
+        match self.write(buf.bytes()) {
+            Ok(written) => {
+                buf.advance(written);
+                Ok(Async::Ready(written))
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(Async::NotReady),
+            Err(e) => Err(e),
+        }
+    }
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        self.inner.write_buf(buf)
+    }
+    #[cfg(windows)]
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        self.inner.write_buf(buf).map_err(win_pipe::map_write_error)
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    extern crate rand;
+
+    use tokio::{self, io::{self, AsyncRead}, runtime::TaskExecutor, reactor::Handle};
+    use futures::{sync::oneshot, Stream, Future};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::Endpoint;
+    use super::IpcConnection;
+    use super::RetryConfig;
+    use super::SecurityAttributes;
+
+    #[cfg(not(windows))]
+    fn random_pipe_path() -> String {
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        format!(r"/tmp/susy-tokio-ipc-test-pipe-{}", num)
+    }
+
+    #[cfg(windows)]
+    fn random_pipe_path() -> String {
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        format!(r"\\.\pipe\my-pipe-{}", num)
+    }
+
+    /// Connected (client, server) pair of connections on the runtime's reactor.
+    pub fn connected_pair(runtime: &mut tokio::runtime::Runtime) -> (IpcConnection, IpcConnection) {
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+        let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future())
+            .map_err(|(err, _)| err)
+            .expect("failed to accept connection");
+        (client, server.expect("no connection received").0)
+    }
+
+    fn run_server(path: &str, exec: TaskExecutor, handle: Handle) {
+        let path = path.to_owned();
+        let (ok_signal, ok_rx) = oneshot::channel();
+        thread::spawn(move || {
+            let endpoint = Endpoint::new(path);
+            let connections = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
+            let srv = connections.for_each(|(stream, _)| {
                     let (reader, writer) = stream.split();
                     let buf = [0u8; 5];
                     io::read_exact(reader,buf).and_then(move |(_reader, buf)| {
@@ -358,49 +2124,1211 @@ mod tests {
             ok_signal.send(()).expect("failed to send ok");
             println!("Server running.");
         });
-        ok_rx.wait().expect("failed to receive handle")
+        ok_rx.wait().expect("failed to receive handle")
+    }
+
+    // NOTE: Intermittently fails or stalls on windows.
+    #[test]
+    fn smoke_test() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let exec = runtime.executor();
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+
+        run_server(&path, exec, handle.clone());
+
+        println!("Connecting to client 0...");
+        let client_0 = IpcConnection::connect(&path, &handle).expect("failed to open client_0");
+        println!("Connecting to client 1...");
+        let client_1 = IpcConnection::connect(&path, &handle).expect("failed to open client_1");
+        let msg = b"hello";
+
+        let rx_buf = vec![0u8; msg.len()];
+        let client_0_fut = io::write_all(client_0, msg)
+            .map_err(|err| panic!("Client 0 write error: {:?}", err))
+            .and_then(move |(client, _)| {
+                io::read_exact(client, rx_buf).map(|(_, buf)| buf)
+                    .map_err(|err| panic!("Client 0 read error: {:?}", err))
+            });
+
+        let rx_buf2 = vec![0u8; msg.len()];
+        let client_1_fut = io::write_all(client_1, msg)
+            .map_err(|err| panic!("Client 1 write error: {:?}", err))
+            .and_then(move |(client, _)| {
+                io::read_exact(client, rx_buf2).map(|(_, buf)| buf)
+                    .map_err(|err| panic!("Client 1 read error: {:?}", err))
+            });
+
+        let fut = client_0_fut.join(client_1_fut).and_then(move |(rx_msg, other_rx_msg)| {
+            assert_eq!(rx_msg,  msg);
+            assert_eq!(other_rx_msg,  msg);
+            Ok(())
+        }).map_err(|err| panic!("Smoke test error: {:?}", err));
+
+        runtime.block_on(fut).expect("Runtime error")
+    }
+
+    #[test]
+    fn test_connect_with_retry() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let exec = runtime.executor();
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let config = RetryConfig {
+            max_attempts: 20,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+
+        assert!(IpcConnection::connect_with_retry(&path, &handle, &RetryConfig { max_attempts: 2, ..config.clone() }).is_err());
+
+        let server_path = path.clone();
+        let server_handle = handle.clone();
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            run_server(&server_path, exec, server_handle);
+        });
+
+        let connection = IpcConnection::connect_with_retry(&path, &handle, &config).expect("failed to connect with retry");
+        // The retries waiting for the server are part of the connect.
+        assert!(connection.connect_duration().expect("no connect duration") >= Duration::from_millis(50));
+        server.join().expect("server thread panicked");
+    }
+
+    #[test]
+    fn test_connect_any() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let dead = random_pipe_path();
+        let live = random_pipe_path();
+        let incoming = Endpoint::new(live.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+
+        let client = IpcConnection::connect_any(&[&dead, &live], &handle).expect("failed to connect to the live endpoint");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+
+        let other_dead = random_pipe_path();
+        let err = match IpcConnection::connect_any(&[&dead, &other_dead], &handle) {
+            Ok(_) => panic!("connected without a live endpoint"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&*dead) && err.to_string().contains(&*other_dead), "unclear error: {}", err);
+    }
+
+    #[test]
+    fn test_connect_duration() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+
+        let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let duration = client.connect_duration().expect("no connect duration");
+        assert!(duration > Duration::from_secs(0) && duration < Duration::from_secs(5), "implausible connect duration {:?}", duration);
+
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert!(server.expect("no connection received").0.connect_duration().is_none());
+    }
+
+    #[test]
+    fn test_rebind_after_drop() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let endpoint = Endpoint::new(path.clone());
+
+        for _ in 0..2 {
+            let incoming = endpoint.rebind(&handle).expect("failed to bind");
+            // Listening twice at once still fails.
+            assert!(endpoint.rebind(&handle).is_err());
+
+            let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+            let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+            assert!(server.is_some());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rebind_replaces_stale_socket() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let path = random_pipe_path();
+
+        let incoming = Endpoint::new(path.clone()).incoming(runtime.reactor()).expect("failed to bind");
+        // A live listener is left alone.
+        match Endpoint::new(path.clone()).rebind(runtime.reactor()) {
+            Ok(_) => panic!("bound over a live listener"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::AddrInUse),
+        }
+
+        // `incoming` leaves the stale file to the caller, `rebind` replaces it.
+        drop(incoming);
+        assert!(::std::path::Path::new(&path).exists());
+        match Endpoint::new(path.clone()).incoming(runtime.reactor()) {
+            Ok(_) => panic!("incoming replaced a stale socket"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::AddrInUse),
+        }
+        let _incoming = Endpoint::new(path.clone()).rebind(runtime.reactor()).expect("failed to bind over a stale socket");
+    }
+
+    // Keeps the files in `dir` from being removed until dropped: by its permissions,
+    // or with the immutable attribute for root, which they don't restrict.
+    #[cfg(target_os = "linux")]
+    struct Unremovable {
+        dir: ::std::path::PathBuf,
+        // The directory and its attributes before they were made immutable.
+        immutable: Option<(::std::fs::File, ::libc::c_long)>,
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Unremovable {
+        const FS_IMMUTABLE_FL: ::libc::c_long = 0x10;
+
+        fn new(dir: &::std::path::Path) -> Option<Unremovable> {
+            use std::os::unix::{fs::PermissionsExt, io::AsRawFd};
+
+            if unsafe { ::libc::geteuid() } != 0 {
+                ::std::fs::set_permissions(dir, ::std::fs::Permissions::from_mode(0o555)).expect("failed to make the directory read-only");
+                return Some(Unremovable { dir: dir.to_owned(), immutable: None });
+            }
+            let file = ::std::fs::File::open(dir).expect("failed to open the directory");
+            let mut flags: ::libc::c_long = 0;
+            if unsafe { ::libc::ioctl(file.as_raw_fd(), ::libc::FS_IOC_GETFLAGS, &mut flags) } != 0 {
+                return None;
+            }
+            if unsafe { ::libc::ioctl(file.as_raw_fd(), ::libc::FS_IOC_SETFLAGS, &(flags | Self::FS_IMMUTABLE_FL)) } != 0 {
+                return None;
+            }
+            Some(Unremovable { dir: dir.to_owned(), immutable: Some((file, flags)) })
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Drop for Unremovable {
+        fn drop(&mut self) {
+            use std::os::unix::{fs::PermissionsExt, io::AsRawFd};
+
+            match self.immutable {
+                Some((ref file, flags)) => {
+                    unsafe { ::libc::ioctl(file.as_raw_fd(), ::libc::FS_IOC_SETFLAGS, &flags) };
+                },
+                None => {
+                    let _ = ::std::fs::set_permissions(&self.dir, ::std::fs::Permissions::from_mode(0o755));
+                },
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_unremovable_socket_file() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        let dir = ::std::env::temp_dir().join(format!("ipc-unremovable-{}", num));
+        ::std::fs::create_dir(&dir).expect("failed to create the socket directory");
+        let path = dir.join("socket").to_string_lossy().into_owned();
+
+        let endpoint = Endpoint::new(path.clone());
+        let incoming = endpoint.rebind(runtime.reactor()).expect("failed to bind");
+        let unremovable = match Unremovable::new(&dir) {
+            Some(unremovable) => unremovable,
+            None => {
+                println!("skipping: the file system can't make {} unremovable", dir.display());
+                drop(incoming);
+                ::std::fs::remove_dir_all(&dir).expect("failed to remove the socket directory");
+                return;
+            },
+        };
+
+        // Neither dropping the stream nor failing to replace the stale file panics, and
+        // binding reports the file it couldn't replace.
+        drop(incoming);
+        match endpoint.rebind(runtime.reactor()) {
+            Ok(_) => panic!("replaced an unremovable socket file"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::AddrInUse),
+        }
+        assert!(::std::path::Path::new(&path).exists());
+        drop(unremovable);
+
+        // Kept even where it could be removed.
+        let keeping = Endpoint::builder().path(path.clone()).keep_socket_file(true).build().unwrap();
+        match keeping.rebind(runtime.reactor()) {
+            Ok(_) => panic!("replaced a socket file to keep"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::AddrInUse),
+        }
+        assert!(::std::path::Path::new(&path).exists());
+        let _incoming = endpoint.rebind(runtime.reactor()).expect("failed to replace the stale socket file");
+        ::std::fs::remove_dir_all(&dir).expect("failed to remove the socket directory");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_dir_removed() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        let dir = ::std::env::temp_dir().join(format!("ipc-dir-{}", num));
+        ::std::fs::create_dir(&dir).expect("failed to create the socket directory");
+        let path = dir.join("socket").to_string_lossy().into_owned();
+
+        let mut endpoint = Endpoint::new(path.clone());
+        drop(endpoint.rebind(runtime.reactor()).expect("failed to bind"));
+        ::std::fs::remove_dir_all(&dir).expect("failed to remove the socket directory");
+
+        let err = match endpoint.rebind(runtime.reactor()) {
+            Ok(_) => panic!("bound into a removed directory"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&*dir.to_string_lossy()), "unclear error: {}", err);
+
+        endpoint.set_create_parent_dirs(true);
+        let _incoming = endpoint.rebind(runtime.reactor()).expect("failed to bind after recreating the directory");
+        assert!(dir.is_dir());
+        ::std::fs::remove_dir_all(&dir).expect("failed to remove the socket directory");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_owner() {
+        use std::os::unix::fs::MetadataExt;
+
+        // Changing the owner to another user needs root.
+        if unsafe { ::libc::geteuid() } != 0 {
+            return;
+        }
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let path = random_pipe_path();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_mode(0o660);
+        endpoint.set_owner(4321, 8765);
+        let _incoming = endpoint.incoming(runtime.reactor()).expect("failed to bind");
+
+        let metadata = ::std::fs::metadata(&path).expect("socket file should exist");
+        assert_eq!((metadata.uid(), metadata.gid()), (4321, 8765));
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_incoming_with_addr() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let mut incoming = Endpoint::new(path.clone()).incoming_with_addr(&handle).expect("failed to open up a new pipe/socket");
+        assert_eq!(incoming.local_addr(), path);
+
+        // One client at a time, a pipe server only has one instance awaiting connections.
+        for _ in 0..2 {
+            let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+            let (accepted, rest) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+            let (_, addr) = accepted.expect("no connection received");
+            assert_eq!(addr, path);
+            incoming = rest;
+        }
+    }
+
+    #[test]
+    fn test_incoming_local_addr() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+        assert!(incoming.is_bound());
+        assert_eq!(incoming.local_addr().expect("listener should be bound"), path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_new_auto_abstract() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        let name = format!("susy-tokio-ipc-auto-{}", num);
+
+        let endpoint = Endpoint::new_auto(&name);
+        assert_eq!(endpoint.path(), format!("@{}", name));
+        let incoming = endpoint.incoming(runtime.reactor()).expect("failed to bind abstract socket");
+        assert_eq!(incoming.local_addr().unwrap(), format!("@{}", name));
+        assert!(!::std::path::Path::new(&format!("@{}", name)).exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_connect_abstract() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        let name = format!("susy-tokio-ipc-abstract-{}", num);
+
+        let incoming = Endpoint::new(format!("@{}", name)).incoming(&handle).expect("failed to bind abstract socket");
+
+        // Both connections wait in the backlog until accepted.
+        let client = IpcConnection::connect_abstract(&name, &handle).expect("failed to connect by name");
+        let _other = IpcConnection::connect(format!("@{}", name), &handle).expect("failed to connect by path");
+        let servers = runtime.block_on(incoming.take(2).collect()).expect("failed to accept");
+        assert_eq!(servers.len(), 2);
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (server, _) = servers.into_iter().next().expect("no connection received");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_connect_abstract_falls_back_to_path() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let num: u64 = self::rand::Rng::gen(&mut rand::thread_rng());
+        let name = format!("susy-tokio-ipc-unbound-{}", num);
+        let path = ::dummy_endpoint();
+
+        // Only the filesystem socket is bound, as in a sandbox blocking abstract sockets.
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+        let client = IpcConnection::connect_abstract_or_path(Some(&name), &path, &handle).expect("fallback should connect");
+        let (server, _incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+
+        let unbound = ::dummy_endpoint();
+        let err = match IpcConnection::connect_abstract_or_path(Some(&name), &unbound, &handle) {
+            Ok(_) => panic!("neither socket is bound"),
+            Err(err) => err,
+        };
+        assert!(err.to_string().contains(&format!("@{}", name)), "{}", err);
+        assert!(err.to_string().contains(&unbound), "{}", err);
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    #[test]
+    fn test_new_auto_filesystem() {
+        let endpoint = Endpoint::new_auto("susy-tokio-ipc-auto");
+        assert!(::std::path::Path::new(endpoint.path()).starts_with(::std::env::temp_dir()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_new_auto_pipe() {
+        let endpoint = Endpoint::new_auto("susy-tokio-ipc-auto");
+        assert_eq!(endpoint.path(), r"\\.\pipe\susy-tokio-ipc-auto");
+    }
+
+    #[test]
+    fn test_endpoint_builder() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        assert!(Endpoint::builder().build().is_err());
+
+        let path = random_pipe_path();
+        let builder = Endpoint::builder()
+            .path(path.clone())
+            .security_attributes(SecurityAttributes::allow_everyone_connect().expect("failed to create attributes"));
+        #[cfg(windows)]
+        let builder = builder.pipe_mode(super::PipeMode::Byte).max_instances(4);
+        let endpoint = builder.build().expect("failed to build endpoint");
+        assert_eq!(endpoint.path(), path);
+
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
+        let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (conn, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert!(conn.is_some());
+
+        let path = random_pipe_path();
+        let endpoint = Endpoint::with_security_attributes(path.clone(), SecurityAttributes::empty());
+        assert_eq!(endpoint.path(), path);
+    }
+
+    #[test]
+    fn test_buf_reader_read_until() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        runtime.spawn(io::write_all(client, b"first line\nsecond line\n")
+            .map(|_| ())
+            .map_err(|err| panic!("failed to write: {:?}", err)));
+
+        let reader = server.into_buf_reader(8);
+        let (reader, line) = runtime.block_on(io::read_until(reader, b'\n', Vec::new())).expect("failed to read");
+        assert_eq!(line, b"first line\n");
+        let (reader, line) = runtime.block_on(io::read_until(reader, b'\n', Vec::new())).expect("failed to read");
+        assert_eq!(line, b"second line\n");
+        assert!(reader.buffer().is_empty());
+    }
+
+    #[test]
+    fn test_dedicated_current_thread_reactor() {
+        use std::thread;
+        use tokio::executor::current_thread::CurrentThread;
+        use tokio::reactor::Reactor;
+
+        let path = random_pipe_path();
+        let io_thread = thread::spawn(move || {
+            let reactor = Reactor::new().expect("failed to create reactor");
+            let handle = reactor.handle();
+            let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+            let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+
+            let server = incoming.into_future()
+                .map_err(|(err, _)| err)
+                .and_then(|(connection, _)| io::read_exact(connection.expect("no connection received").0, [0u8; 4]));
+            let mut executor = CurrentThread::new_with_park(reactor);
+            let ((_, buf), _) = executor.block_on(server.join(io::write_all(client, b"ping")))
+                .expect("failed to exchange data");
+            buf
+        });
+        assert_eq!(&io_thread.join().expect("io thread panicked"), b"ping");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_raw_fd_socketpair() {
+        use std::io::{Read, Write};
+        use std::os::unix::{io::IntoRawFd, net};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (mut peer, socket) = net::UnixStream::pair().expect("failed to create socketpair");
+        let connection = unsafe { IpcConnection::from_raw_fd(socket.into_raw_fd(), runtime.reactor()) }
+            .expect("failed to adopt socket");
+
+        peer.write_all(b"ping").expect("failed to write");
+        let (connection, buf) = runtime.block_on(io::read_exact(connection, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+        runtime.block_on(io::write_all(connection, b"pong")).expect("failed to write");
+        let mut buf = [0u8; 4];
+        peer.read_exact(&mut buf).expect("failed to read");
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_into_blocking_and_back() {
+        use std::io::{Read, Write};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let mut blocking = server.into_blocking().expect("failed to make the connection blocking");
+
+        // The read waits for data written later on instead of failing with `WouldBlock`.
+        let reader = thread::spawn(move || {
+            let mut buf = [0u8; 4];
+            blocking.read_exact(&mut buf).expect("failed to read");
+            (blocking, buf)
+        });
+        thread::sleep(Duration::from_millis(50));
+        let client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (mut blocking, buf) = reader.join().expect("reader panicked");
+        assert_eq!(&buf, b"ping");
+        blocking.write_all(b"pong").expect("failed to write");
+
+        let (client, buf) = runtime.block_on(io::read_exact(client, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"pong");
+
+        // Back on the reactor, reads are asynchronous again.
+        let server = IpcConnection::from_blocking(blocking, runtime.reactor()).expect("failed to register the stream");
+        let _client = runtime.block_on(io::write_all(client, b"done")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"done");
+    }
+
+    #[test]
+    fn test_is_connected() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        assert!(server.is_connected());
+
+        // Pending data doesn't count as a disconnect, nor is it consumed.
+        let client = runtime.block_on(io::write_all(client, b"x")).expect("failed to write").0;
+        assert!(server.is_connected());
+        drop(client);
+        let (server, buf) = runtime.block_on(io::read_exact(server, [0u8; 1])).expect("failed to read");
+        assert_eq!(&buf, b"x");
+        assert!(!server.is_connected());
+    }
+
+    #[test]
+    fn test_poll_peek_magic_prefix() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        // A first message drained by a plain read leaves the connection marked readable.
+        let client = runtime.block_on(io::write_all(client, b"hello")).expect("failed to write").0;
+        let (server, _) = runtime.block_on(io::read_exact(server, [0u8; 5])).expect("failed to read");
+
+        // The peek waits for the prefix written later on.
+        let writer = thread::spawn(move || {
+            use std::io::Write;
+
+            thread::sleep(Duration::from_millis(100));
+            let mut client = client;
+            client.write_all(b"MAGICpayload").expect("failed to write");
+            client
+        });
+        let mut server = Some(server);
+        let (server, magic) = runtime.block_on(::futures::future::poll_fn(move || {
+            let mut magic = [0u8; 5];
+            let peeked = try_ready!(server.as_mut().expect("polled after completion").poll_peek(&mut magic));
+            assert_eq!(peeked, magic.len());
+            Ok::<_, ::std::io::Error>(::futures::Async::Ready((server.take().expect("checked above; qed"), magic)))
+        })).expect("failed to peek");
+        assert_eq!(&magic, b"MAGIC");
+
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 12])).expect("failed to read");
+        assert_eq!(&buf, b"MAGICpayload");
+        writer.join().expect("writer panicked");
+    }
+
+    #[test]
+    fn test_accepted_connections_inherit_config() {
+        use std::time::Instant;
+        use super::ConnectionConfig;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let endpoint = Endpoint::builder().path(path.clone())
+            .connection_config(ConnectionConfig { idle_timeout: Some(Duration::from_millis(200)), ..Default::default() })
+            .build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
+
+        // The client stays connected but never sends anything.
+        let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let started = Instant::now();
+        let err = match runtime.block_on(io::read_exact(server, [0u8; 1])) {
+            Ok(_) => panic!("reading from an idle connection should time out"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_accept_error_hook() {
+        use std::os::unix::io::AsRawFd;
+        use std::sync::{Arc, Mutex};
+        use super::AcceptErrorLog;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let reported = reported.clone();
+            AcceptErrorLog::hook(move |err| reported.lock().unwrap().push(err.raw_os_error()))
+        };
+        let path = random_pipe_path();
+        let endpoint = Endpoint::builder().path(path.clone()).accept_error_log(hook).build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
+
+        // Swap the listener's descriptor for a file while a duplicate keeps the socket
+        // registered, so that a client wakes up the accept, which then fails.
+        let fd = incoming.inner.as_raw_fd();
+        let listener = unsafe { ::libc::dup(fd) };
+        let file = ::std::fs::File::open("/dev/null").expect("failed to open /dev/null");
+        assert_eq!(unsafe { ::libc::dup2(file.as_raw_fd(), fd) }, fd);
+        let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let result = runtime.block_on(incoming.into_future());
+        unsafe {
+            ::libc::dup2(listener, fd);
+            ::libc::close(listener);
+        }
+        let err = match result {
+            Ok(_) => panic!("accepting on a file should fail"),
+            Err((err, _)) => err,
+        };
+        assert!(err.raw_os_error().is_some(), "{:?}", err);
+        assert_eq!(*reported.lock().unwrap(), vec![err.raw_os_error()]);
+    }
+
+    #[test]
+    fn test_on_ready_before_clients() {
+        use std::sync::mpsc;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+
+        // The only client connects from the callback, which fails unless already listening.
+        let (tx, rx) = mpsc::channel();
+        let client_path = path.clone();
+        let client_handle = handle.clone();
+        let mut endpoint = Endpoint::new(path);
+        endpoint.set_on_ready(move || {
+            tx.send(IpcConnection::connect(&client_path, &client_handle)).unwrap();
+        });
+        assert!(rx.try_recv().is_err());
+
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe/socket");
+        let client = rx.try_recv().expect("callback not called").expect("failed to connect when ready");
+        let (server, _incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn test_max_read_chunk() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let payload: Vec<u8> = (0..1024 * 1024).map(|i| i as u8).collect();
+        runtime.spawn(io::write_all(client, payload.clone())
+            .map(|_| ())
+            .map_err(|err| panic!("failed to write: {:?}", err)));
+
+        let server = server.max_read_chunk(1000);
+        let (_, received) = runtime.block_on(io::read_to_end(server, Vec::new())).expect("failed to read");
+        assert!(received == payload, "payload wasn't transferred intact");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_vectored() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, mut server) = connected_pair(&mut runtime);
+        let _client = runtime.block_on(io::write_all(client, b"HEADbody of it")).expect("failed to write").0;
+
+        let (read, header, body) = runtime.block_on(futures::future::poll_fn(move || {
+            let (mut header, mut body) = ([0u8; 4], [0u8; 10]);
+            let read = {
+                let mut bufs = [::std::io::IoSliceMut::new(&mut header), ::std::io::IoSliceMut::new(&mut body)];
+                server.read_vectored(&mut bufs)
+            };
+            match read {
+                Ok(read) => Ok(futures::Async::Ready((read, header, body))),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(futures::Async::NotReady),
+                Err(e) => Err(e),
+            }
+        })).expect("failed to read");
+        assert_eq!(read, 14);
+        assert_eq!(&header, b"HEAD");
+        assert_eq!(&body, b"body of it");
     }
 
-    // NOTE: Intermittently fails or stalls on windows.
+    #[cfg(unix)]
     #[test]
-    fn smoke_test() {
+    fn test_try_clone() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let clone = client.try_clone().expect("failed to clone connection");
+
+        let _server = runtime.block_on(io::write_all(server, b"ab")).expect("failed to write");
+        let (client, buf) = runtime.block_on(io::read_exact(client, [0u8; 1])).expect("failed to read from the original");
+        assert_eq!(&buf, b"a");
+        let (clone, buf) = runtime.block_on(io::read_exact(clone, [0u8; 1])).expect("failed to read from the clone");
+        assert_eq!(&buf, b"b");
+
+        // The connection stays open through the clone.
+        drop(client);
+        runtime.block_on(io::write_all(clone, b"still open")).expect("clone should outlive the original");
+    }
+
+    #[test]
+    fn test_write_after_disconnect() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, mut server) = connected_pair(&mut runtime);
+        drop(client);
+
+        // Pipe writes complete asynchronously, so the failure may only show up on the next write.
+        for _ in 0..10 {
+            match runtime.block_on(io::write_all(server, b"hello")) {
+                Ok((s, _)) => server = s,
+                Err(err) => {
+                    assert_eq!(err.kind(), ::std::io::ErrorKind::BrokenPipe, "{:?}", err);
+                    return;
+                },
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("writing to a disconnected peer should fail");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_message_mode_client() {
+        use super::PipeMode;
+
         let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
-        let exec = runtime.executor();
         let handle = runtime.reactor().clone();
 
         let path = random_pipe_path();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_pipe_mode(PipeMode::Message);
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
 
-        run_server(&path, exec, handle.clone());
+        let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
 
-        println!("Connecting to client 0...");
-        let client_0 = IpcConnection::connect(&path, &handle).expect("failed to open client_0");
-        println!("Connecting to client 1...");
-        let client_1 = IpcConnection::connect(&path, &handle).expect("failed to open client_1");
-        let msg = b"hello";
+        let (server, _) = runtime.block_on(io::write_all(server, b"one")).expect("failed to write first message");
+        let (_server, _) = runtime.block_on(io::write_all(server, b"two")).expect("failed to write second message");
 
-        let rx_buf = vec![0u8; msg.len()];
-        let client_0_fut = io::write_all(client_0, msg)
-            .map_err(|err| panic!("Client 0 write error: {:?}", err))
-            .and_then(move |(client, _)| {
-                io::read_exact(client, rx_buf).map(|(_, buf)| buf)
-                    .map_err(|err| panic!("Client 0 read error: {:?}", err))
-            });
+        let (client, buf, n) = runtime.block_on(io::read(client, vec![0u8; 16])).expect("failed to read first message");
+        assert_eq!(&buf[..n], b"one");
+        let (_client, buf, n) = runtime.block_on(io::read(client, vec![0u8; 16])).expect("failed to read second message");
+        assert_eq!(&buf[..n], b"two");
+    }
 
-        let rx_buf2 = vec![0u8; msg.len()];
-        let client_1_fut = io::write_all(client_1, msg)
-            .map_err(|err| panic!("Client 1 write error: {:?}", err))
-            .and_then(move |(client, _)| {
-                io::read_exact(client, rx_buf2).map(|(_, buf)| buf)
-                    .map_err(|err| panic!("Client 1 read error: {:?}", err))
-            });
+    #[cfg(windows)]
+    #[test]
+    fn test_read_whole_messages() {
+        use futures::{future, Async};
+        use super::PipeMode;
 
-        let fut = client_0_fut.join(client_1_fut).and_then(move |(rx_msg, other_rx_msg)| {
-            assert_eq!(rx_msg,  msg);
-            assert_eq!(other_rx_msg,  msg);
-            Ok(())
-        }).map_err(|err| panic!("Smoke test error: {:?}", err));
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
 
-        runtime.block_on(fut).expect("Runtime error")
+        let path = random_pipe_path();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_pipe_mode(PipeMode::Message);
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+
+        let mut client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        // Both messages are larger than the buffers callers usually read with.
+        let first = vec![1u8; 4000];
+        let second = vec![2u8; 3000];
+        let (server, _) = runtime.block_on(io::write_all(server, first.clone())).expect("failed to write first message");
+        let (_server, _) = runtime.block_on(io::write_all(server, second.clone())).expect("failed to write second message");
+
+        let messages = runtime.block_on(future::poll_fn(move || {
+            let mut messages = Vec::new();
+            while messages.len() < 2 {
+                match client.read_message() {
+                    Ok(message) => messages.push(message),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(Async::Ready(messages))
+        })).expect("failed to read messages");
+        assert_eq!(messages, vec![first, second]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_server_exists() {
+        use errors::{classify, ErrorKindExt};
+        use super::PipeWait;
+
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+
+        // No server.
+        assert!(!Endpoint::server_exists(&path).expect("failed to check the pipe"));
+        match IpcConnection::connect(&path, &handle) {
+            Ok(_) => panic!("connected without a server"),
+            Err(err) => assert_eq!(classify(&err), ErrorKindExt::NotFound),
+        }
+
+        // A server whose only instance is busy.
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_max_instances(1);
+        let _incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+        assert!(Endpoint::server_exists(&path).expect("failed to check the pipe"));
+        let _first = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        assert!(Endpoint::server_exists(&path).expect("failed to check the pipe"));
+        match IpcConnection::connect_with_wait(&path, &handle, PipeWait::Immediate) {
+            Ok(_) => panic!("connected to a busy pipe"),
+            Err(err) => assert_eq!(classify(&err), ErrorKindExt::PipeBusy),
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_wait_for_busy_pipe() {
+        use super::PipeWait;
+
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_max_instances(1);
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+        let first = IpcConnection::connect(&path, &handle).expect("failed to connect");
+
+        let err = IpcConnection::connect_with_wait(&path, &handle, PipeWait::Immediate).err()
+            .expect("the only pipe instance is busy");
+        assert_eq!(err.raw_os_error(), Some(winapi::shared::winerror::ERROR_PIPE_BUSY as i32));
+
+        let waiting = {
+            let (path, handle) = (path.clone(), handle.clone());
+            thread::spawn(move || {
+                IpcConnection::connect_with_wait(&path, &handle, PipeWait::Timeout(Duration::from_secs(5))).map(|_| ())
+            })
+        };
+
+        // Close the busy instance and offer a fresh one.
+        thread::sleep(Duration::from_millis(100));
+        drop(first);
+        drop(incoming);
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_max_instances(1);
+        let _incoming = endpoint.incoming(&handle).expect("failed to reopen the pipe");
+
+        waiting.join().unwrap().expect("waiting client should connect once the first disconnects");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_connect_before_server() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+
+        let waiting = {
+            let (path, handle) = (path.clone(), handle.clone());
+            thread::spawn(move || IpcConnection::connect_wait_for_server(&path, &handle, Duration::from_secs(5)).map(|_| ()))
+        };
+        thread::sleep(Duration::from_millis(200));
+        let _incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe");
+        waiting.join().unwrap().expect("client should connect once the server is up");
+
+        match IpcConnection::connect_wait_for_server(random_pipe_path(), &handle, Duration::from_millis(50)) {
+            Ok(_) => panic!("connected without a server"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_write_through_pipe() {
+        use super::ClientOptions;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let endpoint = Endpoint::builder().path(path.clone()).write_through(true).build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a write-through pipe");
+
+        let client = IpcConnection::connect_with_options(&path, &handle, &ClientOptions::new().write_through(true))
+            .expect("failed to connect with write-through");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_session_aware_connect() {
+        use super::ClientOptions;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe");
+        let client = IpcConnection::connect_with_options(&path, &handle, &ClientOptions::new().session_aware(true))
+            .expect("failed to connect with session awareness");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_overlapped_handles() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        assert!(client.is_overlapped().expect("failed to query the client handle"));
+        assert!(server.is_overlapped().expect("failed to query the server handle"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_back_to_back_connects() {
+        use super::InstanceStrategy;
+
+        for &instances in &[InstanceStrategy::LazyWithOverlap, InstanceStrategy::Eager(4)] {
+            let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+            let handle = runtime.reactor().clone();
+
+            let path = random_pipe_path();
+            let mut endpoint = Endpoint::new(path.clone());
+            endpoint.set_instance_strategy(instances);
+            let mut accepted = Vec::new();
+            let server = endpoint.incoming(&handle).expect("failed to open up a new pipe")
+                .for_each(move |(connection, _)| { accepted.push(connection); Ok(()) })
+                .map_err(|err| panic!("accept failed: {:?}", err));
+            runtime.executor().spawn(server);
+
+            let clients = (0..20).map(|i| IpcConnection::connect(&path, &handle)
+                .unwrap_or_else(|err| panic!("client {} failed to connect with {:?}: {:?}", i, instances, err)))
+                .collect::<Vec<_>>();
+            assert_eq!(clients.len(), 20);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_client_session_id() {
+        use winapi::um::processthreadsapi::{GetCurrentProcessId, ProcessIdToSessionId};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (_client, server) = connected_pair(&mut runtime);
+
+        let mut expected = 0;
+        assert!(unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut expected) } != 0);
+        assert_eq!(server.client_session_id().expect("failed to get client session id"), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_client_session_id_unsupported() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (_client, server) = connected_pair(&mut runtime);
+
+        assert_eq!(server.client_session_id().unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_pipe_info() {
+        use super::PipeMode;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_pipe_mode(PipeMode::Message);
+        endpoint.set_max_instances(4);
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+
+        let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let info = server.pipe_info().expect("failed to get server pipe info");
+        assert!(info.server_end);
+        assert_eq!(info.pipe_mode, PipeMode::Message);
+        assert_eq!(info.max_instances, 4);
+
+        let info = client.pipe_info().expect("failed to get client pipe info");
+        assert!(!info.server_end);
+        assert_eq!(info.pipe_mode, PipeMode::Message);
+        assert_eq!(info.read_mode, PipeMode::Message);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_message_pipe_byte_read_mode() {
+        use super::PipeMode;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let path = random_pipe_path();
+        let endpoint = Endpoint::builder().path(path.clone())
+            .pipe_mode(PipeMode::Message)
+            .read_mode(PipeMode::Byte)
+            .build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+        let _client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+
+        let info = server.pipe_info().expect("failed to get server pipe info");
+        assert_eq!(info.pipe_mode, PipeMode::Message);
+        assert_eq!(info.read_mode, PipeMode::Byte);
+
+        let mut endpoint = Endpoint::new(random_pipe_path());
+        endpoint.set_read_mode(PipeMode::Message);
+        assert_eq!(endpoint.incoming(&handle).err().map(|err| err.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_remote_connect_to_missing_host() {
+        use std::time::Instant;
+        use errors::{classify, ErrorKindExt};
+        use ClientOptions;
+
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let options = ClientOptions::new().remote_timeout(Duration::from_secs(2));
+        let started = Instant::now();
+        let err = match IpcConnection::connect_with_options(r"\\nonexistent-host.invalid\pipe\my-pipe", runtime.reactor(), &options) {
+            Ok(_) => panic!("connecting to a missing host should fail"),
+            Err(err) => err,
+        };
+        // SMB either reports the host right away or never answers.
+        let kind = classify(&err);
+        assert!(kind == ErrorKindExt::HostUnreachable || kind == ErrorKindExt::TimedOut, "unexpected error {:?}", err);
+        assert!(started.elapsed() < Duration::from_secs(5), "connect took {:?}", started.elapsed());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_collect_data_timeout() {
+        use std::os::windows::io::AsRawHandle;
+        use ClientOptions;
+
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let _incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe");
+
+        // Going through the redirector makes the pipe remote, if file sharing is enabled.
+        let remote_path = path.replacen(r"\.", r"\localhost", 1);
+        let options = ClientOptions::new().collect_data_timeout(Duration::from_millis(5));
+        let client = match IpcConnection::connect_with_options(&remote_path, &handle, &options) {
+            Ok(client) => client,
+            Err(err) => return println!("Skipping, {} is unreachable: {:?}", remote_path, err),
+        };
+        let timeout = ::win_pipe::collect_data_timeout(client.inner.as_raw_handle()).expect("failed to get the collect data timeout");
+        assert_eq!(timeout, Duration::from_millis(5));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_from_blocking_pipe() {
+        use std::path::Path;
+        use winapi::um::winbase::FILE_FLAG_OVERLAPPED;
+        use {PipeDirection, PipeWait};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        // Synchronous handles and other files are turned down.
+        let path = random_pipe_path();
+        let _incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe");
+        let synchronous = ::win_pipe::open_client(Path::new(&path), PipeDirection::Duplex.client_access(), 0, PipeWait::default()).expect("failed to open the pipe");
+        match IpcConnection::from_blocking(synchronous, &handle) {
+            Ok(_) => panic!("adopted a synchronous pipe"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        }
+        let not_a_pipe = ::std::fs::File::open(::std::env::current_exe().unwrap()).unwrap();
+        match IpcConnection::from_blocking(not_a_pipe, &handle) {
+            Ok(_) => panic!("adopted a regular file"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+        }
+
+        let path = random_pipe_path();
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe");
+        let file = ::win_pipe::open_client(Path::new(&path), PipeDirection::Duplex.client_access(), FILE_FLAG_OVERLAPPED, PipeWait::default()).expect("failed to open the pipe");
+        let client = IpcConnection::from_blocking(file, &handle).expect("failed to register the pipe");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_outbound_pipe() {
+        use {ClientOptions, PipeDirection};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let endpoint = Endpoint::builder().path(path.clone())
+            .direction(PipeDirection::Outbound)
+            .buffer_size(4096, 1024 * 1024)
+            .build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to create an outbound pipe");
+
+        let options = ClientOptions::new().direction(PipeDirection::Outbound);
+        let client = IpcConnection::connect_with_options(&path, &handle, &options).expect("failed to connect");
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+        let info = server.pipe_info().expect("failed to get pipe info");
+        assert_eq!(info.in_buffer_size, 0);
+        assert!(info.out_buffer_size > 0);
+
+        let _server = runtime.block_on(io::write_all(server, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(client, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_instance_creation_is_retried() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = random_pipe_path();
+        let retry = RetryConfig { max_attempts: 50, initial_delay: Duration::from_millis(20), max_delay: Duration::from_millis(20), ..Default::default() };
+        let endpoint = Endpoint::builder().path(path.clone()).max_instances(1).instance_retry(Some(retry)).build().unwrap();
+        let incoming = endpoint.incoming(&handle).expect("failed to open up a new pipe");
+
+        // The only instance is taken, so creating its replacement fails until it's closed.
+        let first = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let (server, incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert!(server.is_some());
+        assert!(!incoming.is_bound());
+        drop((first, server));
+
+        let second = {
+            let (path, handle) = (path.clone(), handle.clone());
+            thread::spawn(move || IpcConnection::connect_wait_for_server(&path, &handle, Duration::from_secs(5)).map(|_| ()))
+        };
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("accepting stopped");
+        assert!(server.is_some());
+        second.join().unwrap().expect("failed to connect once the instance was recreated");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_connect_with_owner_rights() {
+        use winapi::um::winnt::{GENERIC_READ, GENERIC_WRITE, READ_CONTROL, WRITE_DAC, WRITE_OWNER};
+        use ClientOptions;
+
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let options = ClientOptions::new().access_rights(WRITE_DAC | WRITE_OWNER);
+
+        let granting = random_pipe_path();
+        let attributes = SecurityAttributes::builder()
+            .allow_current_user(GENERIC_READ | GENERIC_WRITE | READ_CONTROL | WRITE_DAC | WRITE_OWNER)
+            .build().unwrap();
+        let _incoming = Endpoint::with_security_attributes(granting.clone(), attributes).incoming(&handle)
+            .expect("failed to open up a new pipe");
+        IpcConnection::connect_with_options(&granting, &handle, &options).expect("failed to connect with owner rights");
+
+        let refusing = random_pipe_path();
+        let attributes = SecurityAttributes::builder().allow_current_user(GENERIC_READ | GENERIC_WRITE).build().unwrap();
+        let _incoming = Endpoint::with_security_attributes(refusing.clone(), attributes).incoming(&handle)
+            .expect("failed to open up a new pipe");
+        match IpcConnection::connect_with_options(&refusing, &handle, &options) {
+            Ok(_) => panic!("connected with rights the server doesn't grant"),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::PermissionDenied),
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_pipes_use_pipe_wait() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        // Overlapped I/O provides the non-blocking behaviour, `PIPE_NOWAIT` would spin.
+        assert!(!server.pipe_info().expect("failed to get server pipe info").nowait);
+        assert!(!client.pipe_info().expect("failed to get client pipe info").nowait);
     }
 
     #[cfg(windows)]
@@ -424,4 +3352,56 @@ mod tests {
         create_pipe_with_permissions(SecurityAttributes::allow_everyone_connect().unwrap())
             .expect("failed with attributes for connecting");
     }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_connect_expecting_other_owner() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor();
+
+        let path = random_pipe_path();
+        let _incoming = Endpoint::new(path.clone()).incoming(handle).expect("failed to open up a new pipe");
+
+        let err = IpcConnection::connect_expecting_owner(&path, handle, "S-1-5-21-1-2-3-1001").err()
+            .expect("a pipe owned by someone else should be rejected");
+        assert_eq!(err.kind(), ::std::io::ErrorKind::PermissionDenied);
+
+        let path = random_pipe_path();
+        let _incoming = Endpoint::new(path.clone()).incoming(handle).expect("failed to open up a new pipe");
+        let err = IpcConnection::connect_expecting_owner(&path, handle, "not a SID").err()
+            .expect("an invalid SID should be rejected");
+        assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_effective_security() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor();
+
+        let endpoint = Endpoint::with_security_attributes(random_pipe_path(), SecurityAttributes::allow_everyone_connect().unwrap());
+        let incoming = endpoint.incoming(handle).expect("failed to open up a new pipe");
+        let sddl = incoming.effective_security().expect("failed to read back the pipe DACL");
+        assert!(sddl.contains(";;;WD)"), "no ACE for the World SID in {}", sddl);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_deny_all_blocks_connection() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor();
+
+        let path = random_pipe_path();
+        let _incoming = Endpoint::with_security_attributes(path.clone(), SecurityAttributes::inherit_default())
+            .incoming(handle)
+            .expect("failed to open up a new pipe");
+        IpcConnection::connect(&path, handle).expect("default security should let the creator connect");
+
+        let path = random_pipe_path();
+        let _incoming = Endpoint::with_security_attributes(path.clone(), SecurityAttributes::deny_all().unwrap())
+            .incoming(handle)
+            .expect("failed to open up a new pipe");
+        let err = IpcConnection::connect(&path, handle).err().expect("an empty DACL should deny everyone");
+        assert_eq!(err.kind(), ::std::io::ErrorKind::PermissionDenied);
+    }
 }
@@ -0,0 +1,183 @@
+use std::cmp;
+use std::io;
+
+use bytes::Bytes;
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use tokio::codec::{Framed, LengthDelimitedCodec};
+
+use budget::{Charged, FrameMemoryBudget};
+use IpcStream;
+
+// Bytes of the length prefix of every message.
+const HEADER_LEN: usize = 4;
+
+/// Connection exchanging discrete messages over any `IpcStream`.
+///
+/// Unix sockets and byte-mode pipes are plain byte streams: a write may arrive split
+/// across reads or merged with the next one. Every message is length-prefixed here, so
+/// `recv` returns exactly what one `send` sent on every platform and in every pipe mode.
+/// Messages are delivered in the order they were sent. Both ends must use a
+/// `MessageConnection`, and messages are limited to 8 MiB. Servers with many
+/// connections can bound the memory buffered for messages with `with_budget`.
+pub struct MessageConnection<S> {
+    framed: Framed<Charged<S>, LengthDelimitedCodec>,
+}
+
+impl<S: IpcStream> MessageConnection<S> {
+    /// Wrap a stream whose peer also uses a `MessageConnection`.
+    pub fn new(stream: S) -> Self {
+        MessageConnection { framed: Framed::new(Charged::new(stream, None), LengthDelimitedCodec::new()) }
+    }
+
+    /// Wrap a stream, charging the messages buffered while receiving them to `budget`.
+    ///
+    /// Receiving pauses while the budget is spent, and messages longer than it fail
+    /// with `InvalidData`.
+    pub fn with_budget(stream: S, budget: FrameMemoryBudget) -> Self {
+        let mut codec = LengthDelimitedCodec::new();
+        let max_len = cmp::min(codec.max_frame_length(), budget.limit().saturating_sub(HEADER_LEN));
+        codec.set_max_frame_length(max_len);
+        MessageConnection { framed: Framed::new(Charged::new(stream, Some(budget)), codec) }
+    }
+
+    /// Send `message`, resolving once it was written to the stream.
+    pub fn send(self, message: &[u8]) -> SendMessage<S> {
+        SendMessage { connection: Some(self), message: Some(Bytes::from(message)) }
+    }
+
+    /// Receive the next message, failing with `UnexpectedEof` if the peer closed the
+    /// connection instead.
+    pub fn recv(self) -> RecvMessage<S> {
+        RecvMessage { connection: Some(self) }
+    }
+
+    /// Unwrap the stream, discarding any buffered data.
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner().into_inner()
+    }
+}
+
+/// Future sending a message, created by `MessageConnection::send`.
+pub struct SendMessage<S> {
+    connection: Option<MessageConnection<S>>,
+    message: Option<Bytes>,
+}
+
+impl<S: IpcStream> Future for SendMessage<S> {
+    type Item = MessageConnection<S>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<MessageConnection<S>, io::Error> {
+        {
+            let connection = self.connection.as_mut().expect("poll a SendMessage after it's done");
+            if let Some(message) = self.message.take() {
+                if let AsyncSink::NotReady(message) = connection.framed.start_send(message)? {
+                    self.message = Some(message);
+                    return Ok(Async::NotReady);
+                }
+            }
+            try_ready!(connection.framed.poll_complete());
+        }
+        Ok(Async::Ready(self.connection.take().expect("checked above; qed")))
+    }
+}
+
+/// Future receiving a message, created by `MessageConnection::recv`.
+pub struct RecvMessage<S> {
+    connection: Option<MessageConnection<S>>,
+}
+
+impl<S: IpcStream> Future for RecvMessage<S> {
+    type Item = (MessageConnection<S>, Vec<u8>);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        let message = {
+            let connection = self.connection.as_mut().expect("poll a RecvMessage after it's done");
+            match try_ready!(connection.framed.poll()) {
+                Some(message) => {
+                    connection.framed.get_mut().release(HEADER_LEN + message.len());
+                    message.to_vec()
+                },
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before a message")),
+            }
+        };
+        Ok(Async::Ready((self.connection.take().expect("checked above; qed"), message)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, ErrorKind};
+
+    use futures::{future, sync::oneshot, Future};
+    use tokio;
+
+    use testing::duplex;
+    use tests::connected_pair;
+    use IpcStream;
+    use super::MessageConnection;
+
+    type BoxFuture<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+    fn send_all<S: IpcStream + 'static>(connection: MessageConnection<S>, mut messages: Vec<Vec<u8>>) -> BoxFuture<MessageConnection<S>> {
+        if messages.is_empty() {
+            return Box::new(future::ok(connection));
+        }
+        let message = messages.remove(0);
+        Box::new(connection.send(&message).and_then(move |connection| send_all(connection, messages)))
+    }
+
+    fn recv_all<S: IpcStream + 'static>(connection: MessageConnection<S>, count: usize, mut received: Vec<Vec<u8>>) -> BoxFuture<(MessageConnection<S>, Vec<Vec<u8>>)> {
+        if received.len() == count {
+            return Box::new(future::ok((connection, received)));
+        }
+        Box::new(connection.recv().and_then(move |(connection, message)| {
+            received.push(message);
+            recv_all(connection, count, received)
+        }))
+    }
+
+    // Receive all requests, then answer with all replies.
+    fn serve<S: IpcStream + 'static>(connection: MessageConnection<S>, count: usize, replies: Vec<Vec<u8>>) -> impl Future<Item = Vec<Vec<u8>>, Error = io::Error> {
+        recv_all(connection, count, Vec::new())
+            .and_then(move |(connection, received)| send_all(connection, replies).map(|_| received))
+    }
+
+    #[test]
+    fn test_message_boundaries() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        // Small messages a byte stream could merge, interleaved with large ones it splits.
+        let requests = vec![b"a".to_vec(), vec![1; 200_000], Vec::new(), b"bc".to_vec(), vec![2; 70_000]];
+        let replies = vec![vec![3; 100_000], b"d".to_vec(), b"ef".to_vec()];
+
+        let (client, server) = connected_pair(&mut runtime);
+        let served = oneshot::spawn(serve(MessageConnection::new(server), requests.len(), replies.clone()), &runtime.executor());
+        let client = runtime.block_on(send_all(MessageConnection::new(client), requests.clone())).expect("failed to send");
+        let (_, received) = runtime.block_on(recv_all(client, replies.len(), Vec::new())).expect("failed to receive");
+        assert_eq!(received, replies);
+        assert_eq!(runtime.block_on(served).expect("server failed"), requests);
+
+        // The same holds over a stream passing 3 bytes at a time.
+        let requests = vec![b"a".to_vec(), vec![1; 2000], Vec::new(), b"bc".to_vec()];
+        let replies = vec![vec![3; 1000], b"d".to_vec()];
+        let (client, server) = duplex(3);
+        let served = oneshot::spawn(serve(MessageConnection::new(server), requests.len(), replies.clone()), &runtime.executor());
+        let client = runtime.block_on(send_all(MessageConnection::new(client), requests.clone())).expect("failed to send");
+        let (_, received) = runtime.block_on(recv_all(client, replies.len(), Vec::new())).expect("failed to receive");
+        assert_eq!(received, replies);
+        assert_eq!(runtime.block_on(served).expect("server failed"), requests);
+    }
+
+    #[test]
+    fn test_recv_after_close() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        drop(server);
+
+        match runtime.block_on(MessageConnection::new(client).recv()) {
+            Ok(_) => panic!("recv should fail once the peer closed"),
+            Err(err) => assert_eq!(err.kind(), ErrorKind::UnexpectedEof),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::io;
+
+/// Identity of the process at the other end of a connection.
+///
+/// Captured when the connection is accepted or made, so it's still available once the
+/// peer exited. Fields the platform can't tell are `None`: Unix systems other than
+/// Linux have no peer pid and Windows no user or group ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// Process id of the peer.
+    pub pid: Option<u32>,
+    /// Effective user id of the peer.
+    pub uid: Option<u32>,
+    /// Effective group id of the peer.
+    pub gid: Option<u32>,
+}
+
+/// Credentials the peer connected with, recorded by the kernel at `connect`.
+#[cfg(target_os = "linux")]
+pub(crate) fn of(stream: &::tokio_uds::UnixStream) -> io::Result<PeerInfo> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let mut cred: ::libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<::libc::ucred>() as ::libc::socklen_t;
+    let result = unsafe {
+        ::libc::getsockopt(stream.as_raw_fd(), ::libc::SOL_SOCKET, ::libc::SO_PEERCRED,
+            &mut cred as *mut ::libc::ucred as *mut _, &mut len)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerInfo { pid: Some(cred.pid as u32), uid: Some(cred.uid), gid: Some(cred.gid) })
+}
+
+// Not exported by `libc`.
+#[cfg(all(target_os = "linux", not(any(target_arch = "sparc", target_arch = "sparc64"))))]
+const SO_PEERGROUPS: ::libc::c_int = 59;
+#[cfg(all(target_os = "linux", any(target_arch = "sparc", target_arch = "sparc64")))]
+const SO_PEERGROUPS: ::libc::c_int = 0x3d;
+
+/// Supplementary groups of the peer, recorded by the kernel at `connect` (Linux 4.13+),
+/// or else read from `/proc` for the peer's process.
+#[cfg(target_os = "linux")]
+pub(crate) fn groups(stream: &::tokio_uds::UnixStream, pid: Option<u32>) -> io::Result<Vec<u32>> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut groups: Vec<::libc::gid_t> = vec![0; 64];
+    loop {
+        let mut len = (groups.len() * ::std::mem::size_of::<::libc::gid_t>()) as ::libc::socklen_t;
+        let result = unsafe {
+            ::libc::getsockopt(stream.as_raw_fd(), ::libc::SOL_SOCKET, SO_PEERGROUPS,
+                groups.as_mut_ptr() as *mut _, &mut len)
+        };
+        let count = len as usize / ::std::mem::size_of::<::libc::gid_t>();
+        if result == 0 {
+            groups.truncate(count);
+            return Ok(groups);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // `len` was set to the size needed.
+            Some(::libc::ERANGE) if count > groups.len() => groups.resize(count, 0),
+            Some(::libc::ENOPROTOOPT) => break,
+            _ => return Err(err),
+        }
+    }
+
+    let pid = pid.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "peer process unknown"))?;
+    let status = ::std::fs::read_to_string(format!("/proc/{}/status", pid))?;
+    let line = status.lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no groups in the process status"))?;
+    line.split_whitespace()
+        .map(|gid| gid.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid group id in the process status")))
+        .collect()
+}
+
+/// Credentials the peer connected with, recorded by the kernel at `connect`.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub(crate) fn of(stream: &::tokio_uds::UnixStream) -> io::Result<PeerInfo> {
+    let cred = stream.peer_cred()?;
+    Ok(PeerInfo { pid: None, uid: Some(cred.uid), gid: Some(cred.gid) })
+}
+
+/// Process at the other end of the pipe, the client for a server end and vice versa.
+#[cfg(windows)]
+pub(crate) fn of(pipe: &::tokio_named_pipes::NamedPipe) -> io::Result<PeerInfo> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::winbase::{GetNamedPipeClientProcessId, GetNamedPipeServerProcessId, PIPE_SERVER_END};
+
+    let handle = pipe.as_raw_handle();
+    let mut pid = 0;
+    let result = if ::win_pipe::pipe_flags(handle)? & PIPE_SERVER_END != 0 {
+        unsafe { GetNamedPipeClientProcessId(handle as _, &mut pid) }
+    } else {
+        unsafe { GetNamedPipeServerProcessId(handle as _, &mut pid) }
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerInfo { pid: Some(pid), uid: None, gid: None })
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use tokio;
+
+    use tests::connected_pair;
+
+    #[cfg(any(target_os = "linux", windows))]
+    #[test]
+    fn test_peer_info_of_pair() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let pid = Some(::std::process::id());
+        assert_eq!(server.peer_info().expect("no peer info on the server end").pid, pid);
+        assert_eq!(client.peer_info().expect("no peer info on the client end").pid, pid);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_peer_groups_of_pair() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (_client, server) = connected_pair(&mut runtime);
+
+        let mut expected = vec![0; 256];
+        let count = unsafe { ::libc::getgroups(expected.len() as ::libc::c_int, expected.as_mut_ptr()) };
+        assert!(count >= 0, "getgroups failed");
+        expected.truncate(count as usize);
+        let mut groups = server.peer_groups().expect("failed to get peer groups");
+        expected.sort_unstable();
+        groups.sort_unstable();
+        assert_eq!(groups, expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_peer_info_after_peer_exited() {
+        use std::ffi::CString;
+        use std::mem;
+
+        use futures::Stream;
+
+        use Endpoint;
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let path = ::dummy_endpoint();
+        let incoming = Endpoint::new(path.clone()).incoming(runtime.reactor()).expect("failed to bind");
+
+        // Everything the child needs is prepared before forking, it only makes syscalls.
+        let mut addr: ::libc::sockaddr_un = unsafe { mem::zeroed() };
+        addr.sun_family = ::libc::AF_UNIX as ::libc::sa_family_t;
+        for (dst, src) in addr.sun_path.iter_mut().zip(CString::new(path.clone()).unwrap().as_bytes()) {
+            *dst = *src as ::libc::c_char;
+        }
+        let child = unsafe { ::libc::fork() };
+        assert!(child >= 0, "fork failed");
+        if child == 0 {
+            unsafe {
+                let fd = ::libc::socket(::libc::AF_UNIX, ::libc::SOCK_STREAM, 0);
+                let connected = ::libc::connect(fd, &addr as *const _ as *const ::libc::sockaddr,
+                    mem::size_of::<::libc::sockaddr_un>() as ::libc::socklen_t);
+                ::libc::_exit(if connected == 0 { 0 } else { 1 });
+            }
+        }
+        let mut status = 0;
+        assert_eq!(unsafe { ::libc::waitpid(child, &mut status, 0) }, child);
+        assert_eq!(status, 0, "child failed to connect");
+
+        // The peer is gone by the time the connection is accepted.
+        let (server, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        let (server, _) = server.expect("no connection received");
+        let info = server.peer_info().expect("no peer info cached");
+        assert_eq!(info.pid, Some(child as u32));
+        assert_eq!(info.uid, Some(unsafe { ::libc::geteuid() }));
+        let _ = ::std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,160 @@
+use std::io;
+
+// Longest full pipe name accepted by `CreateNamedPipeW`.
+const MAX_PIPE_NAME_LEN: usize = 256;
+
+// Names up to this many UTF-16 units, terminator included, are converted on the stack.
+const INLINE_NAME_LEN: usize = 128;
+
+// Device names Windows reserves regardless of the directory or extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn invalid(path: &str, reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid pipe name {:?}: {}", path, reason))
+}
+
+/// Check `path` is a valid `\\server\pipe\name` pipe path, so that users get a
+/// descriptive error rather than a confusing code from `CreateNamedPipeW`.
+pub fn validate(path: &str) -> io::Result<()> {
+    if path.encode_utf16().count() > MAX_PIPE_NAME_LEN {
+        return Err(invalid(path, "longer than 256 characters"));
+    }
+
+    let rest = match path.strip_prefix(r"\\") {
+        Some(rest) => rest,
+        None => return Err(invalid(path, r"expected a path of the form \\.\pipe\name")),
+    };
+    let mut parts = rest.splitn(3, '\\');
+    let server = parts.next().unwrap_or("");
+    let pipe = parts.next().unwrap_or("");
+    let name = parts.next().unwrap_or("");
+
+    if server.is_empty() || !pipe.eq_ignore_ascii_case("pipe") {
+        return Err(invalid(path, r"expected a path of the form \\.\pipe\name"));
+    }
+    if name.is_empty() {
+        return Err(invalid(path, "the pipe name is empty"));
+    }
+    if name.contains('\\') {
+        return Err(invalid(path, "the pipe name must not contain a backslash"));
+    }
+
+    let stem = name.split('.').next().unwrap_or("").trim_end();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        return Err(invalid(path, "the pipe name is a reserved device name"));
+    }
+    Ok(())
+}
+
+/// Nul-terminated UTF-16 pipe name for the wide Win32 functions.
+///
+/// Names are converted on every connect, so short ones are kept in a stack buffer
+/// rather than allocated; only names longer than 127 units go to the heap.
+pub struct WideName {
+    inline: [u16; INLINE_NAME_LEN],
+    len: usize,
+    heap: Vec<u16>,
+}
+
+impl WideName {
+    /// Convert the UTF-16 `units` of a name, appending the terminator.
+    pub fn new<I: IntoIterator<Item = u16>>(units: I) -> Self {
+        let mut name = WideName { inline: [0; INLINE_NAME_LEN], len: 0, heap: Vec::new() };
+        for unit in units.into_iter().chain(Some(0)) {
+            if name.len < INLINE_NAME_LEN {
+                name.inline[name.len] = unit;
+            } else {
+                if name.heap.is_empty() {
+                    name.heap.extend_from_slice(&name.inline);
+                }
+                name.heap.push(unit);
+            }
+            name.len += 1;
+        }
+        name
+    }
+
+    /// The name, terminator included.
+    pub fn as_slice(&self) -> &[u16] {
+        if self.len <= INLINE_NAME_LEN {
+            &self.inline[..self.len]
+        } else {
+            &self.heap
+        }
+    }
+
+    /// Pointer to pass to Win32 functions, valid as long as `self`.
+    #[cfg(windows)]
+    pub fn as_ptr(&self) -> *const u16 {
+        self.as_slice().as_ptr()
+    }
+
+    #[cfg(test)]
+    fn is_inline(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+/// Server part of a pipe path on another host, `None` for local pipes.
+pub fn remote_host(path: &str) -> Option<&str> {
+    let server = path.strip_prefix(r"\\")?.split('\\').next()?;
+    if server.is_empty() || server == "." {
+        return None;
+    }
+    Some(server)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{remote_host, validate, WideName};
+
+    #[test]
+    fn test_valid_names() {
+        validate(r"\\.\pipe\my-pipe-1").expect("local pipe name is valid");
+        validate(r"\\server\pipe\my.pipe").expect("remote pipe name is valid");
+        validate(r"\\.\PIPE\CONSOLE").expect("names merely starting with a reserved name are valid");
+    }
+
+    #[test]
+    fn test_illegal_name() {
+        for name in &[r"\\.\pipe\my\pipe", r"\\.\pipe\", r"\\.\pipes\my-pipe", "/tmp/my-pipe", r"\\\pipe\x"] {
+            let err = validate(name).expect_err(name);
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        let long_name = format!(r"\\.\pipe\{}", "x".repeat(300));
+        assert!(validate(&long_name).is_err());
+    }
+
+    #[test]
+    fn test_wide_names() {
+        for name in &[r"\\.\pipe\my-pipe".to_owned(), format!(r"\\.\pipe\{}", "\u{e9}".repeat(118)), format!(r"\\.\pipe\{}", "x".repeat(300))] {
+            let expected: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+            let wide = WideName::new(name.encode_utf16());
+            assert_eq!(wide.as_slice(), &expected[..], "{}", name);
+            assert_eq!(wide.is_inline(), expected.len() <= 128, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_remote_host() {
+        assert_eq!(remote_host(r"\\.\pipe\my-pipe"), None);
+        assert_eq!(remote_host(r"\\build-box\pipe\my-pipe"), Some("build-box"));
+        assert_eq!(remote_host("/tmp/my-pipe"), None);
+    }
+
+    #[test]
+    fn test_reserved_name() {
+        for name in &[r"\\.\pipe\CON", r"\\.\pipe\con", r"\\.\pipe\nul.txt", r"\\.\pipe\COM1", r"\\.\pipe\lpt9.log"] {
+            let err = validate(name).expect_err(name);
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("reserved"), "{}", err);
+        }
+    }
+}
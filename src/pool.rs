@@ -0,0 +1,232 @@
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bytes::{Buf, BufMut};
+use futures::Poll;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::reactor::Handle;
+
+use IpcConnection;
+
+/// Pool of client connections to one endpoint, reused across short calls.
+///
+/// `get` lends out an idle connection, or connects anew if there is none. Dropping the
+/// `PooledConnection` returns it to the pool, keeping at most `max_idle` connections;
+/// the others are closed. Before being lent out again an idle connection is checked to
+/// still be connected with no stray data to read, since a connection that has unread
+/// data left over from a previous call can't be reused. Clones share the same pool.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    path: PathBuf,
+    handle: Handle,
+    max_idle: usize,
+    idle: Arc<Mutex<Vec<IpcConnection>>>,
+}
+
+impl ConnectionPool {
+    /// New empty pool of connections to `path`, keeping up to `max_idle` of them.
+    pub fn new<P: Into<PathBuf>>(path: P, handle: &Handle, max_idle: usize) -> Self {
+        ConnectionPool {
+            path: path.into(),
+            handle: handle.clone(),
+            max_idle,
+            idle: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Lend out an idle connection, or make a new one if none is left.
+    pub fn get(&self) -> io::Result<PooledConnection> {
+        loop {
+            let connection = match self.lock().pop() {
+                Some(connection) => connection,
+                None => break,
+            };
+            if is_reusable(&connection) {
+                return Ok(self.lend(connection));
+            }
+            trace!("Dropping stale pooled connection to {}", self.path.display());
+        }
+        Ok(self.lend(IpcConnection::connect(&self.path, &self.handle)?))
+    }
+
+    /// Number of idle connections in the pool.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lend(&self, connection: IpcConnection) -> PooledConnection {
+        PooledConnection { connection: Some(connection), pool: self.clone() }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<IpcConnection>> {
+        self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn release(&self, connection: IpcConnection) {
+        let mut idle = self.lock();
+        if idle.len() < self.max_idle {
+            idle.push(connection);
+        }
+    }
+}
+
+// An idle connection is readable only if the peer hung up or sent something nobody asked for.
+#[cfg(unix)]
+fn is_reusable(connection: &IpcConnection) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fd = ::libc::pollfd { fd: connection.inner.as_raw_fd(), events: ::libc::POLLIN, revents: 0 };
+    unsafe { ::libc::poll(&mut fd, 1, 0) == 0 }
+}
+
+// The pipe's buffered data isn't visible through the handle while a read is pending, so
+// only a broken pipe is detected.
+#[cfg(windows)]
+fn is_reusable(connection: &IpcConnection) -> bool {
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+    use winapi::um::namedpipeapi::PeekNamedPipe;
+
+    unsafe {
+        PeekNamedPipe(connection.inner.as_raw_handle() as _, ptr::null_mut(), 0,
+            ptr::null_mut(), ptr::null_mut(), ptr::null_mut()) != 0
+    }
+}
+
+/// Connection lent out by a `ConnectionPool`, returned to it when dropped.
+pub struct PooledConnection {
+    connection: Option<IpcConnection>,
+    pool: ConnectionPool,
+}
+
+impl PooledConnection {
+    /// Take the connection out of the pool for good.
+    pub fn detach(mut self) -> IpcConnection {
+        self.connection.take().expect("only taken on detach or drop; qed")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.release(connection);
+        }
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = IpcConnection;
+
+    fn deref(&self) -> &IpcConnection {
+        self.connection.as_ref().expect("only taken on detach or drop; qed")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut IpcConnection {
+        self.connection.as_mut().expect("only taken on detach or drop; qed")
+    }
+}
+
+impl Read for PooledConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl Write for PooledConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+impl AsyncRead for PooledConnection {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        (**self).prepare_uninitialized_buffer(buf)
+    }
+
+    fn read_buf<B: BufMut>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        AsyncRead::read_buf(&mut **self, buf)
+    }
+}
+
+impl AsyncWrite for PooledConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        AsyncWrite::shutdown(&mut **self)
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        AsyncWrite::write_buf(&mut **self, buf)
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use tokio::{self, io::{self, AsyncRead}};
+
+    use Endpoint;
+    use super::ConnectionPool;
+
+    #[test]
+    fn test_calls_reuse_connections() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = ::dummy_endpoint();
+
+        // Echo server counting the connections it accepted.
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+        let counter = accepted.clone();
+        runtime.spawn(incoming
+            .for_each(move |(connection, _)| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let (reader, writer) = connection.split();
+                tokio::spawn(io::copy(reader, writer).map(|_| ()).map_err(|_| ()));
+                Ok(())
+            })
+            .map_err(|err| panic!("server failed: {:?}", err)));
+
+        let pool = ConnectionPool::new(path, &handle, 2);
+        for _ in 0..20 {
+            let connection = pool.get().expect("failed to get a connection");
+            let connection = runtime.block_on(io::write_all(connection, b"ping")).expect("failed to write").0;
+            let (_, buf) = runtime.block_on(io::read_exact(connection, [0u8; 4])).expect("failed to read");
+            assert_eq!(&buf, b"ping");
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stale_connection_is_replaced() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = ::dummy_endpoint();
+
+        let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+        let pool = ConnectionPool::new(path, &handle, 2);
+        drop(pool.get().expect("failed to connect"));
+        assert_eq!(pool.idle(), 1);
+
+        // The server closes the first connection, so the pool has to connect again.
+        let (first, incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        drop(first);
+        let _connection = pool.get().expect("failed to reconnect");
+        assert_eq!(pool.idle(), 0);
+        let (second, _) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+        assert!(second.is_some());
+    }
+}
@@ -0,0 +1,104 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use futures::Poll;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Connection wrapper replaying bytes already taken off the connection before
+/// continuing with live data.
+///
+/// Created by `IpcConnection::prepend`. Lets a router read a protocol prefix, pick a
+/// handler and hand it a stream that still starts with that prefix. Writes go straight
+/// to the wrapped connection.
+pub struct Prepended<S> {
+    inner: S,
+    prefix: Vec<u8>,
+    pos: usize,
+}
+
+impl<S> Prepended<S> {
+    /// Wrap `inner`, reading `prefix` first.
+    pub fn new(inner: S, prefix: Vec<u8>) -> Self {
+        Prepended { inner, prefix, pos: 0 }
+    }
+
+    /// Prefix bytes not read yet.
+    pub fn remaining_prefix(&self) -> &[u8] {
+        &self.prefix[self.pos..]
+    }
+
+    /// Reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the connection along with the prefix bytes not read yet.
+    pub fn into_inner(mut self) -> (S, Vec<u8>) {
+        self.prefix.drain(..self.pos);
+        (self.inner, self.prefix)
+    }
+}
+
+impl<S: Read> Read for Prepended<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.prefix.len() {
+            return self.inner.read(buf);
+        }
+
+        let n = cmp::min(buf.len(), self.prefix.len() - self.pos);
+        buf[..n].copy_from_slice(&self.prefix[self.pos..self.pos + n]);
+        self.pos += n;
+        if self.pos == self.prefix.len() {
+            self.prefix = Vec::new();
+            self.pos = 0;
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Prepended<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Prepended<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Prepended<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_prefix_is_replayed() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let client = runtime.block_on(io::write_all(client, b"HELLO world")).expect("failed to write").0;
+        // The router took the magic off the connection to pick a handler.
+        let (server, magic) = runtime.block_on(io::read_exact(server, [0u8; 5])).expect("failed to read");
+        assert_eq!(&magic, b"HELLO");
+
+        let server = server.prepend(magic.to_vec());
+        assert_eq!(server.remaining_prefix(), b"HELLO");
+        drop(client);
+        let (server, stream) = runtime.block_on(io::read_to_end(server, Vec::new())).expect("failed to read");
+        assert_eq!(&stream[..], b"HELLO world");
+        assert!(server.remaining_prefix().is_empty());
+    }
+}
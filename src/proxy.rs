@@ -0,0 +1,121 @@
+use std::io;
+
+use futures::{Async, Future, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Copy data both ways between `a` and `b`, e.g. to proxy a connection to another one.
+///
+/// Tokio 0.1 lacks `copy_bidirectional`, this is its counterpart: once either side
+/// reaches the end of file, the other is shut down for writing; the future resolves to
+/// the bytes copied from `a` to `b` and from `b` to `a` once both directions are done.
+/// Windows pipes can't be shut down for writing, so proxying between them only ends
+/// once both ends hung up.
+pub fn copy_bidirectional<A, B>(a: A, b: B) -> CopyBidirectional<A, B>
+    where A: AsyncRead + AsyncWrite, B: AsyncRead + AsyncWrite
+{
+    CopyBidirectional { a, b, a_to_b: Transfer::new(), b_to_a: Transfer::new() }
+}
+
+/// Future returned by `copy_bidirectional`.
+pub struct CopyBidirectional<A, B> {
+    a: A,
+    b: B,
+    a_to_b: Transfer,
+    b_to_a: Transfer,
+}
+
+struct Transfer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    done: bool,
+}
+
+impl Transfer {
+    fn new() -> Self {
+        Transfer { buf: vec![0; BUFFER_SIZE].into_boxed_slice(), pos: 0, cap: 0, amt: 0, read_done: false, done: false }
+    }
+
+    fn poll_copy<R: AsyncRead, W: AsyncWrite>(&mut self, reader: &mut R, writer: &mut W) -> Poll<(), io::Error> {
+        while !self.done {
+            if self.pos == self.cap && !self.read_done {
+                let n = try_ready!(reader.poll_read(&mut self.buf));
+                if n == 0 {
+                    self.read_done = true;
+                } else {
+                    self.pos = 0;
+                    self.cap = n;
+                }
+            }
+
+            while self.pos < self.cap {
+                let n = try_ready!(writer.poll_write(&self.buf[self.pos..self.cap]));
+                if n == 0 {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "write zero byte into writer"));
+                }
+                self.pos += n;
+                self.amt += n as u64;
+            }
+
+            if self.read_done {
+                try_ready!(writer.poll_flush());
+                try_ready!(writer.shutdown());
+                self.done = true;
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<A, B> Future for CopyBidirectional<A, B>
+    where A: AsyncRead + AsyncWrite, B: AsyncRead + AsyncWrite
+{
+    type Item = (u64, u64);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<(u64, u64), io::Error> {
+        let a_to_b = self.a_to_b.poll_copy(&mut self.a, &mut self.b)?;
+        let b_to_a = self.b_to_a.poll_copy(&mut self.b, &mut self.a)?;
+        match (a_to_b, b_to_a) {
+            (Async::Ready(()), Async::Ready(())) => Ok(Async::Ready((self.a_to_b.amt, self.b_to_a.amt))),
+            _ => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::oneshot;
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+    use super::copy_bidirectional;
+
+    #[test]
+    fn test_proxy_between_pairs() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, proxy_front) = connected_pair(&mut runtime);
+        let (proxy_back, server) = connected_pair(&mut runtime);
+        let copied = oneshot::spawn(copy_bidirectional(proxy_front, proxy_back), &runtime.executor());
+
+        let client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (server, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+        let server = runtime.block_on(io::write_all(server, b"pong!")).expect("failed to write").0;
+        let (client, buf) = runtime.block_on(io::read_exact(client, [0u8; 5])).expect("failed to read");
+        assert_eq!(&buf, b"pong!");
+
+        // Each end closing is passed on to the other one.
+        let client = runtime.block_on(io::shutdown(client)).expect("failed to shut down");
+        let (server, rest) = runtime.block_on(io::read_to_end(server, Vec::new())).expect("failed to read");
+        assert!(rest.is_empty());
+        drop(server);
+        let (_, rest) = runtime.block_on(io::read_to_end(client, Vec::new())).expect("failed to read");
+        assert!(rest.is_empty());
+        assert_eq!(runtime.block_on(copied).expect("proxy failed"), (4, 5));
+    }
+}
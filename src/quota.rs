@@ -0,0 +1,154 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use futures::Poll;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Connection wrapper enforcing a total-bytes quota in each direction.
+///
+/// Created by `IpcConnection::with_quota`. Once more than `max_read` bytes were
+/// received, or a write would exceed `max_write` bytes, the operation fails with
+/// an `io::ErrorKind::Other` "quota exceeded" error. Meant as a guard against
+/// abusive peers, so the bytes read past the quota are discarded.
+pub struct Quota<S> {
+    inner: S,
+    max_read: u64,
+    max_write: u64,
+    read: u64,
+    written: u64,
+}
+
+impl<S> Quota<S> {
+    /// Wrap `inner` with the given read and write quotas.
+    pub fn new(inner: S, max_read: u64, max_write: u64) -> Self {
+        Quota { inner, max_read, max_write, read: 0, written: 0 }
+    }
+
+    /// Total bytes received so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Total bytes sent so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the connection, dropping the quota.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+fn quota_exceeded() -> io::Error {
+    io::Error::other("quota exceeded")
+}
+
+impl<S: Read> Read for Quota<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read > self.max_read {
+            return Err(quota_exceeded());
+        }
+
+        // Allow a single byte past the quota through, so that reaching it exactly is fine.
+        let allowed = cmp::min(buf.len() as u64, (self.max_read - self.read).saturating_add(1)) as usize;
+        let n = self.inner.read(&mut buf[..allowed])?;
+        self.read += n as u64;
+        if self.read > self.max_read {
+            return Err(quota_exceeded());
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Quota<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.write(buf);
+        }
+        if self.written >= self.max_write {
+            return Err(quota_exceeded());
+        }
+
+        let allowed = cmp::min(buf.len() as u64, self.max_write - self.written) as usize;
+        let n = self.inner.write(&buf[..allowed])?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Quota<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Quota<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_read_quota_exceeded() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let _client = runtime.block_on(io::write_all(client, b"0123456789")).expect("failed to write");
+
+        let server = server.with_quota(4, 4);
+        let (server, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("reading within the quota should succeed");
+        assert_eq!(&buf, b"0123");
+        assert_eq!(server.bytes_read(), 4);
+
+        let err = match runtime.block_on(io::read_exact(server, [0u8; 4])) {
+            Ok(_) => panic!("reading past the quota should fail"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.to_string(), "quota exceeded");
+    }
+
+    #[test]
+    fn test_write_quota_exceeded() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, _server) = connected_pair(&mut runtime);
+
+        let client = client.with_quota(0, 4);
+        let client = runtime.block_on(io::write_all(client, b"0123")).expect("writing within the quota should succeed").0;
+        assert_eq!(client.bytes_written(), 4);
+        assert!(runtime.block_on(io::write_all(client, b"4")).is_err());
+    }
+
+    #[test]
+    fn test_unlimited_quota() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let client = client.with_quota(u64::MAX, u64::MAX);
+        let client = runtime.block_on(io::write_all(client, b"0123")).expect("failed to write").0;
+        assert_eq!(client.bytes_written(), 4);
+
+        let server = server.with_quota(u64::MAX, u64::MAX);
+        let (server, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"0123");
+        assert_eq!(server.bytes_read(), 4);
+    }
+}
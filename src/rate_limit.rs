@@ -0,0 +1,220 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use futures::{stream::Stream, Async, Future, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::timer::Delay;
+
+/// Stream of incoming connections accepted at a limited rate.
+///
+/// Created by `Endpoint::incoming_rate_limited`. A token bucket holding up to `burst`
+/// tokens is refilled at `max_per_sec` tokens a second, each accept taking one. Excess
+/// connections aren't dropped, they wait to be accepted until a token is available.
+pub struct RateLimited<S> {
+    inner: S,
+    max_per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    delay: Option<Delay>,
+}
+
+impl<S> RateLimited<S> {
+    pub(crate) fn new(inner: S, max_per_sec: u32, burst: u32) -> Self {
+        assert!(max_per_sec > 0, "max_per_sec must be positive");
+        let burst = f64::from(burst.max(1));
+        RateLimited {
+            inner,
+            max_per_sec: f64::from(max_per_sec),
+            burst,
+            tokens: burst,
+            refilled_at: Instant::now(),
+            delay: None,
+        }
+    }
+
+    /// Reference to the wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.refilled_at;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.max_per_sec).min(self.burst);
+        self.refilled_at = now;
+    }
+}
+
+impl<S: Stream<Error = io::Error>> Stream for RateLimited<S> {
+    type Item = S::Item;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<S::Item>, io::Error> {
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                try_ready!(delay.poll().map_err(io::Error::other));
+            }
+            self.delay = None;
+
+            self.refill();
+            if self.tokens >= 1.0 {
+                break;
+            }
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.max_per_sec);
+            self.delay = Some(Delay::new(Instant::now() + wait));
+        }
+
+        let item = try_ready!(self.inner.poll());
+        if item.is_some() {
+            self.tokens -= 1.0;
+        }
+        Ok(Async::Ready(item))
+    }
+}
+
+/// Connection wrapper reading at most a given number of bytes a second.
+///
+/// Created by `IpcConnection::read_rate_limited`. A token bucket holding up to a
+/// second's worth of bytes is refilled continuously, each byte read taking one token.
+/// Once it runs dry, reads wait for a twentieth of a second's worth of tokens, or the
+/// size of the read buffer if smaller, so that a greedy peer fills the kernel buffers
+/// and is slowed down in turn. Writes aren't limited.
+pub struct ReadRateLimited<S> {
+    inner: S,
+    bytes_per_sec: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    delay: Option<Delay>,
+}
+
+impl<S> ReadRateLimited<S> {
+    pub(crate) fn new(inner: S, bytes_per_sec: u32) -> Self {
+        assert!(bytes_per_sec > 0, "bytes_per_sec must be positive");
+        let bytes_per_sec = f64::from(bytes_per_sec);
+        ReadRateLimited { inner, bytes_per_sec, tokens: bytes_per_sec, refilled_at: Instant::now(), delay: None }
+    }
+
+    /// Reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the connection, dropping the limit.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.refilled_at;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.bytes_per_sec).min(self.bytes_per_sec);
+        self.refilled_at = now;
+    }
+}
+
+impl<S: Read> Read for ReadRateLimited<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.read(buf);
+        }
+
+        let wanted = (buf.len() as f64).min((self.bytes_per_sec / 20.0).max(1.0));
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                if let Async::NotReady = delay.poll().map_err(io::Error::other)? {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+            }
+            self.delay = None;
+
+            self.refill();
+            if self.tokens >= wanted {
+                break;
+            }
+            let wait = Duration::from_secs_f64((wanted - self.tokens) / self.bytes_per_sec);
+            self.delay = Some(Delay::new(Instant::now() + wait));
+        }
+
+        let allowed = cmp::min(buf.len(), self.tokens as usize);
+        let n = self.inner.read(&mut buf[..allowed])?;
+        self.tokens -= n as f64;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for ReadRateLimited<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for ReadRateLimited<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for ReadRateLimited<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use futures::Stream;
+    use tokio;
+
+    use tests::connected_pair;
+    use {Endpoint, IpcConnection};
+
+    #[test]
+    fn test_accepts_are_paced() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+        let path = ::dummy_endpoint();
+
+        let incoming = Endpoint::new(path.clone()).incoming_rate_limited(&handle, 20, 1)
+            .expect("failed to open up a new pipe/socket");
+        let clients = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                (0..5).map(|_| IpcConnection::connect(&path, &handle).expect("failed to connect")).collect::<Vec<_>>()
+            })
+        };
+
+        let start = Instant::now();
+        let accepted = runtime.block_on(incoming.take(5).collect()).expect("failed to accept");
+        let elapsed = start.elapsed();
+        assert_eq!(accepted.len(), 5);
+        // One connection right away, then one every 50ms.
+        assert!(elapsed >= Duration::from_millis(180), "accepts weren't paced: {:?}", elapsed);
+        clients.join().unwrap();
+    }
+
+    #[test]
+    fn test_reads_are_paced() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        // A burst of two seconds' worth, of which the first second's worth is read right away.
+        let _client = runtime.block_on(tokio::io::write_all(client, vec![7u8; 16 * 1024])).expect("failed to write");
+        let server = server.read_rate_limited(8 * 1024);
+        let start = Instant::now();
+        let (_, buf) = runtime.block_on(tokio::io::read_exact(server, vec![0u8; 16 * 1024])).expect("failed to read");
+        let elapsed = start.elapsed();
+        assert!(buf.iter().all(|&byte| byte == 7));
+        assert!(elapsed >= Duration::from_millis(900), "reads weren't paced: {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "reads took {:?}", elapsed);
+    }
+}
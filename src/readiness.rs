@@ -0,0 +1,81 @@
+use std::io;
+
+use futures::{Async, Future, Poll};
+
+use IpcConnection;
+
+/// Future resolving to the connection once it is readable.
+///
+/// Created by `IpcConnection::readable`.
+pub struct Readable {
+    connection: Option<IpcConnection>,
+}
+
+/// Future resolving to the connection once it is writable.
+///
+/// Created by `IpcConnection::writable`.
+pub struct Writable {
+    connection: Option<IpcConnection>,
+}
+
+impl Readable {
+    pub(crate) fn new(connection: IpcConnection) -> Self {
+        Readable { connection: Some(connection) }
+    }
+}
+
+impl Writable {
+    pub(crate) fn new(connection: IpcConnection) -> Self {
+        Writable { connection: Some(connection) }
+    }
+}
+
+impl Future for Readable {
+    type Item = IpcConnection;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        try_ready!(self.connection.as_mut().expect("poll a Readable after it's done").poll_read_ready());
+        Ok(Async::Ready(self.connection.take().expect("checked above; qed")))
+    }
+}
+
+impl Future for Writable {
+    type Item = IpcConnection;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        try_ready!(self.connection.as_mut().expect("poll a Writable after it's done").poll_write_ready());
+        Ok(Async::Ready(self.connection.take().expect("checked above; qed")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_writable_fresh_connection() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, _server) = connected_pair(&mut runtime);
+
+        runtime.block_on(client.writable()).expect("fresh connection should become writable");
+    }
+
+    #[test]
+    fn test_readable_after_peer_write() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let mut server = runtime.block_on(server.readable()).expect("connection should become readable");
+
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).expect("data should be available once readable");
+        assert_eq!(&buf, b"ping");
+    }
+}
@@ -0,0 +1,130 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use tokio::codec::{Framed, LengthDelimitedCodec};
+use tokio::timer::Delay;
+
+use IpcConnection;
+
+/// Request/response helper over a single connection.
+///
+/// Requests and replies are length-delimited frames. `call` consumes the helper and
+/// resolves to it together with the reply, so calls can't interleave on the connection.
+/// A timed out call drops the connection, as a late reply would answer the next request.
+pub struct ReqRep {
+    framed: Framed<IpcConnection, LengthDelimitedCodec>,
+    timeout: Duration,
+}
+
+impl ReqRep {
+    /// Wrap a connection, failing calls whose reply takes longer than `timeout`.
+    pub fn new(connection: IpcConnection, timeout: Duration) -> Self {
+        ReqRep { framed: Framed::new(connection, LengthDelimitedCodec::new()), timeout }
+    }
+
+    /// Send `request` and wait for the reply.
+    pub fn call(self, request: Bytes) -> Call {
+        Call { reqrep: Some(self), request: Some(request), flushed: false, deadline: None }
+    }
+
+    /// Unwrap the connection, discarding any buffered data.
+    pub fn into_inner(self) -> IpcConnection {
+        self.framed.into_inner()
+    }
+}
+
+/// Future of a single call, created by `ReqRep::call`.
+pub struct Call {
+    reqrep: Option<ReqRep>,
+    request: Option<Bytes>,
+    flushed: bool,
+    deadline: Option<Delay>,
+}
+
+impl Call {
+    fn poll_reply(&mut self) -> Poll<Bytes, io::Error> {
+        let reqrep = self.reqrep.as_mut().expect("poll a Call after it's done");
+
+        if let Some(request) = self.request.take() {
+            if let AsyncSink::NotReady(request) = reqrep.framed.start_send(request)? {
+                self.request = Some(request);
+                return Ok(Async::NotReady);
+            }
+        }
+        if !self.flushed {
+            try_ready!(reqrep.framed.poll_complete());
+            self.flushed = true;
+        }
+
+        match try_ready!(reqrep.framed.poll()) {
+            Some(reply) => Ok(Async::Ready(reply.freeze())),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the reply")),
+        }
+    }
+}
+
+impl Future for Call {
+    type Item = (ReqRep, Bytes);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        if let Async::Ready(reply) = self.poll_reply()? {
+            let reqrep = self.reqrep.take().expect("checked above; qed");
+            return Ok(Async::Ready((reqrep, reply)));
+        }
+
+        let timeout = self.reqrep.as_ref().expect("checked above; qed").timeout;
+        let deadline = self.deadline.get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+        match deadline.poll().map_err(io::Error::other)? {
+            Async::Ready(()) => {
+                self.reqrep.take();
+                Err(io::Error::new(io::ErrorKind::TimedOut, "call timed out"))
+            },
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::{Future, Sink, Stream};
+    use tokio::{self, codec::{Framed, LengthDelimitedCodec}};
+
+    use tests::connected_pair;
+    use super::ReqRep;
+
+    #[test]
+    fn test_sequential_calls() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let (sink, stream) = Framed::new(server, LengthDelimitedCodec::new()).split();
+        runtime.spawn(sink.send_all(stream.map(|request| request.freeze())).map(|_| ()).map_err(|err| panic!("echo server failed: {:?}", err)));
+
+        let mut reqrep = ReqRep::new(client, Duration::from_secs(5));
+        for request in &["first", "second", "third"] {
+            let (next, reply) = runtime.block_on(reqrep.call(Bytes::from(*request))).expect("call failed");
+            assert_eq!(reply, Bytes::from(*request));
+            reqrep = next;
+        }
+    }
+
+    #[test]
+    fn test_call_timeout() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, _server) = connected_pair(&mut runtime);
+
+        let reqrep = ReqRep::new(client, Duration::from_millis(50));
+        let err = match runtime.block_on(reqrep.call(Bytes::from("unanswered"))) {
+            Ok(_) => panic!("call without a reply should time out"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+}
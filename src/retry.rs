@@ -0,0 +1,103 @@
+use std::cmp;
+use std::time::Duration;
+
+/// Backoff policy used when (re)connecting to an endpoint.
+///
+/// The delay before retry `n` (starting at 0) is `initial_delay * multiplier^n`,
+/// capped at `max_delay`. With a non-zero `jitter` the delay is picked uniformly
+/// from `[delay * (1 - jitter), delay]`, so that many clients restarting at once
+/// don't hammer the server in lockstep.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Maximum number of attempts, including the first one.
+    pub max_attempts: usize,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each failed attempt.
+    pub multiplier: u32,
+    /// Fraction (`0.0..=1.0`) of the delay that is randomized. `0.0` disables jitter.
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Same policy without jitter, handy for deterministic tests.
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = 0.0;
+        self
+    }
+
+    /// Delay to wait before retry number `retry` (starting at 0).
+    pub fn delay(&self, retry: usize) -> Duration {
+        extern crate rand;
+
+        let max_delay = millis(self.max_delay);
+        let mut delay = millis(self.initial_delay);
+        for _ in 0..retry {
+            delay = cmp::min(delay.saturating_mul(u64::from(self.multiplier)), max_delay);
+        }
+        let delay = cmp::min(delay, max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 || delay == 0 {
+            return Duration::from_millis(delay);
+        }
+
+        let low = delay as f64 * (1.0 - jitter);
+        let delay = rand::Rng::gen_range(&mut rand::thread_rng(), low, delay as f64 + 1.0);
+        Duration::from_millis(delay as u64)
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs().saturating_mul(1000) + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryConfig;
+
+    #[test]
+    fn test_delay_without_jitter() {
+        let config = RetryConfig {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(100),
+            multiplier: 2,
+            jitter: 0.0,
+        };
+
+        let delays: Vec<_> = (0..6).map(|retry| config.delay(retry)).collect();
+        assert_eq!(delays, [10, 20, 40, 80, 100, 100].iter().map(|ms| Duration::from_millis(*ms)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_delay_with_jitter() {
+        let config = RetryConfig {
+            initial_delay: Duration::from_millis(1000),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        let delays: Vec<_> = (0..20).map(|retry| config.delay(retry)).collect();
+        assert!(delays.iter().all(|d| *d >= Duration::from_millis(500) && *d <= Duration::from_millis(1000)));
+        assert!(delays.iter().any(|d| *d != delays[0]), "jittered delays should vary: {:?}", delays);
+        assert_eq!(config.without_jitter().delay(3), Duration::from_millis(1000));
+    }
+}
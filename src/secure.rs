@@ -0,0 +1,297 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use futures::{Async, Future, Poll};
+use snow::{HandshakeState, TransportState};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+// Largest Noise message, frames carry a 2-byte big-endian length as the spec suggests.
+const MAX_MESSAGE_LEN: usize = 65535;
+// Authentication tag added to every transport message.
+const TAG_LEN: usize = 16;
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Length-prefixed Noise messages over a connection.
+struct Frames<S> {
+    inner: S,
+    // Frames not yet written to `inner`.
+    write_buf: Vec<u8>,
+    // Raw bytes read from `inner`, not yet forming a complete frame.
+    read_buf: Vec<u8>,
+}
+
+impl<S> Frames<S> {
+    fn push(&mut self, message: &[u8]) {
+        self.write_buf.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        self.write_buf.extend_from_slice(message);
+    }
+
+    /// Length of the complete frame at the start of `read_buf`, if any.
+    fn frame_len(&self) -> Option<usize> {
+        if self.read_buf.len() < 2 {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.read_buf[0], self.read_buf[1]]) as usize;
+        if self.read_buf.len() < 2 + len {
+            return None;
+        }
+        Some(len)
+    }
+}
+
+impl<S: Read> Frames<S> {
+    /// Wait for a complete frame at the start of `read_buf`, returning its length.
+    fn fill(&mut self) -> io::Result<usize> {
+        loop {
+            if let Some(len) = self.frame_len() {
+                return Ok(len);
+            }
+            let mut chunk = [0u8; 8192];
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of a frame"));
+            }
+            self.read_buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+impl<S: Write> Frames<S> {
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.write_buf.is_empty() {
+            let n = self.inner.write(&self.write_buf)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write encrypted frame"));
+            }
+            self.write_buf.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+/// Future performing a Noise handshake, created by `IpcConnection::secure`.
+///
+/// Resolves to the encrypted connection, or fails with `InvalidData` if the handshake
+/// failed, e.g. because the peer doesn't have the expected key.
+pub struct Handshake<S> {
+    state: Option<(HandshakeState, Frames<S>)>,
+}
+
+impl<S> Handshake<S> {
+    /// Handshake over `inner` according to `state`, built with `snow::Builder`.
+    pub fn new(inner: S, state: HandshakeState) -> Self {
+        Handshake {
+            state: Some((state, Frames { inner, write_buf: Vec::new(), read_buf: Vec::new() })),
+        }
+    }
+}
+
+impl<S: Read + Write> Handshake<S> {
+    fn advance(state: &mut HandshakeState, frames: &mut Frames<S>) -> io::Result<()> {
+        loop {
+            frames.flush()?;
+            if state.is_handshake_finished() {
+                return Ok(());
+            }
+
+            let mut message = vec![0u8; MAX_MESSAGE_LEN];
+            if state.is_my_turn() {
+                let len = state.write_message(&[], &mut message).map_err(noise_error)?;
+                frames.push(&message[..len]);
+            } else {
+                let len = frames.fill()?;
+                state.read_message(&frames.read_buf[2..2 + len], &mut message).map_err(noise_error)?;
+                frames.read_buf.drain(..2 + len);
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Future for Handshake<S> {
+    type Item = SecureTransport<S>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<SecureTransport<S>, io::Error> {
+        {
+            let (ref mut state, ref mut frames) = *self.state.as_mut().expect("poll a Handshake after it's done");
+            match Self::advance(state, frames) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                result => result?,
+            }
+        }
+
+        let (state, frames) = self.state.take().expect("checked above; qed");
+        let transport = state.into_transport_mode().map_err(noise_error)?;
+        Ok(Async::Ready(SecureTransport { transport, frames, decoded: Vec::new(), decoded_pos: 0 }))
+    }
+}
+
+/// Connection encrypted with the Noise protocol.
+///
+/// Created by a `Handshake`. Every write is encrypted into its own length-prefixed
+/// Noise message of at most 64KB, which also authenticates it; tampered or replayed
+/// messages fail reads with `InvalidData`.
+pub struct SecureTransport<S> {
+    transport: TransportState,
+    frames: Frames<S>,
+    // Decrypted data of the last message and how much of it was read.
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+}
+
+impl<S> SecureTransport<S> {
+    /// Static public key the peer authenticated with, if the handshake pattern has one.
+    pub fn remote_static(&self) -> Option<&[u8]> {
+        self.transport.get_remote_static()
+    }
+
+    /// Reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.frames.inner
+    }
+}
+
+impl<S: Read> Read for SecureTransport<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded_pos == self.decoded.len() {
+            if self.frames.frame_len().is_none() && self.frames.read_buf.is_empty() {
+                // Plain end of file only between messages.
+                let mut chunk = [0u8; 8192];
+                let n = self.frames.inner.read(&mut chunk)?;
+                if n == 0 {
+                    return Ok(0);
+                }
+                self.frames.read_buf.extend_from_slice(&chunk[..n]);
+            }
+            let len = self.frames.fill()?;
+            self.decoded.resize(len, 0);
+            let decoded_len = self.transport
+                .read_message(&self.frames.read_buf[2..2 + len], &mut self.decoded)
+                .map_err(noise_error)?;
+            self.decoded.truncate(decoded_len);
+            self.decoded_pos = 0;
+            self.frames.read_buf.drain(..2 + len);
+        }
+
+        let n = cmp::min(buf.len(), self.decoded.len() - self.decoded_pos);
+        buf[..n].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + n]);
+        self.decoded_pos += n;
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for SecureTransport<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only take new data once the previous messages are out, to bound the buffer.
+        self.frames.flush()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = cmp::min(buf.len(), MAX_MESSAGE_LEN - TAG_LEN);
+        let mut message = vec![0u8; len + TAG_LEN];
+        let message_len = self.transport.write_message(&buf[..len], &mut message).map_err(noise_error)?;
+        self.frames.push(&message[..message_len]);
+        match self.frames.flush() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+            result => result?,
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.frames.flush()?;
+        self.frames.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for SecureTransport<S> {
+    unsafe fn prepare_uninitialized_buffer(&self, _buf: &mut [u8]) -> bool {
+        false
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for SecureTransport<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.frames.flush() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            result => result?,
+        }
+        self.frames.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use futures::{sync::oneshot, Future};
+    use snow::Builder;
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    const XX: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+    const NK: &str = "Noise_NK_25519_ChaChaPoly_BLAKE2s";
+
+    #[test]
+    fn test_handshake_and_exchange() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let client_keys = Builder::new(XX.parse().unwrap()).generate_keypair().unwrap();
+        let server_keys = Builder::new(XX.parse().unwrap()).generate_keypair().unwrap();
+        let client_public = client_keys.public.clone();
+
+        let server_state = Builder::new(XX.parse().unwrap())
+            .local_private_key(&server_keys.private).unwrap()
+            .build_responder().unwrap();
+        let (tx, rx) = oneshot::channel();
+        runtime.spawn(server.secure(server_state)
+            .and_then(|server| io::read_exact(server, [0u8; 6]))
+            .and_then(|(server, buf)| io::write_all(server, buf))
+            .and_then(|(server, _)| io::flush(server))
+            .map(|server| { let _ = tx.send(server.remote_static().map(|key| key.to_vec())); })
+            .map_err(|err| panic!("server failed: {:?}", err)));
+
+        let client_state = Builder::new(XX.parse().unwrap())
+            .local_private_key(&client_keys.private).unwrap()
+            .build_initiator().unwrap();
+        let client = runtime.block_on(client.secure(client_state)).expect("handshake failed");
+        assert_eq!(client.remote_static(), Some(&server_keys.public[..]));
+
+        // The server only echoes what it could decrypt.
+        let client = runtime.block_on(io::write_all(client, b"secret").and_then(|(client, _)| io::flush(client)))
+            .expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(client, [0u8; 6])).expect("failed to read");
+        assert_eq!(&buf, b"secret");
+        assert_eq!(runtime.block_on(rx).expect("server dropped"), Some(client_public));
+    }
+
+    #[test]
+    fn test_handshake_with_wrong_key() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let server_keys = Builder::new(NK.parse().unwrap()).generate_keypair().unwrap();
+        let other_keys = Builder::new(NK.parse().unwrap()).generate_keypair().unwrap();
+
+        // The client expects another server.
+        let client_state = Builder::new(NK.parse().unwrap())
+            .remote_public_key(&other_keys.public).unwrap()
+            .build_initiator().unwrap();
+        runtime.spawn(client.secure(client_state).map(|_| ()).map_err(|_| ()));
+
+        let server_state = Builder::new(NK.parse().unwrap())
+            .local_private_key(&server_keys.private).unwrap()
+            .build_responder().unwrap();
+        let err = match runtime.block_on(server.secure(server_state)) {
+            Ok(_) => panic!("handshake should fail"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
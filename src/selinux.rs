@@ -0,0 +1,87 @@
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use libc;
+
+const ATTRIBUTE: &[u8] = b"security.selinux\0";
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte"))
+}
+
+/// Label the file at `path` with the SELinux `context`, e.g. `system_u:object_r:my_sock_t:s0`.
+pub fn set_context(path: &Path, context: &str) -> io::Result<()> {
+    let c_path = c_path(path)?;
+    let result = unsafe {
+        libc::setxattr(c_path.as_ptr(), ATTRIBUTE.as_ptr() as *const libc::c_char,
+            context.as_ptr() as *const libc::c_void, context.len(), 0)
+    };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    let (kind, reason) = match err.raw_os_error() {
+        Some(libc::EINVAL) => (io::ErrorKind::InvalidInput, "the context is invalid".to_owned()),
+        Some(libc::ENOTSUP) => (io::ErrorKind::Unsupported, "the file system or kernel doesn't support SELinux labels".to_owned()),
+        _ => (err.kind(), err.to_string()),
+    };
+    Err(io::Error::new(kind, format!("failed to set the SELinux context of {} to {:?}: {}", path.display(), context, reason)))
+}
+
+/// SELinux context of the file at `path`.
+#[cfg(test)]
+pub fn context(path: &Path) -> io::Result<String> {
+    let c_path = c_path(path)?;
+    let mut buf = vec![0u8; 256];
+    let len = unsafe {
+        libc::getxattr(c_path.as_ptr(), ATTRIBUTE.as_ptr() as *const libc::c_char,
+            buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+    };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+    // The kernel reports the context nul-terminated.
+    if buf.last() == Some(&0) {
+        buf.pop();
+    }
+    String::from_utf8(buf).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "context is not UTF-8"))
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    use tokio;
+
+    use Endpoint;
+    use super::context;
+
+    #[test]
+    fn test_socket_context() {
+        // Labels the socket like the temporary directory, a context the policy accepts.
+        let label = match context(&env::temp_dir()) {
+            Ok(label) => label,
+            Err(_) => return, // No SELinux here.
+        };
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let path = ::dummy_endpoint();
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_selinux_context(label.clone());
+        let _incoming = endpoint.incoming(runtime.reactor()).expect("failed to bind with the context");
+        assert_eq!(context(Path::new(&path)).expect("failed to read back the context"), label);
+
+        let mut endpoint = Endpoint::new(::dummy_endpoint());
+        endpoint.set_selinux_context("not a context".to_owned());
+        match endpoint.incoming(runtime.reactor()) {
+            Ok(_) => panic!("bound with an invalid context"),
+            Err(err) => assert!(err.to_string().contains("SELinux context"), "unclear error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+
+use futures::{Async, Future, Poll};
+use tokio::io::AsyncWrite;
+
+use IpcConnection;
+
+// Upper bound for a single `sendfile` call, roughly what the kernel moves per call anyway.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAX_SENDFILE_CHUNK: usize = 0x7fff_f000;
+
+// Size of the intermediate buffer used by the copy fallback.
+const COPY_CHUNK: usize = 64 * 1024;
+
+/// Future that streams a region of a file over an `IpcConnection`.
+///
+/// Created by `IpcConnection::send_file`. Resolves to the connection, the file
+/// and the number of bytes actually sent, which is less than requested if the
+/// end of the file was reached first.
+pub struct SendFile {
+    state: Option<(IpcConnection, File)>,
+    offset: u64,
+    remaining: usize,
+    sent: usize,
+}
+
+impl SendFile {
+    pub(crate) fn new(connection: IpcConnection, file: File, offset: u64, len: usize) -> Self {
+        SendFile {
+            state: Some((connection, file)),
+            offset,
+            remaining: len,
+            sent: 0,
+        }
+    }
+}
+
+impl Future for SendFile {
+    type Item = (IpcConnection, File, usize);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        {
+            let (ref mut connection, ref file) = *self.state.as_mut().expect("poll a SendFile after it's done");
+            while self.remaining > 0 {
+                let n = try_ready!(send_chunk(connection, file, self.offset, self.remaining));
+                if n == 0 {
+                    trace!("End of file reached after sending {} bytes", self.sent);
+                    break;
+                }
+                self.offset += n as u64;
+                self.remaining -= n;
+                self.sent += n;
+            }
+        }
+
+        let (connection, file) = self.state.take().expect("checked above; qed");
+        Ok(Async::Ready((connection, file, self.sent)))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_chunk(connection: &mut IpcConnection, file: &File, offset: u64, len: usize) -> Poll<usize, io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    try_ready!(connection.inner.poll_write_ready());
+
+    let mut off = offset as ::libc::off_t;
+    let count = cmp::min(len, MAX_SENDFILE_CHUNK);
+    // A peer that is gone raises `SIGPIPE`, which a host application may not ignore.
+    let res = ::sockopt::without_sigpipe(|| {
+        match unsafe { ::libc::sendfile(connection.inner.as_raw_fd(), file.as_raw_fd(), &mut off, count) } {
+            -1 => Err(io::Error::last_os_error()),
+            sent => Ok(sent as usize),
+        }
+    });
+    let err = match res {
+        Ok(sent) => return Ok(Async::Ready(sent)),
+        Err(err) => err,
+    };
+    match err.raw_os_error() {
+        // The socket is full. A regular write of the next chunk either makes progress
+        // or re-arms the reactor readiness, which `sendfile` alone cannot do.
+        Some(::libc::EAGAIN) | Some(::libc::EINTR) => copy_chunk(connection, file, offset, len),
+        // The file does not support `sendfile` (e.g. some special filesystems).
+        Some(::libc::EINVAL) | Some(::libc::ENOSYS) => {
+            trace!("sendfile is not supported for this file, falling back to copy: {:?}", err);
+            copy_chunk(connection, file, offset, len)
+        },
+        _ => Err(err),
+    }
+}
+
+// `TransmitFile` only works with sockets, so named pipes always go through the copy loop.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn send_chunk(connection: &mut IpcConnection, file: &File, offset: u64, len: usize) -> Poll<usize, io::Error> {
+    copy_chunk(connection, file, offset, len)
+}
+
+fn copy_chunk(connection: &mut IpcConnection, file: &File, offset: u64, len: usize) -> Poll<usize, io::Error> {
+    let mut buf = [0u8; COPY_CHUNK];
+    let count = cmp::min(len, buf.len());
+    let read = read_at(file, &mut buf[..count], offset)?;
+    if read == 0 {
+        return Ok(Async::Ready(0));
+    }
+    connection.poll_write(&buf[..read])
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    use futures::{sync::oneshot, Future, Stream};
+    use tokio::{self, io};
+
+    use {dummy_endpoint, Endpoint, IpcConnection};
+
+    #[test]
+    fn test_send_file() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let handle = runtime.reactor().clone();
+
+        let contents: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+        let file_path = ::std::env::temp_dir().join(format!("susy-tokio-ipc-send-file-{}", ::std::process::id()));
+        File::create(&file_path).and_then(|mut f| f.write_all(&contents)).expect("failed to write temp file");
+
+        let path = dummy_endpoint();
+        let server = Endpoint::new(path.clone())
+            .incoming(&handle)
+            .expect("failed to open up a new pipe/socket")
+            .into_future()
+            .map_err(|(err, _)| err)
+            .and_then(|(conn, _)| {
+                let (conn, _) = conn.expect("no connection received");
+                io::read_to_end(conn, Vec::new()).map(|(_, buf)| buf)
+            });
+        let received = oneshot::spawn(server, &runtime.executor());
+
+        let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+        let file = File::open(&file_path).expect("failed to open temp file");
+        let offset = 1000;
+        let (_, _, sent) = runtime.block_on(client.send_file(file, offset as u64, contents.len()))
+            .expect("failed to send file");
+        assert_eq!(sent, contents.len() - offset);
+
+        let received = runtime.block_on(received).expect("failed to receive file");
+        assert!(received[..] == contents[offset..]);
+
+        fs::remove_file(&file_path).expect("failed to remove temp file");
+    }
+}
@@ -0,0 +1,70 @@
+use std::io;
+
+use futures::{Async, Future, Poll};
+
+use IpcConnection;
+
+/// Future closing both directions of a connection.
+///
+/// Created by `IpcConnection::shutdown_both`.
+pub struct ShutdownBoth {
+    connection: Option<IpcConnection>,
+}
+
+impl ShutdownBoth {
+    pub(crate) fn new(connection: IpcConnection) -> Self {
+        ShutdownBoth { connection: Some(connection) }
+    }
+}
+
+impl Future for ShutdownBoth {
+    type Item = IpcConnection;
+    type Error = io::Error;
+
+    #[cfg(not(windows))]
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        use std::net::Shutdown;
+
+        let connection = self.connection.take().expect("poll a ShutdownBoth after it's done");
+        connection.inner.shutdown(Shutdown::Both)?;
+        Ok(Async::Ready(connection))
+    }
+
+    #[cfg(windows)]
+    fn poll(&mut self) -> Poll<IpcConnection, io::Error> {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::winbase::PIPE_SERVER_END;
+        use win_pipe;
+
+        // Let the in-flight write complete first, disconnecting discards it.
+        try_ready!(self.connection.as_mut().expect("poll a ShutdownBoth after it's done").poll_write_ready());
+
+        let connection = self.connection.take().expect("checked above; qed");
+        if win_pipe::pipe_flags(connection.inner.as_raw_handle())? & PIPE_SERVER_END != 0 {
+            connection.inner.disconnect()?;
+        }
+        Ok(Async::Ready(connection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_shutdown_both() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let server = runtime.block_on(server.shutdown_both()).expect("failed to shut down");
+
+        let (server, _, n) = runtime.block_on(io::read(server, [0u8; 8])).expect("read after shutdown should see EOF");
+        assert_eq!(n, 0);
+        assert!(runtime.block_on(io::write_all(server, b"hello")).is_err(), "write after shutdown should fail");
+
+        let (_, _, n) = runtime.block_on(io::read(client, [0u8; 8])).expect("peer should see EOF");
+        assert_eq!(n, 0);
+    }
+}
@@ -0,0 +1,204 @@
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use libc::{self, c_int, socklen_t, timeval};
+
+/// Set a timeval socket option (`SO_RCVTIMEO`, `SO_SNDTIMEO`), `None` disabling it.
+///
+/// A zero duration would disable the timeout as well, so it is rejected like in `std`.
+pub fn set_timeout(fd: RawFd, option: c_int, timeout: Option<Duration>) -> io::Result<()> {
+    let value = match timeout {
+        Some(timeout) if timeout == Duration::new(0, 0) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot set a 0 duration timeout"));
+        },
+        Some(timeout) => {
+            let mut value = timeval {
+                tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+                tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+            };
+            // Round sub-microsecond timeouts up rather than disabling the timeout.
+            if value.tv_sec == 0 && value.tv_usec == 0 {
+                value.tv_usec = 1;
+            }
+            value
+        },
+        None => timeval { tv_sec: 0, tv_usec: 0 },
+    };
+
+    let result = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, option,
+            &value as *const timeval as *const _, mem::size_of::<timeval>() as socklen_t)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read back a timeval socket option, `None` meaning no timeout.
+pub fn timeout(fd: RawFd, option: c_int) -> io::Result<Option<Duration>> {
+    let mut value: timeval = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<timeval>() as socklen_t;
+    let result = unsafe {
+        libc::getsockopt(fd, libc::SOL_SOCKET, option, &mut value as *mut timeval as *mut _, &mut len)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if value.tv_sec == 0 && value.tv_usec == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(Duration::new(value.tv_sec as u64, value.tv_usec as u32 * 1000)))
+    }
+}
+
+/// Set an integer size socket option (`SO_RCVBUF`, `SO_SNDBUF`).
+pub fn set_buffer_size(fd: RawFd, option: c_int, size: usize) -> io::Result<()> {
+    if size > c_int::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "socket buffer size too large"));
+    }
+    let value = size as c_int;
+    let result = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, option, &value as *const c_int as *const _, mem::size_of::<c_int>() as socklen_t)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Read back an integer size socket option.
+pub fn buffer_size(fd: RawFd, option: c_int) -> io::Result<usize> {
+    let mut value: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as socklen_t;
+    let result = unsafe {
+        libc::getsockopt(fd, libc::SOL_SOCKET, option, &mut value as *mut c_int as *mut _, &mut len)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value as usize)
+}
+
+/// Write to a socket without raising `SIGPIPE` if the peer is gone, failing with
+/// `EPIPE` instead, as a host application may not ignore the signal like Rust does.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn write_nosignal(stream: &mut ::tokio_uds::UnixStream, buf: &[u8]) -> io::Result<usize> {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    use futures::Async;
+
+    if let Async::NotReady = stream.poll_write_ready()? {
+        return Err(io::ErrorKind::WouldBlock.into());
+    }
+    let written = unsafe { libc::send(stream.as_raw_fd(), buf.as_ptr() as *const _, buf.len(), libc::MSG_NOSIGNAL) };
+    if written >= 0 {
+        return Ok(written as usize);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() != io::ErrorKind::WouldBlock {
+        return Err(err);
+    }
+    // Readiness is only cleared by a plain write that would block, which may write
+    // after all if the socket drained meanwhile, or hit a peer that just left.
+    without_sigpipe(|| stream.write(buf))
+}
+
+/// Run `f` with `SIGPIPE` blocked on this thread, discarding a `SIGPIPE` it raised.
+///
+/// Clobbers `errno`, so `f` has to capture the error of the call it makes itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn without_sigpipe<T, F: FnOnce() -> T>(f: F) -> T {
+    use std::ptr;
+
+    unsafe {
+        let mut sigpipe = mem::zeroed();
+        libc::sigemptyset(&mut sigpipe);
+        libc::sigaddset(&mut sigpipe, libc::SIGPIPE);
+        let mut previous = mem::zeroed();
+        libc::pthread_sigmask(libc::SIG_BLOCK, &sigpipe, &mut previous);
+
+        // Leave a signal alone that was pending before.
+        let mut pending = mem::zeroed();
+        libc::sigpending(&mut pending);
+        let was_pending = libc::sigismember(&pending, libc::SIGPIPE) == 1;
+
+        let result = f();
+        if !was_pending {
+            let poll = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+            libc::sigtimedwait(&sigpipe, ptr::null_mut(), &poll);
+        }
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous, ptr::null_mut());
+        result
+    }
+}
+
+/// Make writes to the socket fail with `EPIPE` rather than raise `SIGPIPE`.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+pub fn set_nosigpipe(fd: RawFd) -> io::Result<()> {
+    let value: c_int = 1;
+    let result = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_NOSIGPIPE,
+            &value as *const c_int as *const _, mem::size_of::<c_int>() as socklen_t)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio;
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_socket_timeouts() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, _server) = connected_pair(&mut runtime);
+
+        assert_eq!(client.read_timeout().expect("failed to get read timeout"), None);
+
+        client.set_read_timeout(Some(Duration::from_millis(1500))).expect("failed to set read timeout");
+        client.set_write_timeout(Some(Duration::from_secs(2))).expect("failed to set write timeout");
+        assert_eq!(client.read_timeout().expect("failed to get read timeout"), Some(Duration::from_millis(1500)));
+        assert_eq!(client.write_timeout().expect("failed to get write timeout"), Some(Duration::from_secs(2)));
+
+        client.set_read_timeout(None).expect("failed to clear read timeout");
+        assert_eq!(client.read_timeout().expect("failed to get read timeout"), None);
+
+        assert!(client.set_write_timeout(Some(Duration::new(0, 0))).is_err());
+    }
+
+    #[test]
+    fn test_socket_buffer_sizes() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, _server) = connected_pair(&mut runtime);
+
+        client.set_recv_buffer_size(256 * 1024).expect("failed to set receive buffer size");
+        client.set_send_buffer_size(128 * 1024).expect("failed to set send buffer size");
+        // Linux doubles the value for bookkeeping overhead, and caps it at `rmem_max`/`wmem_max`.
+        let recv = client.recv_buffer_size().expect("failed to get receive buffer size");
+        let send = client.send_buffer_size().expect("failed to get send buffer size");
+        assert!(recv > 0 && send > 0);
+        if cfg!(target_os = "linux") {
+            let rmem_max = read_sysctl("/proc/sys/net/core/rmem_max");
+            let wmem_max = read_sysctl("/proc/sys/net/core/wmem_max");
+            assert_eq!(recv, 2 * (256 * 1024).min(rmem_max));
+            assert_eq!(send, 2 * (128 * 1024).min(wmem_max));
+        }
+    }
+
+    fn read_sysctl(path: &str) -> usize {
+        ::std::fs::read_to_string(path).ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(usize::MAX)
+    }
+}
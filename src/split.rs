@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use futures::{sync::BiLock, Async, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use IpcConnection;
+
+/// Owned read half of a connection, created by `IpcConnection::into_split`.
+pub struct OwnedReadHalf {
+    inner: BiLock<IpcConnection>,
+}
+
+/// Owned write half of a connection, created by `IpcConnection::into_split`.
+pub struct OwnedWriteHalf {
+    inner: BiLock<IpcConnection>,
+}
+
+/// Error reuniting halves that don't belong to the same connection, giving them back.
+pub struct ReuniteError(pub OwnedReadHalf, pub OwnedWriteHalf);
+
+pub(crate) fn into_split(connection: IpcConnection) -> (OwnedReadHalf, OwnedWriteHalf) {
+    let (read, write) = BiLock::new(connection);
+    (OwnedReadHalf { inner: read }, OwnedWriteHalf { inner: write })
+}
+
+impl OwnedReadHalf {
+    /// Put the connection back together, failing if `write` is the half of another one.
+    pub fn reunite(self, write: OwnedWriteHalf) -> Result<IpcConnection, ReuniteError> {
+        self.inner.reunite(write.inner).map_err(|err| {
+            ReuniteError(OwnedReadHalf { inner: err.0 }, OwnedWriteHalf { inner: err.1 })
+        })
+    }
+}
+
+impl OwnedWriteHalf {
+    /// Put the connection back together, failing if `read` is the half of another one.
+    pub fn reunite(self, read: OwnedReadHalf) -> Result<IpcConnection, ReuniteError> {
+        read.reunite(self)
+    }
+}
+
+impl fmt::Debug for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ReuniteError").finish()
+    }
+}
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "tried to reunite halves that are not from the same connection")
+    }
+}
+
+impl Error for ReuniteError {}
+
+fn would_block() -> io::Error {
+    io::Error::new(io::ErrorKind::WouldBlock, "connection is in use by the other half")
+}
+
+impl Read for OwnedReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.poll_lock() {
+            Async::Ready(mut connection) => connection.read(buf),
+            Async::NotReady => Err(would_block()),
+        }
+    }
+}
+
+impl AsyncRead for OwnedReadHalf {
+    unsafe fn prepare_uninitialized_buffer(&self, _buf: &mut [u8]) -> bool {
+        false
+    }
+}
+
+impl Write for OwnedWriteHalf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.poll_lock() {
+            Async::Ready(mut connection) => connection.write(buf),
+            Async::NotReady => Err(would_block()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.poll_lock() {
+            Async::Ready(mut connection) => connection.flush(),
+            Async::NotReady => Err(would_block()),
+        }
+    }
+}
+
+impl AsyncWrite for OwnedWriteHalf {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.inner.poll_lock() {
+            Async::Ready(mut connection) => connection.shutdown(),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{sync::oneshot, Future};
+    use tokio::{self, io};
+
+    use tests::connected_pair;
+    use IpcConnection;
+
+    #[test]
+    fn test_split_halves_in_tasks() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let (server_read, server_write) = server.into_split();
+
+        // Echo "ping" read by one task through the other task's write half.
+        let (read_tx, read_rx) = oneshot::channel();
+        runtime.spawn(io::read_exact(server_read, [0u8; 4])
+            .map(|(read, buf)| { let _ = read_tx.send((read, buf)); })
+            .map_err(|err| panic!("read task failed: {:?}", err)));
+        let (write_tx, write_rx) = oneshot::channel();
+        runtime.spawn(io::write_all(server_write, b"pong")
+            .map(|(write, _)| { let _ = write_tx.send(write); })
+            .map_err(|err| panic!("write task failed: {:?}", err)));
+
+        let client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write").0;
+        let (_, buf) = runtime.block_on(io::read_exact(client, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"pong");
+
+        let (server_read, buf) = runtime.block_on(read_rx).expect("read task dropped");
+        assert_eq!(&buf, b"ping");
+        let server_write = runtime.block_on(write_rx).expect("write task dropped");
+        server_read.reunite(server_write).expect("halves are from the same connection");
+    }
+
+    #[test]
+    fn test_split_framed_full_duplex() {
+        use bytes::Bytes;
+        use futures::{Sink, Stream};
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let (server_read, server_write) = server.split_framed();
+        let (client_read, client_write) = client.split_framed();
+
+        // Both sides send their messages while their other task decodes the peer's.
+        let messages = || (0..100).map(|i| Bytes::from(format!("message {}", i)));
+        runtime.spawn(server_write.send_all(futures::stream::iter_ok::<_, io::Error>(messages()))
+            .map(|_| ())
+            .map_err(|err| panic!("server write task failed: {:?}", err)));
+        runtime.spawn(client_write.send_all(futures::stream::iter_ok::<_, io::Error>(messages()))
+            .map(|_| ())
+            .map_err(|err| panic!("client write task failed: {:?}", err)));
+        let (server_tx, server_rx) = oneshot::channel();
+        runtime.spawn(server_read.take(100).collect()
+            .map(|frames| { let _ = server_tx.send(frames); })
+            .map_err(|err| panic!("server read task failed: {:?}", err)));
+
+        let received = runtime.block_on(client_read.take(100).collect()).expect("failed to read from server");
+        assert!(received.into_iter().map(|frame| frame.freeze()).eq(messages()));
+        let received = runtime.block_on(server_rx).expect("server read task dropped");
+        assert!(received.into_iter().map(|frame| frame.freeze()).eq(messages()));
+    }
+
+    #[test]
+    fn test_reunite_matched_halves() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let (server_read, server_write) = server.into_split();
+
+        let server = IpcConnection::reunite(server_read, server_write).expect("halves are from the same connection");
+        let _client = runtime.block_on(io::write_all(client, b"ping")).expect("failed to write");
+        let (_, buf) = runtime.block_on(io::read_exact(server, [0u8; 4])).expect("failed to read");
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn test_reunite_mismatched_halves() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let (client_read, client_write) = client.into_split();
+        let (server_read, server_write) = server.into_split();
+
+        let err = match IpcConnection::reunite(client_read, server_write) {
+            Ok(_) => panic!("halves are from different connections"),
+            Err(err) => err,
+        };
+        // Both halves are given back and still reunite with their own counterparts.
+        err.0.reunite(client_write).expect("client halves match");
+        IpcConnection::reunite(server_read, err.1).expect("server halves match");
+    }
+}
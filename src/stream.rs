@@ -0,0 +1,62 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use {IpcConnection, PeerInfo};
+
+/// Byte stream to write protocol code against, implemented by `IpcConnection` and by
+/// the in-memory connections of the `testing` module.
+///
+/// Lets handlers taking `S: IpcStream` be tested without OS resources, e.g. over a
+/// `testing::duplex` pair.
+pub trait IpcStream: AsyncRead + AsyncWrite + Send {
+    /// Identity of the peer process, see `IpcConnection::peer_info`. `None` unless
+    /// implemented, as for streams without a peer process.
+    fn peer_info(&self) -> Option<PeerInfo> {
+        None
+    }
+}
+
+impl IpcStream for IpcConnection {
+    fn peer_info(&self) -> Option<PeerInfo> {
+        IpcConnection::peer_info(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use futures::Future;
+    use tokio;
+
+    use testing::MockConnection;
+    use tests::connected_pair;
+    use {IpcStream, PeerInfo};
+
+    // Handler under test: greet the peer by user id.
+    fn greet<S: IpcStream + 'static>(stream: S) -> impl Future<Item = S, Error = io::Error> {
+        let greeting = match stream.peer_info().and_then(|info| info.uid) {
+            Some(uid) => format!("hello {}", uid),
+            None => "hello stranger".to_owned(),
+        };
+        tokio::io::write_all(stream, greeting).map(|(stream, _)| stream)
+    }
+
+    #[test]
+    fn test_generic_handler() {
+        let mock = MockConnection::new(Vec::new()).with_peer_info(PeerInfo { pid: Some(42), uid: Some(1000), gid: None });
+        assert_eq!(greet(mock).wait().expect("failed to greet").written(), b"hello 1000");
+        assert_eq!(greet(MockConnection::new(Vec::new())).wait().expect("failed to greet").written(), b"hello stranger");
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+        let expected = match server.peer_info().and_then(|info| info.uid) {
+            Some(uid) => format!("hello {}", uid),
+            None => "hello stranger".to_owned(),
+        };
+        #[cfg(unix)]
+        assert_eq!(expected, format!("hello {}", unsafe { ::libc::geteuid() }));
+        let _server = runtime.block_on(greet(server)).expect("failed to greet");
+        let (_, greeting) = runtime.block_on(tokio::io::read_exact(client, vec![0u8; expected.len()])).expect("failed to read");
+        assert_eq!(greeting, expected.into_bytes());
+    }
+}
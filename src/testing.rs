@@ -0,0 +1,305 @@
+//! In-memory stand-ins for connections, to test protocol code without OS resources.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures::{task::{self, Task}, Async, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use {IpcStream, PeerInfo};
+
+/// Connection reading scripted bytes and recording what is written to it.
+///
+/// Implements `AsyncRead` and `AsyncWrite` like `IpcConnection`, and never blocks:
+/// reads return the scripted bytes followed by end of file. Errors can be injected at
+/// a byte offset of either direction, to check how a protocol copes with e.g. a peer
+/// resetting the connection mid-frame. An injected error is returned once, after which
+/// the connection carries on.
+#[derive(Debug, Default)]
+pub struct MockConnection {
+    input: Vec<u8>,
+    read: usize,
+    read_error: Option<(usize, io::Error)>,
+    written: Vec<u8>,
+    write_error: Option<(usize, io::Error)>,
+    peer_info: Option<PeerInfo>,
+}
+
+impl MockConnection {
+    /// New connection yielding `input` to reads.
+    pub fn new<B: Into<Vec<u8>>>(input: B) -> Self {
+        MockConnection { input: input.into(), ..Default::default() }
+    }
+
+    /// Fail the read that reaches `offset` bytes into the input with `err`.
+    pub fn read_error_at(mut self, offset: usize, err: io::Error) -> Self {
+        self.read_error = Some((offset, err));
+        self
+    }
+
+    /// Fail the write that reaches `offset` bytes into the output with `err`.
+    pub fn write_error_at(mut self, offset: usize, err: io::Error) -> Self {
+        self.write_error = Some((offset, err));
+        self
+    }
+
+    /// Report `info` as the identity of the peer process.
+    pub fn with_peer_info(mut self, info: PeerInfo) -> Self {
+        self.peer_info = Some(info);
+        self
+    }
+
+    /// Bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// Unwrap the bytes written.
+    pub fn into_written(self) -> Vec<u8> {
+        self.written
+    }
+}
+
+/// Bytes of a `len` long transfer at `position` allowed before an error at `offset`,
+/// taking the error if it is due right away.
+fn limit(position: usize, len: usize, error: &mut Option<(usize, io::Error)>) -> io::Result<usize> {
+    match error.take() {
+        Some((offset, err)) if offset <= position => Err(err),
+        Some((offset, err)) => {
+            *error = Some((offset, err));
+            Ok(cmp::min(len, offset - position))
+        },
+        None => Ok(len),
+    }
+}
+
+impl Read for MockConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = &self.input[self.read..];
+        let len = limit(self.read, cmp::min(buf.len(), remaining.len()), &mut self.read_error)?;
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+
+impl AsyncRead for MockConnection {}
+
+impl Write for MockConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = limit(self.written.len(), buf.len(), &mut self.write_error)?;
+        self.written.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for MockConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+impl IpcStream for MockConnection {
+    fn peer_info(&self) -> Option<PeerInfo> {
+        self.peer_info
+    }
+}
+
+/// One direction of a `duplex` pair.
+#[derive(Debug, Default)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    // The writing end shut down or was dropped.
+    closed: bool,
+    // The reading end was dropped.
+    abandoned: bool,
+    reader: Option<Task>,
+    writer: Option<Task>,
+}
+
+fn lock(pipe: &Mutex<Pipe>) -> MutexGuard<'_, Pipe> {
+    pipe.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn notify(task: &mut Option<Task>) {
+    if let Some(task) = task.take() {
+        task.notify();
+    }
+}
+
+/// End of an in-memory connection created by `duplex`.
+///
+/// Behaves like an `IpcConnection` without involving the OS: reads wait for the other
+/// end to write, writes wait while the other end has `capacity` bytes left to read, and
+/// dropping or shutting down an end makes the other one read the end of file. Has to
+/// be used from within a task, like a real connection.
+#[derive(Debug)]
+pub struct DuplexConnection {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Pair of connected in-memory connections, buffering up to `capacity` bytes in each
+/// direction.
+///
+/// Lighter than a loopback pair of real connections, for testing protocol code written
+/// against `IpcStream`. Panics if `capacity` is zero.
+pub fn duplex(capacity: usize) -> (DuplexConnection, DuplexConnection) {
+    assert!(capacity > 0, "duplex capacity must be positive");
+    let one = Arc::new(Mutex::new(Pipe { capacity, ..Default::default() }));
+    let other = Arc::new(Mutex::new(Pipe { capacity, ..Default::default() }));
+    (DuplexConnection { read: one.clone(), write: other.clone() }, DuplexConnection { read: other, write: one })
+}
+
+impl Read for DuplexConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = lock(&self.read);
+        if pipe.buf.is_empty() {
+            if pipe.closed || buf.is_empty() {
+                return Ok(0);
+            }
+            pipe.reader = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let len = cmp::min(buf.len(), pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..len)) {
+            *dst = src;
+        }
+        notify(&mut pipe.writer);
+        Ok(len)
+    }
+}
+
+impl AsyncRead for DuplexConnection {}
+
+impl Write for DuplexConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = lock(&self.write);
+        if pipe.abandoned || pipe.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = cmp::min(buf.len(), pipe.capacity - pipe.buf.len());
+        if len == 0 {
+            pipe.writer = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        pipe.buf.extend(&buf[..len]);
+        notify(&mut pipe.reader);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for DuplexConnection {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        let mut pipe = lock(&self.write);
+        pipe.closed = true;
+        notify(&mut pipe.reader);
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Drop for DuplexConnection {
+    fn drop(&mut self) {
+        {
+            let mut pipe = lock(&self.write);
+            pipe.closed = true;
+            notify(&mut pipe.reader);
+        }
+        let mut pipe = lock(&self.read);
+        pipe.abandoned = true;
+        notify(&mut pipe.writer);
+    }
+}
+
+impl IpcStream for DuplexConnection {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, ErrorKind};
+
+    use bytes::Bytes;
+    use futures::{Future, Sink, Stream};
+    use tokio;
+    use tokio::codec::{Framed, LengthDelimitedCodec};
+
+    use {IpcConnection, IpcStream};
+    use super::{duplex, MockConnection};
+
+    fn assert_connection_bounds<T: IpcStream + 'static>() {}
+
+    // Protocol under test: echo every length prefixed frame back.
+    fn echo<S: IpcStream + 'static>(stream: S) -> impl Future<Item = (), Error = io::Error> {
+        let (sink, frames) = Framed::new(stream, LengthDelimitedCodec::new()).split();
+        frames.map(|frame| frame.freeze()).forward(sink).map(|_| ())
+    }
+
+    #[test]
+    fn test_protocol_over_duplex() {
+        assert_connection_bounds::<IpcConnection>();
+
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        // Smaller than a frame, so that both ends have to wait for each other.
+        let (client, server) = duplex(3);
+        runtime.spawn(echo(server).map_err(|err| panic!("echo failed: {:?}", err)));
+
+        let client = Framed::new(client, LengthDelimitedCodec::new());
+        let client = runtime.block_on(client.send(Bytes::from_static(b"ping"))).expect("failed to send");
+        let (reply, client) = runtime.block_on(client.into_future()).map_err(|(err, _)| err).expect("failed to receive");
+        assert_eq!(&reply.expect("echo ended early")[..], b"ping");
+
+        // Closing the client ends the echo loop, which closes the server end in turn.
+        let client = runtime.block_on(tokio::io::shutdown(client.into_inner())).expect("failed to shut down");
+        let (_, rest) = runtime.block_on(tokio::io::read_to_end(client, Vec::new())).expect("failed to read");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_reset_mid_frame() {
+        assert_connection_bounds::<MockConnection>();
+
+        // Two length prefixed frames of 4 bytes, the connection is reset halfway into the second.
+        let input = b"\0\0\0\x04ping\0\0\0\x04pong".to_vec();
+        let connection = MockConnection::new(input)
+            .read_error_at(10, io::Error::new(ErrorKind::ConnectionReset, "connection reset by peer"));
+        let framed = Framed::new(connection, LengthDelimitedCodec::new());
+
+        let (frame, framed) = framed.into_future().wait().map_err(|(err, _)| err).expect("first frame should decode");
+        assert_eq!(&frame.expect("stream ended early")[..], b"ping");
+        let err = match framed.into_future().wait() {
+            Ok(_) => panic!("truncated frame should fail"),
+            Err((err, _)) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn test_records_writes() {
+        let connection = MockConnection::new(Vec::new())
+            .write_error_at(6, io::Error::new(ErrorKind::BrokenPipe, "peer went away"));
+        let framed = Framed::new(connection, LengthDelimitedCodec::new());
+
+        let framed = framed.send(Bytes::from_static(b"ab")).wait().expect("first frame should be written");
+        assert_eq!(framed.get_ref().written(), b"\0\0\0\x02ab");
+        let err = match framed.send(Bytes::from_static(b"cd")).wait() {
+            Ok(_) => panic!("second frame should fail"),
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    }
+}
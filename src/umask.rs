@@ -0,0 +1,54 @@
+use std::sync::{Mutex, MutexGuard};
+
+use libc::{self, mode_t};
+
+// The umask is process-global: binds from this crate are serialized, but other
+// threads creating files meanwhile get the temporary umask as well.
+static UMASK_LOCK: Mutex<()> = Mutex::new(());
+
+/// Restores the previous umask when dropped.
+pub struct UmaskGuard {
+    previous: mode_t,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl UmaskGuard {
+    /// Set a umask creating files with at most `mode` permissions.
+    pub fn restrict_to(mode: u32) -> UmaskGuard {
+        let lock = UMASK_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = unsafe { libc::umask(!(mode as mode_t) & 0o777) };
+        UmaskGuard { previous, _lock: lock }
+    }
+}
+
+impl Drop for UmaskGuard {
+    fn drop(&mut self) {
+        unsafe { libc::umask(self.previous) };
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use tokio;
+
+    use Endpoint;
+
+    #[test]
+    fn test_bind_with_mode() {
+        let runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let path = ::dummy_endpoint();
+
+        let mut endpoint = Endpoint::new(path.clone());
+        endpoint.set_mode(0o600);
+        let _incoming = endpoint.incoming(runtime.reactor()).expect("failed to bind");
+
+        // The socket is created with the restricted mode, not narrowed down afterwards.
+        let mode = fs::metadata(&path).expect("socket file should exist").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let _ = fs::remove_file(&path);
+    }
+}
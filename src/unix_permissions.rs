@@ -5,12 +5,53 @@ use std::io;
 pub struct SecurityAttributes;
 
 impl SecurityAttributes {
-    /// New default security attributes.
+    /// New default security attributes, same as `inherit_default`.
     pub fn empty() -> Self { SecurityAttributes }
 
+    /// Security attributes leaving the endpoint with the default security.
+    pub fn inherit_default() -> Self { SecurityAttributes }
+
+    /// Security attributes denying everyone on Windows. Has no effect on Unix, where
+    /// the socket file mode has to be restricted instead.
+    pub fn deny_all() -> io::Result<Self> { Ok(SecurityAttributes) }
+
     /// New security attributes that allow everyone to connect.
     pub fn allow_everyone_connect() -> io::Result<Self> { Ok(SecurityAttributes) }
 
     /// New security attributes that allow everyone to create.
     pub fn allow_everyone_create() -> io::Result<Self> { Ok(SecurityAttributes) }
+
+    /// New security attributes that allow authenticated users while denying anonymous logons.
+    pub fn local_users_no_anonymous() -> io::Result<Self> { Ok(SecurityAttributes) }
+
+    /// Builder for security attributes with a custom set of access control entries.
+    pub fn builder() -> SecurityAttributesBuilder { SecurityAttributesBuilder }
+}
+
+/// A NOOP builder mirroring the Windows `SecurityAttributesBuilder`.
+pub struct SecurityAttributesBuilder;
+
+impl SecurityAttributesBuilder {
+    /// Grant `permissions` (an access mask) to everyone.
+    pub fn allow_everyone(self, _permissions: u32) -> Self { self }
+
+    /// Grant `permissions` (an access mask) to the user the current process runs as.
+    pub fn allow_current_user(self, _permissions: u32) -> Self { self }
+
+    /// Grant `permissions` (an access mask) to all authenticated users.
+    pub fn allow_authenticated_users(self, _permissions: u32) -> Self { self }
+
+    /// Deny `permissions` (an access mask) to anonymous logons.
+    pub fn deny_anonymous_logon(self, _permissions: u32) -> Self { self }
+
+    /// Grant `permissions` (an access mask) to the SID of `authority` with the
+    /// sub-authorities `rids`.
+    pub fn allow_sid(self, _authority: [u8; 6], _rids: &[u32], _permissions: u32) -> Self { self }
+
+    /// Deny `permissions` (an access mask) to the SID of `authority` with the
+    /// sub-authorities `rids`.
+    pub fn deny_sid(self, _authority: [u8; 6], _rids: &[u32], _permissions: u32) -> Self { self }
+
+    /// Build the security attributes.
+    pub fn build(self) -> io::Result<SecurityAttributes> { Ok(SecurityAttributes) }
 }
@@ -4,6 +4,9 @@ use winapi::um::aclapi::*;
 use winapi::um::securitybaseapi::*;
 use winapi::um::minwinbase::{LPTR, SECURITY_ATTRIBUTES, PSECURITY_ATTRIBUTES};
 use winapi::um::winbase::{LocalAlloc, LocalFree};
+use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+use winapi::um::handleapi::CloseHandle;
+use winapi::shared::ntdef::HANDLE;
 use winapi::shared::winerror::ERROR_SUCCESS;
 
 use std::ptr;
@@ -17,21 +20,159 @@ pub struct SecurityAttributes {
 }
 
 impl SecurityAttributes {
-    /// New default security attributes.
+    /// New default security attributes, same as `inherit_default`.
+    ///
+    /// Note this does *not* mean "no access": see `deny_all` for that.
     pub fn empty() -> SecurityAttributes {
+        Self::inherit_default()
+    }
+
+    /// Security attributes leaving the pipe with the default security.
+    ///
+    /// No security descriptor is passed to `CreateNamedPipeW`, so the pipe gets the
+    /// default DACL of the process token: full control for the creator, LocalSystem and
+    /// administrators, read access for Everyone and anonymous logons.
+    pub fn inherit_default() -> SecurityAttributes {
         SecurityAttributes { attributes: None }
     }
 
+    /// Security attributes with an empty DACL, denying everyone, the creator included.
+    ///
+    /// Unlike a null DACL (or `inherit_default`), an empty one grants no access at all.
+    pub fn deny_all() -> io::Result<SecurityAttributes> {
+        Self::builder().build()
+    }
+
     /// New default security attributes that allow everyone to connect.
     pub fn allow_everyone_connect() -> io::Result<SecurityAttributes> {
-        let attributes = Some(InnerAttributes::allow_everyone(GENERIC_READ | FILE_WRITE_DATA)?);
-        Ok(SecurityAttributes { attributes })
+        Self::builder().allow_everyone(GENERIC_READ | FILE_WRITE_DATA).build()
     }
 
     /// New default security attributes that allow everyone to create.
     pub fn allow_everyone_create() -> io::Result<SecurityAttributes> {
-        let attributes = Some(InnerAttributes::allow_everyone(GENERIC_READ | GENERIC_WRITE)?);
-        Ok(SecurityAttributes { attributes })
+        Self::builder().allow_everyone(GENERIC_READ | GENERIC_WRITE).build()
+    }
+
+    /// New security attributes that allow authenticated users to connect while explicitly
+    /// denying anonymous logons, which "Everyone" may include in some configurations.
+    pub fn local_users_no_anonymous() -> io::Result<SecurityAttributes> {
+        Self::builder()
+            .allow_authenticated_users(GENERIC_READ | FILE_WRITE_DATA)
+            .deny_anonymous_logon(GENERIC_ALL)
+            .build()
+    }
+
+    /// Builder for security attributes with a custom set of access control entries.
+    pub fn builder() -> SecurityAttributesBuilder {
+        SecurityAttributesBuilder { entries: Vec::new() }
+    }
+
+    /// Security attributes using a security descriptor built elsewhere, e.g. from a group
+    /// policy template, instead of going through the builder.
+    ///
+    /// With `owned` the descriptor is freed with `LocalFree` when the attributes are
+    /// dropped, as needed for those returned by
+    /// `ConvertStringSecurityDescriptorToSecurityDescriptorW`. Otherwise the caller keeps
+    /// ownership. A null descriptor gives the default security, like `inherit_default`.
+    ///
+    /// # Safety
+    ///
+    /// `descriptor` must be null or point to a valid security descriptor that stays
+    /// valid for as long as the attributes are in use, including by an endpoint creating
+    /// new pipe instances with them. When `owned`, it must have been allocated with
+    /// `LocalAlloc` and must not be freed by anything else.
+    pub unsafe fn from_raw_descriptor(descriptor: PSECURITY_DESCRIPTOR, owned: bool) -> SecurityAttributes {
+        if descriptor.is_null() {
+            return Self::inherit_default();
+        }
+        let descriptor = SecurityDescriptor { descriptor_ptr: descriptor, owned };
+        let mut attrs = mem::zeroed::<SECURITY_ATTRIBUTES>();
+        attrs.nLength = mem::size_of::<SECURITY_ATTRIBUTES>() as u32;
+        attrs.lpSecurityDescriptor = descriptor.as_ptr();
+        attrs.bInheritHandle = false as i32;
+        let acl = Acl::empty().expect("this should never fail");
+
+        SecurityAttributes { attributes: Some(InnerAttributes { acl, descriptor, attrs }) }
+    }
+
+    /// Call `f` with the raw `SECURITY_ATTRIBUTES` pointer, null for `inherit_default`.
+    ///
+    /// Lets other Win32 objects (mutexes, events, file mappings) be secured the same way
+    /// as the pipes. The pointer is only valid during the call and must not be kept.
+    pub fn with_raw<R, F: FnOnce(PSECURITY_ATTRIBUTES) -> R>(&mut self, f: F) -> R {
+        f(unsafe { self.as_ptr() })
+    }
+
+    /// Access rights the current process gets to a pipe created with these attributes,
+    /// e.g. `FILE_READ_DATA | FILE_WRITE_DATA` to connect, for diagnosing "access denied"
+    /// errors before connecting.
+    ///
+    /// Runs `AccessCheck` with the process token against the DACL, the default DACL of the
+    /// token for `inherit_default`, generic rights mapping to file rights. The process is
+    /// taken to own the pipe, as when creating it: owners are implicitly granted
+    /// `READ_CONTROL` and `WRITE_DAC`, which clients of another user's pipe lack.
+    pub fn granted_access(&self) -> io::Result<u32> {
+        let mut token = ptr::null_mut();
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY | TOKEN_DUPLICATE, &mut token) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let granted = self.access_check(token);
+        unsafe { CloseHandle(token) };
+        granted
+    }
+
+    fn access_check(&self, token: HANDLE) -> io::Result<u32> {
+        let (mut present, mut dacl, mut defaulted) = (0, ptr::null_mut(), 0);
+        if let Some(ref attributes) = self.attributes {
+            if unsafe { GetSecurityDescriptorDacl(attributes.descriptor.as_ptr(), &mut present, &mut dacl, &mut defaulted) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        // Without a DACL of their own, pipes get the default one of the token.
+        let default_dacl;
+        if present == 0 {
+            default_dacl = token_information(token, TokenDefaultDacl)?;
+            dacl = unsafe { (*(default_dacl.as_ptr() as *const TOKEN_DEFAULT_DACL)).DefaultDacl };
+        }
+        let token_user = token_information(token, TokenUser)?;
+        let user_sid = unsafe { (*(token_user.as_ptr() as *const TOKEN_USER)).User.Sid };
+
+        // `AccessCheck` needs an owner and a group besides the DACL.
+        let descriptor = SecurityDescriptor::new()?;
+        if unsafe {
+            SetSecurityDescriptorDacl(descriptor.as_ptr(), true as i32, dacl, false as i32) == 0
+                || SetSecurityDescriptorOwner(descriptor.as_ptr(), user_sid, false as i32) == 0
+                || SetSecurityDescriptorGroup(descriptor.as_ptr(), user_sid, false as i32) == 0
+        } {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut impersonation = ptr::null_mut();
+        if unsafe { DuplicateToken(token, SecurityImpersonation, &mut impersonation) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut mapping = GENERIC_MAPPING {
+            GenericRead: FILE_GENERIC_READ,
+            GenericWrite: FILE_GENERIC_WRITE,
+            GenericExecute: FILE_GENERIC_EXECUTE,
+            GenericAll: FILE_ALL_ACCESS,
+        };
+        let mut privileges = [0u64; 8];
+        let mut privileges_len = mem::size_of_val(&privileges) as u32;
+        let (mut granted, mut status) = (0, 0);
+        let checked = unsafe {
+            AccessCheck(descriptor.as_ptr(), impersonation, MAXIMUM_ALLOWED, &mut mapping,
+                privileges.as_mut_ptr() as PPRIVILEGE_SET, &mut privileges_len, &mut granted, &mut status)
+        };
+        let result = if checked == 0 {
+            Err(io::Error::last_os_error())
+        } else if status == 0 {
+            Ok(0)
+        } else {
+            Ok(granted)
+        };
+        unsafe { CloseHandle(impersonation) };
+        result
     }
 
     /// Return raw handle of security attributes.
@@ -45,28 +186,233 @@ impl SecurityAttributes {
 
 unsafe impl Send for SecurityAttributes {}
 
+/// Check the owner of the kernel object behind `handle` is the string SID `expected`,
+/// failing with `PermissionDenied` otherwise.
+pub(crate) fn check_owner(handle: HANDLE, expected: &str) -> io::Result<()> {
+    use winapi::shared::sddl::ConvertStringSidToSidW;
+
+    let wide: Vec<u16> = expected.encode_utf16().chain(Some(0)).collect();
+    let mut expected_sid = ptr::null_mut();
+    if unsafe { ConvertStringSidToSidW(wide.as_ptr(), &mut expected_sid) } == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid SID {:?}", expected)));
+    }
+
+    let mut owner = ptr::null_mut();
+    let mut descriptor = ptr::null_mut();
+    let result = unsafe {
+        GetSecurityInfo(handle, SE_KERNEL_OBJECT, OWNER_SECURITY_INFORMATION,
+            &mut owner, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut descriptor)
+    };
+    let outcome = if result != ERROR_SUCCESS {
+        Err(io::Error::from_raw_os_error(result as i32))
+    } else if unsafe { EqualSid(owner, expected_sid) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied,
+            format!("pipe is owned by {}, expected {}", sid_string(owner), expected)))
+    };
+
+    unsafe {
+        if !descriptor.is_null() {
+            LocalFree(descriptor);
+        }
+        LocalFree(expected_sid);
+    }
+    outcome
+}
+
+// String form (`S-1-5-...`) of a SID, for error messages.
+fn sid_string(sid: PSID) -> String {
+    use std::slice;
+    use winapi::shared::sddl::ConvertSidToStringSidW;
+
+    let mut string = ptr::null_mut();
+    if unsafe { ConvertSidToStringSidW(sid, &mut string) } == 0 {
+        return "an unknown SID".to_owned();
+    }
+    let len = (0..).take_while(|&i| unsafe { *string.offset(i) } != 0).count();
+    let result = String::from_utf16_lossy(unsafe { slice::from_raw_parts(string, len) });
+    unsafe { LocalFree(string as *mut _) };
+    result
+}
+
+/// DACL of the kernel object behind `handle`, in SDDL form (e.g. `D:(A;;FRFW;;;WD)`).
+pub(crate) fn dacl_sddl(handle: HANDLE) -> io::Result<String> {
+    use std::slice;
+    use winapi::shared::sddl::{ConvertSecurityDescriptorToStringSecurityDescriptorW, SDDL_REVISION_1};
+
+    let mut descriptor = ptr::null_mut();
+    let result = unsafe {
+        GetSecurityInfo(handle, SE_KERNEL_OBJECT, DACL_SECURITY_INFORMATION,
+            ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut descriptor)
+    };
+    if result != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(result as i32));
+    }
+
+    let mut sddl = ptr::null_mut();
+    let mut len = 0;
+    let converted = unsafe {
+        ConvertSecurityDescriptorToStringSecurityDescriptorW(descriptor, SDDL_REVISION_1 as u32,
+            DACL_SECURITY_INFORMATION, &mut sddl, &mut len)
+    };
+    let result = if converted == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        // `len` includes the terminating null.
+        let wide = unsafe { slice::from_raw_parts(sddl, (len as usize).saturating_sub(1)) };
+        Ok(String::from_utf16_lossy(wide))
+    };
+
+    unsafe {
+        if !sddl.is_null() {
+            LocalFree(sddl as *mut _);
+        }
+        LocalFree(descriptor);
+    }
+    result
+}
+
+/// Builder for `SecurityAttributes` with a custom list of access control entries.
+///
+/// Permission masks are applied verbatim, so standard rights such as `WRITE_DAC` or
+/// `WRITE_OWNER` can be granted to clients that need to re-secure the pipe.
+pub struct SecurityAttributesBuilder {
+    entries: Vec<Entry>,
+}
+
+impl SecurityAttributesBuilder {
+    /// Grant `permissions` (an access mask) to everyone.
+    pub fn allow_everyone(self, permissions: u32) -> Self {
+        self.sid_entry(SECURITY_WORLD_SID_AUTHORITY, &[SECURITY_WORLD_RID], TRUSTEE_IS_WELL_KNOWN_GROUP, SET_ACCESS, permissions)
+    }
+
+    /// Grant `permissions` (an access mask) to the user the current process runs as.
+    pub fn allow_current_user(self, permissions: u32) -> Self {
+        self.entry(Trustee::CurrentUser, SET_ACCESS, permissions)
+    }
+
+    /// Grant `permissions` (an access mask) to all authenticated users.
+    pub fn allow_authenticated_users(self, permissions: u32) -> Self {
+        self.sid_entry(SECURITY_NT_AUTHORITY, &[SECURITY_AUTHENTICATED_USER_RID], TRUSTEE_IS_WELL_KNOWN_GROUP, SET_ACCESS, permissions)
+    }
+
+    /// Deny `permissions` (an access mask) to anonymous logons.
+    pub fn deny_anonymous_logon(self, permissions: u32) -> Self {
+        self.sid_entry(SECURITY_NT_AUTHORITY, &[SECURITY_ANONYMOUS_LOGON_RID], TRUSTEE_IS_WELL_KNOWN_GROUP, DENY_ACCESS, permissions)
+    }
+
+    /// Grant `permissions` (an access mask) to the SID of `authority` with the
+    /// sub-authorities `rids`, for trustees without a helper of their own.
+    ///
+    /// E.g. `SECURITY_NT_AUTHORITY` with `[SECURITY_BUILTIN_DOMAIN_RID,
+    /// DOMAIN_ALIAS_RID_ADMINS]` for the local administrators, S-1-5-32-544. `build`
+    /// fails with `InvalidInput` unless there are 1 to 8 `rids`.
+    pub fn allow_sid(self, authority: [u8; 6], rids: &[u32], permissions: u32) -> Self {
+        self.sid_entry(authority, rids, TRUSTEE_IS_UNKNOWN, SET_ACCESS, permissions)
+    }
+
+    /// Deny `permissions` (an access mask) to the SID of `authority` with the
+    /// sub-authorities `rids`, see `allow_sid`.
+    pub fn deny_sid(self, authority: [u8; 6], rids: &[u32], permissions: u32) -> Self {
+        self.sid_entry(authority, rids, TRUSTEE_IS_UNKNOWN, DENY_ACCESS, permissions)
+    }
+
+    /// Build the security attributes.
+    pub fn build(self) -> io::Result<SecurityAttributes> {
+        let attributes = Some(InnerAttributes::from_entries(&self.entries)?);
+        Ok(SecurityAttributes { attributes })
+    }
+
+    fn sid_entry(self, authority: [u8; 6], rids: &[u32], trustee_type: u32, access_mode: u32, permissions: u32) -> Self {
+        self.entry(Trustee::Sid { authority, rids: rids.to_vec(), trustee_type }, access_mode, permissions)
+    }
+
+    fn entry(mut self, trustee: Trustee, access_mode: u32, permissions: u32) -> Self {
+        self.entries.push(Entry { trustee, access_mode, permissions });
+        self
+    }
+}
+
+enum Trustee {
+    CurrentUser,
+    Sid { authority: [u8; 6], rids: Vec<u32>, trustee_type: u32 },
+}
+
+impl Trustee {
+    fn sid(&self) -> io::Result<Sid> {
+        match *self {
+            Trustee::CurrentUser => Sid::current_user_sid(),
+            Trustee::Sid { authority, ref rids, .. } => Sid::from_authority_and_rids(authority, rids),
+        }
+    }
+
+    fn trustee_type(&self) -> u32 {
+        match *self {
+            Trustee::CurrentUser => TRUSTEE_IS_USER,
+            Trustee::Sid { trustee_type, .. } => trustee_type,
+        }
+    }
+}
+
+struct Entry {
+    trustee: Trustee,
+    access_mode: u32,
+    permissions: u32,
+}
+
 
 struct Sid {
-    sid_ptr: PSID
+    sid_ptr: PSID,
+    // Backing storage for SIDs copied out of a token, `None` if allocated by `AllocateAndInitializeSid`.
+    buffer: Option<Vec<u32>>,
 }
 
 impl Sid {
-    fn everyone_sid() -> io::Result<Sid> {
+    /// SID of `authority` with the sub-authorities `rids`, of which there are 1 to 8.
+    fn from_authority_and_rids(authority: [u8; 6], rids: &[u32]) -> io::Result<Sid> {
+        if rids.is_empty() || rids.len() > 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a SID has 1 to 8 sub-authorities"));
+        }
+        let mut sub_authorities = [0; 8];
+        sub_authorities[..rids.len()].copy_from_slice(rids);
+
+        let mut authority = SID_IDENTIFIER_AUTHORITY { Value: authority };
         let mut sid_ptr = ptr::null_mut();
         let result = unsafe {
             AllocateAndInitializeSid(
-                SECURITY_WORLD_SID_AUTHORITY.as_mut_ptr() as *mut _, 1,
-                SECURITY_WORLD_RID,
-                0, 0, 0, 0, 0, 0, 0,
+                &mut authority, rids.len() as u8,
+                sub_authorities[0], sub_authorities[1], sub_authorities[2], sub_authorities[3],
+                sub_authorities[4], sub_authorities[5], sub_authorities[6], sub_authorities[7],
                 &mut sid_ptr)
         };
         if result == 0 {
             Err(io::Error::last_os_error())
         } else {
-            Ok(Sid{sid_ptr})
+            Ok(Sid{sid_ptr, buffer: None})
         }
     }
 
+    fn current_user_sid() -> io::Result<Sid> {
+        let mut token = ptr::null_mut();
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let token_user = token_information(token, TokenUser);
+        unsafe { CloseHandle(token) };
+        let token_user = token_user?;
+
+        let user_sid = unsafe { (*(token_user.as_ptr() as *const TOKEN_USER)).User.Sid };
+        let sid_len = unsafe { GetLengthSid(user_sid) };
+        let mut buffer = vec![0u32; (sid_len as usize).div_ceil(4)];
+        if unsafe { CopySid(sid_len, buffer.as_mut_ptr() as PSID, user_sid) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Sid{sid_ptr: buffer.as_mut_ptr() as PSID, buffer: Some(buffer)})
+    }
+
     // Unsafe - the returned pointer is only valid for the lifetime of self.
     unsafe fn as_ptr(&self) -> PSID {
         self.sid_ptr
@@ -75,12 +421,26 @@ impl Sid {
 
 impl Drop for Sid {
     fn drop(&mut self) {
-        if !self.sid_ptr.is_null() {
+        if self.buffer.is_none() && !self.sid_ptr.is_null() {
             unsafe{ FreeSid(self.sid_ptr); }
         }
     }
 }
 
+// Query a token information class into a suitably aligned buffer.
+fn token_information(token: HANDLE, class: TOKEN_INFORMATION_CLASS) -> io::Result<Vec<u64>> {
+    let mut len = 0;
+    unsafe { GetTokenInformation(token, class, ptr::null_mut(), 0, &mut len) };
+    if len == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+    if unsafe { GetTokenInformation(token, class, buffer.as_mut_ptr() as *mut _, len, &mut len) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(buffer)
+}
 
 struct AceWithSid<'a> {
     explicit_access: EXPLICIT_ACCESS_W,
@@ -143,6 +503,21 @@ impl Acl {
     unsafe fn as_ptr(&self) -> PACL {
         self.acl_ptr
     }
+
+    // Type and access mask of every ACE in the list.
+    #[cfg(test)]
+    fn entries(&self) -> io::Result<Vec<(u8, u32)>> {
+        let count = unsafe { (*self.acl_ptr).AceCount };
+        (0..count as u32).map(|index| {
+            let mut ace = ptr::null_mut();
+            if unsafe { GetAce(self.acl_ptr, index, &mut ace) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // All the ACE kinds we create share the `ACCESS_ALLOWED_ACE` layout.
+            let ace = unsafe { &*(ace as *const ACCESS_ALLOWED_ACE) };
+            Ok((ace.Header.AceType, ace.Mask))
+        }).collect()
+    }
 }
 
 impl Drop for Acl {
@@ -155,6 +530,8 @@ impl Drop for Acl {
 
 struct SecurityDescriptor {
     descriptor_ptr: PSECURITY_DESCRIPTOR,
+    // Whether the descriptor is freed on drop, false for borrowed raw descriptors.
+    owned: bool,
 }
 
 impl SecurityDescriptor{
@@ -174,7 +551,7 @@ impl SecurityDescriptor{
             return Err(io::Error::last_os_error());
         };
 
-        Ok(SecurityDescriptor{descriptor_ptr})
+        Ok(SecurityDescriptor{descriptor_ptr, owned: true})
     }
 
     fn set_dacl(&mut self, acl: &Acl) -> io::Result<()> {
@@ -196,7 +573,7 @@ impl SecurityDescriptor{
 
 impl Drop for SecurityDescriptor {
     fn drop(&mut self) {
-        if !self.descriptor_ptr.is_null() {
+        if self.owned && !self.descriptor_ptr.is_null() {
             unsafe { LocalFree(self.descriptor_ptr) };
             self.descriptor_ptr = ptr::null_mut();
         }
@@ -228,19 +605,19 @@ impl InnerAttributes {
         })
     }
 
-    fn allow_everyone(permissions: u32) -> io::Result<InnerAttributes> {
+    fn from_entries(entries: &[Entry]) -> io::Result<InnerAttributes> {
         let mut attributes = Self::empty()?;
-        let sid = Sid::everyone_sid()?;
-        println!("pisec");
-
-        let mut everyone_ace = AceWithSid::new(&sid, TRUSTEE_IS_WELL_KNOWN_GROUP);
-        everyone_ace.set_access_mode(SET_ACCESS)
-                    .set_access_permissions(permissions)
-                    .allow_inheritance(false as u32);
+        let sids = entries.iter().map(|entry| entry.trustee.sid()).collect::<io::Result<Vec<_>>>()?;
 
+        let mut aces = entries.iter().zip(sids.iter()).map(|(entry, sid)| {
+            let mut ace = AceWithSid::new(sid, entry.trustee.trustee_type());
+            ace.set_access_mode(entry.access_mode)
+                .set_access_permissions(entry.permissions)
+                .allow_inheritance(false as u32);
+            ace
+        }).collect::<Vec<_>>();
 
-        let mut entries = vec![everyone_ace];
-        attributes.acl = Acl::new(&mut entries)?;
+        attributes.acl = Acl::new(&mut aces)?;
         attributes.descriptor.set_dacl(&attributes.acl)?;
 
         Ok(attributes)
@@ -253,7 +630,9 @@ impl InnerAttributes {
 
 #[cfg(test)]
 mod test {
-    use super::SecurityAttributes;
+    use winapi::um::winnt::{ACCESS_ALLOWED_ACE_TYPE, ACCESS_DENIED_ACE_TYPE, GENERIC_READ, GENERIC_WRITE, WRITE_DAC};
+
+    use super::{sid_string, SecurityAttributes, Sid};
 
     #[test]
     fn test_allow_everyone_everything() {
@@ -267,4 +646,131 @@ mod test {
             .expect("failed to create security attributes that allow everyone to read and write to/from a pipe");
     }
 
+    #[test]
+    fn test_sid_from_authority_and_rids() {
+        use winapi::um::winnt::{DOMAIN_ALIAS_RID_ADMINS, SECURITY_BUILTIN_DOMAIN_RID, SECURITY_LOCAL_SYSTEM_RID,
+            SECURITY_NT_AUTHORITY, SECURITY_WORLD_RID, SECURITY_WORLD_SID_AUTHORITY};
+
+        let system = Sid::from_authority_and_rids(SECURITY_NT_AUTHORITY, &[SECURITY_LOCAL_SYSTEM_RID]).unwrap();
+        assert_eq!(sid_string(system.sid_ptr), "S-1-5-18");
+        let admins = Sid::from_authority_and_rids(SECURITY_NT_AUTHORITY,
+            &[SECURITY_BUILTIN_DOMAIN_RID, DOMAIN_ALIAS_RID_ADMINS]).unwrap();
+        assert_eq!(sid_string(admins.sid_ptr), "S-1-5-32-544");
+        let everyone = Sid::from_authority_and_rids(SECURITY_WORLD_SID_AUTHORITY, &[SECURITY_WORLD_RID]).unwrap();
+        assert_eq!(sid_string(everyone.sid_ptr), "S-1-1-0");
+
+        assert!(Sid::from_authority_and_rids(SECURITY_NT_AUTHORITY, &[]).is_err());
+        assert!(Sid::from_authority_and_rids(SECURITY_NT_AUTHORITY, &[0; 9]).is_err());
+    }
+
+    #[test]
+    fn test_with_raw_create_event() {
+        use std::ptr;
+        use winapi::um::handleapi::CloseHandle;
+        use winapi::um::synchapi::CreateEventW;
+
+        let mut attrs = SecurityAttributes::allow_everyone_connect().unwrap();
+        let event = attrs.with_raw(|attributes| unsafe { CreateEventW(attributes, 1, 0, ptr::null()) });
+        assert!(!event.is_null(), "failed to create an event: {}", ::std::io::Error::last_os_error());
+        unsafe { CloseHandle(event) };
+    }
+
+    #[test]
+    fn test_deny_all_empty_dacl() {
+        let attrs = SecurityAttributes::deny_all().expect("failed to create security attributes denying everyone");
+        let entries = attrs.attributes.as_ref().expect("deny_all must install a DACL").acl.entries().expect("failed to read back ACL");
+        assert!(entries.is_empty());
+        assert!(SecurityAttributes::inherit_default().attributes.is_none());
+    }
+
+    #[test]
+    fn test_allow_current_user_write_dac() {
+        let attrs = SecurityAttributes::builder()
+            .allow_current_user(GENERIC_READ | GENERIC_WRITE | WRITE_DAC)
+            .build()
+            .expect("failed to create security attributes granting WRITE_DAC to the current user");
+
+        let entries = attrs.attributes.as_ref().unwrap().acl.entries().expect("failed to read back ACL");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, ACCESS_ALLOWED_ACE_TYPE);
+        assert!(entries[0].1 & WRITE_DAC != 0, "WRITE_DAC was stripped from the ACE mask");
+    }
+
+    #[test]
+    fn test_custom_sid_entries() {
+        use winapi::um::winnt::{DOMAIN_ALIAS_RID_ADMINS, SECURITY_BUILTIN_DOMAIN_RID, SECURITY_LOCAL_SYSTEM_RID,
+            SECURITY_NT_AUTHORITY};
+
+        let attrs = SecurityAttributes::builder()
+            .allow_sid(SECURITY_NT_AUTHORITY, &[SECURITY_BUILTIN_DOMAIN_RID, DOMAIN_ALIAS_RID_ADMINS], GENERIC_READ | GENERIC_WRITE)
+            .deny_sid(SECURITY_NT_AUTHORITY, &[SECURITY_LOCAL_SYSTEM_RID], GENERIC_WRITE)
+            .build()
+            .expect("failed to create security attributes for custom SIDs");
+
+        let entries = attrs.attributes.as_ref().unwrap().acl.entries().expect("failed to read back ACL");
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().any(|&(ace_type, _)| ace_type == ACCESS_ALLOWED_ACE_TYPE));
+        assert!(entries.iter().any(|&(ace_type, _)| ace_type == ACCESS_DENIED_ACE_TYPE));
+
+        match SecurityAttributes::builder().allow_sid(SECURITY_NT_AUTHORITY, &[], GENERIC_READ).build() {
+            Ok(_) => panic!("a SID without sub-authorities is invalid"),
+            Err(err) => assert_eq!(err.kind(), ::std::io::ErrorKind::InvalidInput),
+        }
+    }
+
+    #[test]
+    fn test_local_users_no_anonymous() {
+        let attrs = SecurityAttributes::local_users_no_anonymous()
+            .expect("failed to create security attributes denying anonymous logons");
+
+        let entries = attrs.attributes.as_ref().unwrap().acl.entries().expect("failed to read back ACL");
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().any(|&(ace_type, _)| ace_type == ACCESS_ALLOWED_ACE_TYPE));
+        assert!(entries.iter().any(|&(ace_type, _)| ace_type == ACCESS_DENIED_ACE_TYPE));
+    }
+
+    #[test]
+    fn test_granted_access() {
+        use winapi::um::winnt::{FILE_READ_DATA, FILE_WRITE_DATA};
+
+        let granted = SecurityAttributes::builder().allow_everyone(GENERIC_READ).build().unwrap()
+            .granted_access().expect("failed to check access");
+        assert!(granted & FILE_READ_DATA != 0, "read not granted: {:#x}", granted);
+        assert!(granted & FILE_WRITE_DATA == 0, "write granted: {:#x}", granted);
+
+        let granted = SecurityAttributes::deny_all().unwrap().granted_access().expect("failed to check access");
+        assert_eq!(granted & (FILE_READ_DATA | FILE_WRITE_DATA), 0);
+        // The creator is granted full control by the default DACL.
+        let granted = SecurityAttributes::inherit_default().granted_access().expect("failed to check access");
+        assert_eq!(granted & (FILE_READ_DATA | FILE_WRITE_DATA), FILE_READ_DATA | FILE_WRITE_DATA);
+    }
+
+    #[test]
+    fn test_from_raw_sddl_descriptor() {
+        use std::ptr;
+        use winapi::shared::sddl::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+        use winapi::um::synchapi::CreateEventW;
+
+        use winapi::um::handleapi::CloseHandle;
+
+        use super::dacl_sddl;
+
+        // Only Everyone is granted access, unlike with the default DACL.
+        let sddl: Vec<u16> = "D:(A;;GRGW;;;WD)".encode_utf16().chain(Some(0)).collect();
+        let mut descriptor = ptr::null_mut();
+        let converted = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1 as u32,
+                &mut descriptor, ptr::null_mut())
+        };
+        assert!(converted != 0, "failed to convert SDDL: {}", ::std::io::Error::last_os_error());
+
+        let mut attrs = unsafe { SecurityAttributes::from_raw_descriptor(descriptor, true) };
+        let event = attrs.with_raw(|attributes| unsafe { CreateEventW(attributes, 1, 0, ptr::null()) });
+        assert!(!event.is_null(), "failed to create an event: {}", ::std::io::Error::last_os_error());
+        let dacl = dacl_sddl(event).expect("failed to read back the DACL");
+        unsafe { CloseHandle(event) };
+        // Generic rights may come back mapped to the specific rights of events.
+        assert!(dacl.starts_with("D:(A;;") && dacl.ends_with(";;;WD)") && dacl.matches('(').count() == 1, "unexpected DACL {}", dacl);
+    }
+
 }
@@ -0,0 +1,619 @@
+use winapi::shared::minwindef::DWORD;
+use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+use winapi::um::namedpipeapi::{CreateNamedPipeW, GetNamedPipeHandleStateW, GetNamedPipeInfo, SetNamedPipeHandleState, WaitNamedPipeW};
+use winapi::um::winnt::{FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_WRITE_ATTRIBUTES, GENERIC_READ, GENERIC_WRITE};
+use winapi::um::winbase::{GetNamedPipeClientSessionId, FILE_FLAG_FIRST_PIPE_INSTANCE, FILE_FLAG_OVERLAPPED, FILE_FLAG_SESSION_AWARE, FILE_FLAG_WRITE_THROUGH, PIPE_ACCESS_DUPLEX,
+    PIPE_ACCESS_INBOUND, PIPE_ACCESS_OUTBOUND,
+    PIPE_READMODE_BYTE, PIPE_READMODE_MESSAGE, PIPE_SERVER_END, PIPE_TYPE_BYTE, PIPE_TYPE_MESSAGE,
+    PIPE_NOWAIT, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT};
+
+use winapi::shared::winerror::{ERROR_BAD_NETPATH, ERROR_BROKEN_PIPE, ERROR_FILE_NOT_FOUND, ERROR_HOST_UNREACHABLE, ERROR_NETWORK_UNREACHABLE,
+    ERROR_NO_DATA, ERROR_PIPE_BUSY, ERROR_SEM_TIMEOUT};
+
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, RawHandle};
+use std::path::Path;
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pipe_name;
+use {RetryConfig, SecurityAttributes};
+
+/// Longest message `IpcConnection::read_message` can return: `mio-named-pipes` reads
+/// through buffers of this size.
+pub const MAX_MESSAGE_LEN: usize = 8 * 1024;
+
+// Not defined by `winapi`.
+const NMPWAIT_USE_DEFAULT_WAIT: DWORD = 0x0000_0000;
+const NMPWAIT_WAIT_FOREVER: DWORD = 0xffff_ffff;
+
+/// Type of a named pipe, chosen by the server when creating it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PipeMode {
+    /// Data is written and read as a stream of bytes.
+    #[default]
+    Byte,
+    /// Data is written and read as discrete messages.
+    Message,
+}
+
+impl PipeMode {
+    fn type_flag(&self) -> DWORD {
+        match *self {
+            PipeMode::Byte => PIPE_TYPE_BYTE,
+            PipeMode::Message => PIPE_TYPE_MESSAGE,
+        }
+    }
+
+    fn read_mode_flag(&self) -> DWORD {
+        match *self {
+            PipeMode::Byte => PIPE_READMODE_BYTE,
+            PipeMode::Message => PIPE_READMODE_MESSAGE,
+        }
+    }
+}
+
+/// How a client waits when all instances of a pipe are busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeWait {
+    /// Fail right away with `ERROR_PIPE_BUSY`.
+    Immediate,
+    /// Wait until an instance becomes available.
+    Forever,
+    /// Wait up to the given duration overall, then fail with `ERROR_SEM_TIMEOUT`.
+    Timeout(Duration),
+}
+
+impl Default for PipeWait {
+    fn default() -> Self {
+        PipeWait::Timeout(Duration::from_millis(::PIPE_AVAILABILITY_TIMEOUT))
+    }
+}
+
+/// Direction data flows through a pipe, seen from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PipeDirection {
+    /// Both ends read and write.
+    #[default]
+    Duplex,
+    /// Clients write and the server reads.
+    Inbound,
+    /// The server writes and clients read.
+    Outbound,
+}
+
+impl PipeDirection {
+    fn open_mode(&self) -> DWORD {
+        match *self {
+            PipeDirection::Duplex => PIPE_ACCESS_DUPLEX,
+            PipeDirection::Inbound => PIPE_ACCESS_INBOUND,
+            PipeDirection::Outbound => PIPE_ACCESS_OUTBOUND,
+        }
+    }
+
+    // Attribute access lets clients query the pipe and switch to message read mode.
+    pub(crate) fn client_access(&self) -> DWORD {
+        match *self {
+            PipeDirection::Duplex => GENERIC_READ | GENERIC_WRITE,
+            PipeDirection::Inbound => GENERIC_WRITE | FILE_READ_ATTRIBUTES,
+            PipeDirection::Outbound => GENERIC_READ | FILE_WRITE_ATTRIBUTES,
+        }
+    }
+}
+
+/// Options for connecting to a pipe, see `IpcConnection::connect_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    wait: PipeWait,
+    write_through: bool,
+    session_aware: bool,
+    remote_timeout: Option<Duration>,
+    collect_data_timeout: Option<Duration>,
+    direction: PipeDirection,
+    extra_access: DWORD,
+}
+
+impl ClientOptions {
+    /// New default options: wait up to 5 seconds on busy pipes, buffered writes.
+    pub fn new() -> Self {
+        ClientOptions::default()
+    }
+
+    /// How to wait while all pipe instances are busy.
+    pub fn wait(mut self, wait: PipeWait) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// Open the pipe with `FILE_FLAG_WRITE_THROUGH`, see `Endpoint::set_write_through`.
+    pub fn write_through(mut self, write_through: bool) -> Self {
+        self.write_through = write_through;
+        self
+    }
+
+    /// Open the pipe with `FILE_FLAG_SESSION_AWARE`, off by default.
+    ///
+    /// Only matters for clients running in session 0, i.e. services: without the flag
+    /// they can't open per-session devices, as redirected into remote desktop sessions.
+    /// Pipe names themselves are not per session, a service reaches a server in a
+    /// console or remote session either way. The flag has no effect in other sessions.
+    pub fn session_aware(mut self, session_aware: bool) -> Self {
+        self.session_aware = session_aware;
+        self
+    }
+
+    /// Give up connecting to a pipe on another host, `\\host\pipe\name`, after
+    /// `timeout`, failing with `TimedOut`.
+    ///
+    /// Opening a remote pipe goes through SMB, which can take long to notice a host
+    /// that doesn't answer. The timeout covers the whole attempt, waiting on busy
+    /// instances included. Local pipes aren't affected.
+    pub fn remote_timeout(mut self, timeout: Duration) -> Self {
+        self.remote_timeout = Some(timeout);
+        self
+    }
+
+    /// Send buffered writes to a pipe on another host at most `timeout` after they were
+    /// made, the collect data timeout of the pipe handle.
+    ///
+    /// Byte-mode clients of remote pipes collect small writes before sending them over
+    /// the network, which batches them at the cost of latency; latency-sensitive callers
+    /// can set a low timeout. Writes to message pipes are sent as messages right away,
+    /// and `write_through` disables collecting altogether. Local pipes don't collect
+    /// writes and aren't affected.
+    pub fn collect_data_timeout(mut self, timeout: Duration) -> Self {
+        self.collect_data_timeout = Some(timeout);
+        self
+    }
+
+    /// Direction of the pipe, which the client has to match: clients of one-way pipes can
+    /// only open them for reading or writing. Duplex by default.
+    pub fn direction(mut self, direction: PipeDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Also request the access rights `rights` when opening the pipe, e.g. `WRITE_DAC`
+    /// and `WRITE_OWNER` for a privileged client changing the security of the pipe with
+    /// `SetSecurityInfo` once connected.
+    ///
+    /// The DACL of the server has to grant the rights to the client, connecting fails with
+    /// `PermissionDenied` otherwise. The default DACL grants them to the server's owner,
+    /// administrators and LocalSystem only.
+    pub fn access_rights(mut self, rights: u32) -> Self {
+        self.extra_access |= rights;
+        self
+    }
+
+    fn access(&self) -> DWORD {
+        self.direction.client_access() | self.extra_access
+    }
+
+    pub(crate) fn open(&self, path: &Path) -> io::Result<File> {
+        let mut flags = FILE_FLAG_OVERLAPPED;
+        if self.write_through {
+            flags |= FILE_FLAG_WRITE_THROUGH;
+        }
+        if self.session_aware {
+            flags |= FILE_FLAG_SESSION_AWARE;
+        }
+
+        let host = match path.to_str().and_then(pipe_name::remote_host) {
+            Some(host) => host,
+            None => return open_client(path, self.access(), flags, self.wait),
+        };
+        let result = match self.remote_timeout {
+            Some(timeout) => open_remote(path, self.access(), flags, self.wait, timeout),
+            None => open_client(path, self.access(), flags, self.wait),
+        };
+        let file = result.map_err(|err| remote_error(err, host))?;
+        if let Some(timeout) = self.collect_data_timeout {
+            set_collect_data_timeout(file.as_raw_handle(), timeout)?;
+        }
+        Ok(file)
+    }
+}
+
+/// Open a pipe on another host from a helper thread, as `CreateFileW` can't be given a
+/// timeout. If the thread is abandoned, it closes the pipe itself should it still open.
+fn open_remote(path: &Path, access: DWORD, flags: DWORD, wait: PipeWait, timeout: Duration) -> io::Result<File> {
+    let (tx, rx) = mpsc::channel();
+    let remote_path = path.to_owned();
+    thread::Builder::new().name("ipc-remote-connect".into()).spawn(move || {
+        let _ = tx.send(open_client(&remote_path, access, flags, wait));
+    })?;
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(io::ErrorKind::TimedOut,
+            format!("connecting to {:?} timed out after {:?}", path, timeout))),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::Error::other("remote connect thread panicked")),
+    }
+}
+
+/// Report SMB failures to reach the host of a remote pipe with matching error kinds.
+fn remote_error(err: io::Error, host: &str) -> io::Error {
+    let kind = match err.raw_os_error().map(|code| code as u32) {
+        Some(ERROR_BAD_NETPATH) | Some(ERROR_HOST_UNREACHABLE) => io::ErrorKind::HostUnreachable,
+        Some(ERROR_NETWORK_UNREACHABLE) => io::ErrorKind::NetworkUnreachable,
+        _ => return err,
+    };
+    io::Error::new(kind, format!("can't reach pipe host {}: {}", host, err))
+}
+
+/// Open the client end of the pipe at `path` with the desired `access` and file `flags`,
+/// waiting on busy pipes as configured.
+pub(crate) fn open_client(path: &Path, access: DWORD, flags: DWORD, wait: PipeWait) -> io::Result<File> {
+    let deadline = match wait {
+        PipeWait::Timeout(timeout) => Some(Instant::now() + timeout),
+        _ => None,
+    };
+    // Converted once for all attempts, and without allocating for usual names.
+    let name = pipe_name::WideName::new(path.as_os_str().encode_wide());
+
+    loop {
+        let handle = unsafe {
+            CreateFileW(name.as_ptr(), access, FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                ptr::null_mut(), OPEN_EXISTING, flags, ptr::null_mut())
+        };
+        if handle != INVALID_HANDLE_VALUE {
+            return Ok(unsafe { File::from_raw_handle(handle as RawHandle) });
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(ERROR_PIPE_BUSY as i32) {
+            return Err(err);
+        }
+
+        let timeout = match (wait, deadline) {
+            (PipeWait::Forever, _) => None,
+            (PipeWait::Timeout(_), Some(deadline)) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(io::Error::from_raw_os_error(ERROR_SEM_TIMEOUT as i32));
+                }
+                // `WaitNamedPipe` treats a zero timeout as "use the server's default wait".
+                Some(cmp::max(deadline - now, Duration::from_millis(1)))
+            },
+            _ => return Err(io::Error::from_raw_os_error(ERROR_PIPE_BUSY as i32)),
+        };
+        trace!("Pipe {:?} is busy, waiting {:?} for an instance", path, timeout);
+        let millis = match timeout {
+            Some(timeout) => cmp::min(timeout.as_millis(), u128::from(NMPWAIT_WAIT_FOREVER - 1)) as DWORD,
+            None => NMPWAIT_WAIT_FOREVER,
+        };
+        if unsafe { WaitNamedPipeW(name.as_ptr(), millis) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+}
+
+// Bounds of the delay between attempts while the pipe doesn't exist yet.
+const SERVER_POLL_INITIAL: Duration = Duration::from_millis(5);
+const SERVER_POLL_MAX: Duration = Duration::from_millis(250);
+
+/// Open the client end of the pipe at `path`, as `open_client` with `FILE_FLAG_OVERLAPPED`,
+/// polling with exponential backoff until a server creates it, for up to `timeout` overall.
+///
+/// `WaitNamedPipe` fails right away on pipes that don't exist, so it only serves for
+/// waiting on busy instances here. Fails with `TimedOut` if no server turns up in time.
+pub(crate) fn open_waiting_for_server(path: &Path, timeout: Duration) -> io::Result<File> {
+    let deadline = Instant::now() + timeout;
+    let mut delay = SERVER_POLL_INITIAL;
+    loop {
+        let now = Instant::now();
+        let remaining = if now < deadline { deadline - now } else { Duration::from_millis(0) };
+        match open_client(path, PipeDirection::Duplex.client_access(), FILE_FLAG_OVERLAPPED, PipeWait::Timeout(remaining)) {
+            Err(ref err) if err.raw_os_error() == Some(ERROR_FILE_NOT_FOUND as i32) => {},
+            result => return result,
+        }
+
+        if remaining == Duration::from_millis(0) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                format!("no server created {:?} within {:?}", path, timeout)));
+        }
+        trace!("Pipe {:?} doesn't exist yet, retrying in {:?}", path, delay);
+        thread::sleep(cmp::min(delay, remaining));
+        delay = cmp::min(delay * 2, SERVER_POLL_MAX);
+    }
+}
+
+/// Whether a server created the pipe at `path`, busy or not, without connecting to it.
+pub(crate) fn pipe_exists(path: &Path) -> io::Result<bool> {
+    let name = pipe_name::WideName::new(path.as_os_str().encode_wide());
+    // Returns right away when an instance is available or there is no pipe at all.
+    if unsafe { WaitNamedPipeW(name.as_ptr(), 1) } != 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error().map(|code| code as u32) {
+        Some(ERROR_SEM_TIMEOUT) => Ok(true),
+        Some(ERROR_FILE_NOT_FOUND) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// How many pipe instances a server keeps awaiting connections.
+///
+/// A client can only connect to an instance that exists and awaits a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InstanceStrategy {
+    /// A single instance awaits connections, and its replacement is created as soon as a
+    /// client connects to it. Clients arriving in between find the pipe busy and wait.
+    #[default]
+    LazyWithOverlap,
+    /// The given number of instances await connections, so that bursts of clients connect
+    /// without waiting, at the cost of idle instances. Limited by the maximum number of
+    /// instances of the endpoint.
+    Eager(u8),
+}
+
+// Buffer sizes of the pipes, in each direction.
+const DEFAULT_BUFFER_SIZE: u32 = 65536;
+
+/// Options shared by all pipe instances of an endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct PipeOptions {
+    pub pipe_mode: PipeMode,
+    pub read_mode: Option<PipeMode>,
+    pub max_instances: u8,
+    pub write_through: bool,
+    pub instances: InstanceStrategy,
+    pub default_timeout: Option<Duration>,
+    pub direction: PipeDirection,
+    pub in_buffer_size: u32,
+    pub out_buffer_size: u32,
+    pub instance_retry: Option<RetryConfig>,
+}
+
+impl Default for PipeOptions {
+    fn default() -> Self {
+        PipeOptions {
+            pipe_mode: PipeMode::default(),
+            read_mode: None,
+            max_instances: PIPE_UNLIMITED_INSTANCES as u8,
+            write_through: false,
+            instances: InstanceStrategy::default(),
+            default_timeout: None,
+            direction: PipeDirection::default(),
+            in_buffer_size: DEFAULT_BUFFER_SIZE,
+            out_buffer_size: DEFAULT_BUFFER_SIZE,
+            instance_retry: None,
+        }
+    }
+}
+
+impl PipeOptions {
+    /// Create a new pipe instance. `overlapped` pipes are required by the Tokio transport.
+    pub fn create(&self, path: &str, first: bool, overlapped: bool, security_attributes: &mut SecurityAttributes)
+        -> io::Result<File>
+    {
+        pipe_name::validate(path)?;
+        let read_mode = self.read_mode.unwrap_or(self.pipe_mode);
+        if self.pipe_mode == PipeMode::Byte && read_mode == PipeMode::Message {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "byte pipes can't be read in message mode"));
+        }
+
+        let (in_buffer_size, out_buffer_size) = self.buffer_sizes();
+        let name = pipe_name::WideName::new(path.encode_utf16());
+        let mut open_mode = self.direction.open_mode();
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        if overlapped {
+            open_mode |= FILE_FLAG_OVERLAPPED;
+        }
+        if self.write_through {
+            open_mode |= FILE_FLAG_WRITE_THROUGH;
+        }
+
+        // Always `PIPE_WAIT`: overlapped handles complete asynchronously through the
+        // completion port regardless. The deprecated `PIPE_NOWAIT` bypasses it instead,
+        // failing operations right away without ever signalling readiness, so awaiting a
+        // connection or data would spin.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                self.pipe_mode.type_flag() | read_mode.read_mode_flag() | PIPE_WAIT,
+                DWORD::from(self.max_instances),
+                out_buffer_size,
+                in_buffer_size,
+                default_timeout_millis(self.default_timeout),
+                security_attributes.as_ptr(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_handle(handle as _) })
+    }
+
+    /// Inbound and outbound buffer sizes, the one a one-way pipe never uses being zero.
+    fn buffer_sizes(&self) -> (u32, u32) {
+        match self.direction {
+            PipeDirection::Duplex => (self.in_buffer_size, self.out_buffer_size),
+            PipeDirection::Inbound => (self.in_buffer_size, 0),
+            PipeDirection::Outbound => (0, self.out_buffer_size),
+        }
+    }
+}
+
+/// `nDefaultTimeOut` of `CreateNamedPipeW` for `timeout`.
+///
+/// A zero `nDefaultTimeOut` is the `NMPWAIT_USE_DEFAULT_WAIT` sentinel, which gives the
+/// system default of 50 milliseconds rather than no timeout, so `None` maps to it.
+/// `NMPWAIT_WAIT_FOREVER` isn't valid here, longer timeouts are capped just below it.
+fn default_timeout_millis(timeout: Option<Duration>) -> DWORD {
+    match timeout {
+        None => NMPWAIT_USE_DEFAULT_WAIT,
+        Some(timeout) => cmp::min(timeout.as_millis(), u128::from(NMPWAIT_WAIT_FOREVER - 1)) as DWORD,
+    }
+}
+
+/// Properties of a pipe, as reported by `GetNamedPipeInfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeInfo {
+    /// Whether the handle is the server end of the pipe.
+    pub server_end: bool,
+    /// Type of the pipe.
+    pub pipe_mode: PipeMode,
+    /// Read mode of the handle, always `Byte` for byte pipes.
+    pub read_mode: PipeMode,
+    /// Size of the outbound buffer in bytes.
+    pub out_buffer_size: u32,
+    /// Size of the inbound buffer in bytes.
+    pub in_buffer_size: u32,
+    /// Maximum number of pipe instances, 255 meaning unlimited.
+    pub max_instances: u32,
+    /// Whether the handle is in the deprecated `PIPE_NOWAIT` mode, never the case for
+    /// pipes created or opened by this crate.
+    pub nowait: bool,
+}
+
+impl PipeInfo {
+    pub(crate) fn of(handle: RawHandle) -> io::Result<PipeInfo> {
+        let (mut flags, mut out_buffer_size, mut in_buffer_size, mut max_instances) = (0, 0, 0, 0);
+        if unsafe {
+            GetNamedPipeInfo(handle as _, &mut flags, &mut out_buffer_size, &mut in_buffer_size, &mut max_instances)
+        } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut state = 0;
+        if unsafe {
+            GetNamedPipeHandleStateW(handle as _, &mut state, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(),
+                ptr::null_mut(), 0)
+        } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PipeInfo {
+            server_end: flags & PIPE_SERVER_END != 0,
+            pipe_mode: if flags & PIPE_TYPE_MESSAGE != 0 { PipeMode::Message } else { PipeMode::Byte },
+            read_mode: if state & PIPE_READMODE_MESSAGE != 0 { PipeMode::Message } else { PipeMode::Byte },
+            out_buffer_size,
+            in_buffer_size,
+            max_instances,
+            nowait: state & PIPE_NOWAIT != 0,
+        })
+    }
+}
+
+/// Flags (`PIPE_SERVER_END`, `PIPE_TYPE_MESSAGE`) of a pipe handle, failing if it isn't a pipe.
+pub(crate) fn pipe_flags(handle: RawHandle) -> io::Result<DWORD> {
+    let mut flags = 0;
+    if unsafe { GetNamedPipeInfo(handle as _, &mut flags, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags)
+}
+
+/// Terminal services session of the client connected to the server end `handle`.
+pub(crate) fn client_session_id(handle: RawHandle) -> io::Result<u32> {
+    let mut session_id = 0;
+    if unsafe { GetNamedPipeClientSessionId(handle as _, &mut session_id) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(session_id)
+}
+
+/// Map the errors of writing to a pipe whose peer went away to `BrokenPipe`, like
+/// `EPIPE` on Unix, whichever of `ERROR_NO_DATA` or `ERROR_BROKEN_PIPE` is reported.
+pub(crate) fn map_write_error(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(code) if code == ERROR_NO_DATA as i32 || code == ERROR_BROKEN_PIPE as i32 => {
+            io::Error::new(io::ErrorKind::BrokenPipe, err)
+        },
+        _ => err,
+    }
+}
+
+#[allow(non_snake_case)]
+#[repr(C)]
+struct IO_STATUS_BLOCK {
+    Status: usize,
+    Information: usize,
+}
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQueryInformationFile(FileHandle: RawHandle, IoStatusBlock: *mut IO_STATUS_BLOCK,
+        FileInformation: *mut DWORD, Length: DWORD, FileInformationClass: DWORD) -> i32;
+}
+
+// `FILE_INFORMATION_CLASS::FileModeInformation` and its synchronous I/O flags.
+const FILE_MODE_INFORMATION: DWORD = 16;
+const FILE_SYNCHRONOUS_IO_ALERT: DWORD = 0x10;
+const FILE_SYNCHRONOUS_IO_NONALERT: DWORD = 0x20;
+
+/// Whether `handle` was opened for overlapped I/O, i.e. without `FILE_SYNCHRONOUS_IO_*`.
+pub(crate) fn is_overlapped(handle: RawHandle) -> io::Result<bool> {
+    let mut status = IO_STATUS_BLOCK { Status: 0, Information: 0 };
+    let mut mode: DWORD = 0;
+    let result = unsafe {
+        NtQueryInformationFile(handle, &mut status, &mut mode, 4, FILE_MODE_INFORMATION)
+    };
+    if result < 0 {
+        return Err(io::Error::other(format!("NtQueryInformationFile failed with status {:#x}", result)));
+    }
+    Ok(mode & (FILE_SYNCHRONOUS_IO_ALERT | FILE_SYNCHRONOUS_IO_NONALERT) == 0)
+}
+
+/// Set the collect data timeout of the client end of a remote pipe, leaving its mode and
+/// maximum collection count as they are.
+fn set_collect_data_timeout(handle: RawHandle, timeout: Duration) -> io::Result<()> {
+    let mut millis = cmp::min(timeout.as_millis(), u128::from(DWORD::MAX)) as DWORD;
+    if unsafe { SetNamedPipeHandleState(handle as _, ptr::null_mut(), ptr::null_mut(), &mut millis) } == 0 {
+        let err = io::Error::last_os_error();
+        return Err(io::Error::new(err.kind(), format!("failed to set the collect data timeout to {:?}: {}", timeout, err)));
+    }
+    Ok(())
+}
+
+/// Collect data timeout of the client end of a remote pipe.
+#[cfg(test)]
+pub(crate) fn collect_data_timeout(handle: RawHandle) -> io::Result<Duration> {
+    let mut millis = 0;
+    if unsafe {
+        GetNamedPipeHandleStateW(handle as _, ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), &mut millis,
+            ptr::null_mut(), 0)
+    } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Duration::from_millis(u64::from(millis)))
+}
+
+/// Switch a client handle to message read mode if the server created a message-type pipe.
+///
+/// Clients always open pipes in byte read mode, which would otherwise merge messages.
+pub(crate) fn match_read_mode(handle: RawHandle) -> io::Result<()> {
+    if pipe_flags(handle)? & PIPE_TYPE_MESSAGE != 0 {
+        let mut mode = PIPE_READMODE_MESSAGE | PIPE_WAIT;
+        if unsafe { SetNamedPipeHandleState(handle as _, &mut mode, ptr::null_mut(), ptr::null_mut()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{default_timeout_millis, NMPWAIT_USE_DEFAULT_WAIT, NMPWAIT_WAIT_FOREVER};
+
+    #[test]
+    fn test_default_timeout_mapping() {
+        // Zero isn't "no timeout" but the 50ms system default.
+        assert_eq!(NMPWAIT_USE_DEFAULT_WAIT, 0);
+        assert_eq!(default_timeout_millis(None), NMPWAIT_USE_DEFAULT_WAIT);
+        assert_eq!(default_timeout_millis(Some(Duration::from_millis(0))), NMPWAIT_USE_DEFAULT_WAIT);
+
+        assert_eq!(default_timeout_millis(Some(Duration::from_millis(250))), 250);
+        assert_eq!(default_timeout_millis(Some(Duration::from_secs(u64::MAX))), NMPWAIT_WAIT_FOREVER - 1);
+    }
+}
@@ -0,0 +1,153 @@
+use std::io;
+
+use futures::{Async, Future, Poll};
+
+use IpcConnection;
+
+/// Future writing a length-prefixed frame made of a header and a payload.
+///
+/// Created by `IpcConnection::write_frame_vectored`. The frame is prefixed with the
+/// combined length as 4 big-endian bytes, the default framing of
+/// `LengthDelimitedCodec`. Resolves to the connection and both buffers once the whole
+/// frame was written.
+pub struct WriteFrame<H, P> {
+    state: Option<(IpcConnection, H, P)>,
+    prefix: [u8; 4],
+    written: usize,
+}
+
+impl<H: AsRef<[u8]>, P: AsRef<[u8]>> WriteFrame<H, P> {
+    pub(crate) fn new(connection: IpcConnection, header: H, payload: P) -> Self {
+        let len = header.as_ref().len() + payload.as_ref().len();
+        WriteFrame {
+            state: Some((connection, header, payload)),
+            prefix: (len as u32).to_be_bytes(),
+            written: 0,
+        }
+    }
+}
+
+impl<H: AsRef<[u8]>, P: AsRef<[u8]>> Future for WriteFrame<H, P> {
+    type Item = (IpcConnection, H, P);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, io::Error> {
+        {
+            let (ref mut connection, ref header, ref payload) = *self.state.as_mut().expect("poll a WriteFrame after it's done");
+            let parts = [&self.prefix[..], header.as_ref(), payload.as_ref()];
+            let total = parts.iter().map(|part| part.len()).sum::<usize>();
+            if total - self.prefix.len() > u32::MAX as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame too long for a 4 byte length prefix"));
+            }
+            while self.written < total {
+                let remaining = remaining(&parts, self.written);
+                let n = match write_slices(connection, &remaining) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                    result => result?,
+                };
+                if n == 0 {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write frame"));
+                }
+                self.written += n;
+            }
+        }
+
+        let (connection, header, payload) = self.state.take().expect("checked above; qed");
+        Ok(Async::Ready((connection, header, payload)))
+    }
+}
+
+/// What is left of `parts` once `written` bytes of them went out.
+fn remaining<'a>(parts: &[&'a [u8]], mut written: usize) -> Vec<&'a [u8]> {
+    let mut remaining = Vec::with_capacity(parts.len());
+    for part in parts {
+        if written >= part.len() {
+            written -= part.len();
+        } else {
+            remaining.push(&part[written..]);
+            written = 0;
+        }
+    }
+    remaining
+}
+
+// `sendmsg` rather than `writev`, to pass `MSG_NOSIGNAL` like plain writes do.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_vectored(connection: &mut IpcConnection, parts: &[&[u8]]) -> io::Result<usize> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let mut iov: Vec<::libc::iovec> = parts.iter()
+        .map(|part| ::libc::iovec { iov_base: part.as_ptr() as *mut _, iov_len: part.len() })
+        .collect();
+    let mut msg: ::libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = iov.as_mut_ptr();
+    msg.msg_iovlen = iov.len() as _;
+    let written = unsafe { ::libc::sendmsg(connection.inner.as_raw_fd(), &msg, ::libc::MSG_NOSIGNAL) };
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(written as usize)
+}
+
+// Other systems set `SO_NOSIGPIPE` on the socket, or can't raise the signal at all.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn send_vectored(connection: &mut IpcConnection, parts: &[&[u8]]) -> io::Result<usize> {
+    use std::io::Write;
+    use std::mem::ManuallyDrop;
+    use std::os::unix::{io::{AsRawFd, FromRawFd}, net};
+
+    let slices: Vec<io::IoSlice> = parts.iter().map(|part| io::IoSlice::new(part)).collect();
+    // Borrow the descriptor as a std stream, whose `write_vectored` uses `writev`.
+    let mut stream = ManuallyDrop::new(unsafe { net::UnixStream::from_raw_fd(connection.inner.as_raw_fd()) });
+    stream.write_vectored(&slices)
+}
+
+/// Write as much of `parts` as possible with a single vectored write.
+#[cfg(unix)]
+fn write_slices(connection: &mut IpcConnection, parts: &[&[u8]]) -> io::Result<usize> {
+    use std::io::Write;
+
+    if let Async::NotReady = connection.poll_write_ready()? {
+        return Err(io::ErrorKind::WouldBlock.into());
+    }
+    match send_vectored(connection, parts) {
+        // Readiness can only be cleared by a plain write that would block, which
+        // may write the first part after all if the socket drained meanwhile.
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => connection.write(parts[0]),
+        result => result,
+    }
+}
+
+/// Write `parts` concatenated, pipes having no gather writes.
+#[cfg(windows)]
+fn write_slices(connection: &mut IpcConnection, parts: &[&[u8]]) -> io::Result<usize> {
+    use std::io::Write;
+
+    connection.write(&parts.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use tokio;
+    use tokio::codec::{FramedRead, LengthDelimitedCodec};
+
+    use tests::connected_pair;
+
+    #[test]
+    fn test_frame_is_reassembled() {
+        let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+        let (client, server) = connected_pair(&mut runtime);
+
+        let payload = vec![7u8; 256 * 1024];
+        let expected = [&b"header:"[..], &payload[..]].concat();
+        runtime.spawn(client.write_frame_vectored(b"header:", payload)
+            .map(|_| ())
+            .map_err(|err| panic!("failed to write frame: {:?}", err)));
+
+        let frames = FramedRead::new(server, LengthDelimitedCodec::new());
+        let (frame, _) = runtime.block_on(frames.into_future()).map_err(|(err, _)| err).expect("failed to read frame");
+        assert_eq!(&frame.expect("no frame received")[..], &expected[..]);
+    }
+}
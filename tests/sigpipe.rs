@@ -0,0 +1,49 @@
+//! Writes to a closed peer with the default `SIGPIPE` action, which kills the process.
+//!
+//! Rust programs ignore `SIGPIPE`, applications embedding the crate may not. Resetting
+//! the handler affects every thread of the process, so this runs in a test binary of
+//! its own, as its only test.
+#![cfg(target_os = "linux")]
+
+extern crate futures;
+extern crate libc;
+extern crate susy_tokio_ipc;
+extern crate tokio;
+
+use std::fs::File;
+use std::io::ErrorKind;
+
+use futures::Stream;
+use susy_tokio_ipc::{dummy_endpoint, Endpoint, IpcConnection};
+use tokio::io;
+
+#[allow(deprecated)]
+fn closed_peer(runtime: &mut tokio::runtime::Runtime) -> IpcConnection {
+    let handle = runtime.reactor().clone();
+    let path = dummy_endpoint();
+    let incoming = Endpoint::new(path.clone()).incoming(&handle).expect("failed to open up a new pipe/socket");
+    let client = IpcConnection::connect(&path, &handle).expect("failed to connect");
+    let (server, _incoming) = runtime.block_on(incoming.into_future()).map_err(|(err, _)| err).expect("failed to accept");
+    drop(server);
+    client
+}
+
+#[test]
+fn test_closed_peer_without_sigpipe() {
+    let mut runtime = tokio::runtime::Runtime::new().expect("Error creating tokio runtime");
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+    let client = closed_peer(&mut runtime);
+    match runtime.block_on(io::write_all(client, b"hello")) {
+        Ok(_) => panic!("writing to a closed peer should fail"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::BrokenPipe),
+    }
+
+    // `sendfile` bypasses the write path.
+    let file = File::open("/proc/self/exe").expect("failed to open the test binary");
+    let client = closed_peer(&mut runtime);
+    match runtime.block_on(client.send_file(file, 0, 4096)) {
+        Ok(_) => panic!("sending to a closed peer should fail"),
+        Err(err) => assert_eq!(err.kind(), ErrorKind::BrokenPipe),
+    }
+}